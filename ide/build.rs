@@ -0,0 +1,18 @@
+use std::path::Path;
+
+const FONT: &str = "assets/fonts/JetBrainsMono-Regular.ttf";
+
+fn main() {
+    println!("cargo:rerun-if-changed=syntax");
+    // The folder too: putting back a font keeps its old timestamp, but the folder's changes.
+    println!("cargo:rerun-if-changed=assets/fonts");
+    println!("cargo:rerun-if-changed={FONT}");
+
+    // The font is embedded when it's there; without it the editor uses the system monospace.
+    println!("cargo::rustc-check-cfg=cfg(bundled_font)");
+    if Path::new(FONT).is_file() {
+        println!("cargo::rustc-cfg=bundled_font");
+    } else {
+        println!("cargo::warning={FONT} not found; building without the bundled font (the system monospace font is used instead)");
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+// Alternate files: a file's conventional counterpart, like a C header and its source or a
+// Rust module and its integration test. A rule is a list of patterns where `*` stands for
+// the file name without the pattern's extension, and anything before it is the end of the
+// folder path. The first pattern pairs with each of the others: `foo.h` looks for `foo.c`,
+// `foo.cpp`, ..., and each of those looks back for `foo.h`.
+
+/// Built-in rules, tried after the user's `alternate_files` from settings.toml.
+pub const BUILTIN_RULES: &[&[&str]] = &[
+    &["*.h", "*.c", "*.cpp", "*.cc", "*.cxx", "*.m", "*.mm"],
+    &["*.hpp", "*.cpp", "*.cc", "*.cxx"],
+    &["*.hh", "*.cc"],
+    &["src/*.rs", "tests/*.rs"],
+];
+
+/// Where the counterpart of `path` could be, most likely first. User rules come before the
+/// built-in ones; empty when no rule matches the file.
+pub fn candidates(path: &Path, user_rules: &[Vec<String>]) -> Vec<PathBuf> {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let rules = user_rules
+        .iter()
+        .map(|r| r.iter().map(String::as_str).collect::<Vec<_>>())
+        .chain(BUILTIN_RULES.iter().map(|r| r.to_vec()));
+
+    let mut out: Vec<PathBuf> = Vec::new();
+    for rule in rules {
+        // The most specific pattern the file matches, so `foo.spec.ts` goes by `*.spec.ts`
+        // rather than `*.ts`.
+        let Some((i, base, stem)) = rule
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| split_match(&path, p).map(|(base, stem)| (i, base, stem, p.len())))
+            .max_by_key(|m| m.3)
+            .map(|(i, base, stem, _)| (i, base, stem))
+        else {
+            continue;
+        };
+        let others: Vec<&str> = if i == 0 { rule[1..].to_vec() } else { vec![rule[0]] };
+        for other in others {
+            if let Some((prefix, suffix)) = other.split_once('*') {
+                let candidate = PathBuf::from(format!("{base}{prefix}{stem}{suffix}"));
+                if !out.contains(&candidate) {
+                    out.push(candidate);
+                }
+            }
+        }
+    }
+    out
+}
+
+// If `path` fits `pattern`, the folder path before the pattern's prefix and the part `*`
+// matched (never empty and never across a folder).
+fn split_match<'a>(path: &'a str, pattern: &str) -> Option<(&'a str, &'a str)> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+    let rest = path.strip_suffix(suffix)?;
+    let stem_start = rest.rfind('/').map_or(0, |i| i + 1);
+    let stem = &rest[stem_start..];
+    let base = rest[..stem_start].strip_suffix(prefix)?;
+    // The prefix has to start a folder name: `src/` fits `/proj/src/` but not `/proj/mysrc/`.
+    if stem.is_empty() || !(prefix.is_empty() || base.is_empty() || base.ends_with('/')) {
+        return None;
+    }
+    Some((base, stem))
+}
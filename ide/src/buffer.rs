@@ -0,0 +1,1564 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::scan::{self, is_word_char};
+use crate::transform;
+
+// The text being edited, with its cursor, selection and undo history. Nothing in here
+// knows about Dioxus, so it can be driven from tests, macros or scripts as well as the UI.
+//
+// Lines are stored without their '\n'. Positions are (line, byte column) and every
+// method keeps the column on a char boundary, clamping whatever it's given.
+
+/// A position in the buffer. `col` is a byte offset into the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Rectangular (column) selection. Positions are (line, column) with the column counted
+/// in characters, so the rectangle stays straight over multi-byte text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockSelection {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+}
+
+impl BlockSelection {
+    pub fn lines(&self) -> std::ops::RangeInclusive<usize> {
+        self.anchor.0.min(self.head.0)..=self.anchor.0.max(self.head.0)
+    }
+
+    pub fn cols(&self) -> std::ops::Range<usize> {
+        self.anchor.1.min(self.head.1)..self.anchor.1.max(self.head.1)
+    }
+}
+
+/// Replace the text between two positions. An empty range inserts, empty text deletes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub start: Cursor,
+    pub end: Cursor,
+    pub text: String,
+}
+
+/// Buffer as it was before an undo group. Lines are shared with the live buffer until the
+/// next edit copies them, so taking one is cheap.
+#[derive(Clone, Debug)]
+struct UndoEntry {
+    lines: Arc<Vec<String>>,
+    crlf: bool,
+    cursor: Cursor,
+    anchor: Option<Cursor>,
+    // What the step between this entry and the next state toward the present did, for the
+    // history list. None while it's the open group, which can still grow.
+    label: Option<String>,
+}
+
+/// One undo group, for the history list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryStep {
+    pub label: String,
+    /// False once it's been undone (it can still be redone).
+    pub done: bool,
+}
+
+/// What the previous edit was, so runs of typing or deleting undo as one step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditKind {
+    None,
+    Typing,
+    Deleting,
+    Other,
+}
+
+// Oldest groups fall off past this many.
+const UNDO_LIMIT: usize = 500;
+
+// Recent edits remembered for first_changed_line(); asking about an older revision than
+// these reach back to just gets line 0.
+const EDIT_LOG_LIMIT: usize = 64;
+
+// Revisions come from one counter for every buffer, so no two versions of any text share one.
+static REVISIONS: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// Typing one of these openers over a selection wraps it, whatever the language auto-closes.
+const SURROUND: [(char, char); 6] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')];
+
+#[derive(Clone, Debug)]
+pub struct Buffer {
+    lines: Arc<Vec<String>>,
+    // Lines end in "\r\n" rather than "\n" when written out. Undoable, like the text.
+    crlf: bool,
+    // The text and line ending as last loaded or saved, to tell whether there's anything to save.
+    saved: (Arc<Vec<String>>, bool),
+    cursor: Cursor,
+    // Other end of the selection; the cursor is the end that moves. None = no selection.
+    anchor: Option<Cursor>,
+    block: Option<BlockSelection>,
+    undo: Vec<UndoEntry>,
+    redo: Vec<UndoEntry>,
+    last_edit: EditKind,
+    // Inside group(): edits don't open undo groups of their own.
+    grouped: bool,
+    revision: u64,
+    // (revision, first line it changed) for the latest edits, oldest first, and the revision
+    // the oldest of them started from.
+    edit_log: Vec<(u64, usize)>,
+    edit_log_base: u64,
+    // Selections expand_selection() grew from, innermost last, and the selection it left;
+    // the stack only applies while that selection is still the current one.
+    expand_stack: Vec<(Option<Cursor>, Cursor)>,
+    expanded: Option<(Option<Cursor>, Cursor)>,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::from_text("")
+    }
+}
+
+impl Buffer {
+    /// Load text, treating "\r\n" as a line break. The cursor starts at the top, and the text
+    /// is written with "\n" unless with_crlf() says otherwise.
+    pub fn from_text(text: &str) -> Self {
+        let lines = Arc::new(text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_string()).collect());
+        let revision = next_revision();
+        Self {
+            saved: (Arc::clone(&lines), false),
+            lines,
+            crlf: false,
+            cursor: Cursor::default(),
+            anchor: None,
+            block: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: EditKind::None,
+            grouped: false,
+            revision,
+            edit_log: Vec::new(),
+            edit_log_base: revision,
+            expand_stack: Vec::new(),
+            expanded: None,
+        }
+    }
+
+    /// The whole buffer joined with "\n".
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Never empty: an empty buffer is one empty line.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    /// Changes with every change to the text (including undo/redo), never on cursor moves.
+    /// Unique across buffers, so a reloaded or different buffer never matches an old one.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The first line that may differ from the text as it was at `revision`, or None if
+    /// nothing has changed since. Line 0 when `revision` is too old to tell, or not this
+    /// buffer's.
+    pub fn first_changed_line(&self, revision: u64) -> Option<usize> {
+        if revision == self.revision {
+            return None;
+        }
+        let from = if revision == self.edit_log_base {
+            0
+        } else {
+            match self.edit_log.iter().position(|&(r, _)| r == revision) {
+                Some(i) => i + 1,
+                None => return Some(0),
+            }
+        };
+        self.edit_log[from..].iter().map(|&(_, line)| line).min()
+    }
+
+    // A new revision for a change starting at `line`.
+    fn changed_from(&mut self, line: usize) {
+        self.revision = next_revision();
+        if self.edit_log.len() == EDIT_LOG_LIMIT {
+            self.edit_log_base = self.edit_log.remove(0).0;
+        }
+        self.edit_log.push((self.revision, line));
+    }
+
+    /// The line ending a freshly loaded buffer is written with, as it was on disk.
+    pub fn with_crlf(mut self, crlf: bool) -> Self {
+        self.crlf = crlf;
+        self.saved.1 = crlf;
+        self
+    }
+
+    /// True when lines are written with "\r\n", false for "\n".
+    pub fn crlf(&self) -> bool {
+        self.crlf
+    }
+
+    /// Switch the line ending the text is written with, as its own undo step.
+    pub fn set_crlf(&mut self, crlf: bool) {
+        if crlf == self.crlf {
+            return;
+        }
+        self.begin_edit(EditKind::Other);
+        if !self.grouped
+            && let Some(entry) = self.undo.last_mut()
+        {
+            entry.label = Some(format!("line endings to {}", if crlf { "CRLF" } else { "LF" }));
+        }
+        self.crlf = crlf;
+        // No line's text changes.
+        self.changed_from(self.lines.len());
+        self.last_edit = EditKind::None;
+    }
+
+    /// Remember the current text and line ending as what's on disk.
+    pub fn mark_saved(&mut self) {
+        self.saved = (Arc::clone(&self.lines), self.crlf);
+    }
+
+    /// The text as mark_saved() last recorded it (or as loaded).
+    pub fn saved_lines(&self) -> &[String] {
+        &self.saved.0
+    }
+
+    /// Whether the text or line ending differs from what mark_saved() last recorded. Undoing
+    /// back to that point counts as unmodified again.
+    pub fn is_modified(&self) -> bool {
+        !Arc::ptr_eq(&self.saved.0, &self.lines) || self.saved.1 != self.crlf
+    }
+
+    /// Pull a position back inside the buffer and onto a char boundary.
+    pub fn clamp(&self, pos: Cursor) -> Cursor {
+        let line = pos.line.min(self.lines.len() - 1);
+        Cursor { line, col: floor_char_boundary(&self.lines[line], pos.col) }
+    }
+
+    /// Position of a character column on a line (both clamped).
+    pub fn pos_at_char(&self, line: usize, char_col: usize) -> Cursor {
+        let line = line.min(self.lines.len() - 1);
+        Cursor { line, col: char_to_byte(&self.lines[line], char_col) }
+    }
+
+    /// Character column of a position, for laying out the caret.
+    pub fn char_col(&self, pos: Cursor) -> usize {
+        let pos = self.clamp(pos);
+        self.lines[pos.line][..pos.col].chars().count()
+    }
+
+    /// Column a position is drawn at, each tab before it reaching the next multiple of
+    /// `tab_width` the way the editor draws tabs.
+    pub fn visual_col(&self, pos: Cursor, tab_width: usize) -> usize {
+        let pos = self.clamp(pos);
+        transform::display_columns(&self.lines[pos.line][..pos.col], tab_width)
+    }
+
+    /// The position drawn nearest visual column `col` of a line (both clamped), counting
+    /// tabs as visual_col() does. Inside a tab that's whichever side of it is closer.
+    pub fn pos_at_visual_col(&self, line: usize, col: usize, tab_width: usize) -> Cursor {
+        let line = line.min(self.lines.len() - 1);
+        let text = &self.lines[line];
+        let width = tab_width.max(1);
+        let mut at = 0;
+        for (i, c) in text.char_indices() {
+            let next = if c == '\t' { at + width - at % width } else { at + 1 };
+            if col < next {
+                let col = if (col - at) * 2 < next - at { i } else { i + c.len_utf8() };
+                return Cursor { line, col };
+            }
+            at = next;
+        }
+        Cursor { line, col: text.len() }
+    }
+
+    /// Move the cursor, dropping any selection.
+    pub fn set_cursor(&mut self, pos: Cursor) {
+        self.cursor = self.clamp(pos);
+        self.anchor = None;
+        self.block = None;
+        self.last_edit = EditKind::None;
+    }
+
+    /* ===== Selection ===== */
+
+    pub fn anchor(&self) -> Option<Cursor> {
+        self.anchor
+    }
+
+    pub fn select(&mut self, anchor: Cursor, head: Cursor) {
+        self.anchor = Some(self.clamp(anchor));
+        self.cursor = self.clamp(head);
+        self.block = None;
+        self.last_edit = EditKind::None;
+    }
+
+    /// Move the cursor to `pos` keeping (or starting) a selection from where it was.
+    pub fn extend_selection(&mut self, pos: Cursor) {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        self.select(anchor, pos);
+    }
+
+    pub fn select_all(&mut self) {
+        let last = self.lines.len() - 1;
+        self.select(Cursor::default(), Cursor { line: last, col: self.lines[last].len() });
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+        self.block = None;
+    }
+
+    /// (start, end) of a non-empty selection, in document order.
+    pub fn selection(&self) -> Option<(Cursor, Cursor)> {
+        let a = self.clamp(self.anchor?);
+        let b = self.clamp(self.cursor);
+        match a.cmp(&b) {
+            std::cmp::Ordering::Less => Some((a, b)),
+            std::cmp::Ordering::Greater => Some((b, a)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        if let Some(b) = self.block {
+            return Some(self.block_text(b));
+        }
+        let (start, end) = self.selection()?;
+        Some(self.text_range(start, end))
+    }
+
+    pub fn block(&self) -> Option<BlockSelection> {
+        self.block
+    }
+
+    /// Start or extend a column selection; the cursor follows the moving corner.
+    pub fn set_block(&mut self, anchor: (usize, usize), head: (usize, usize)) {
+        let last = self.lines.len() - 1;
+        let anchor = (anchor.0.min(last), anchor.1);
+        let head = (head.0.min(last), head.1);
+        self.block = Some(BlockSelection { anchor, head });
+        self.anchor = None;
+        self.cursor = self.pos_at_char(head.0, head.1);
+    }
+
+    /// Grow the selection to the next larger unit around it: word, line, inside the enclosing
+    /// brackets, the brackets themselves, outer brackets, ... and finally the whole buffer.
+    /// Returns false when it already covers everything.
+    pub fn expand_selection(&mut self) -> bool {
+        if self.expanded != Some((self.anchor, self.cursor)) {
+            self.expand_stack.clear();
+        }
+        let cur = self.selection().unwrap_or((self.clamp(self.cursor), self.clamp(self.cursor)));
+        let contains = |r: (Cursor, Cursor)| r.0 <= cur.0 && cur.1 <= r.1 && r != cur;
+
+        let mut candidates = vec![self.word_range(cur.1), self.line_range(cur)];
+        let mut from = cur.0;
+        while let Some((open, close)) = self.enclosing_brackets(from) {
+            let inner = (self.position_after(open), close);
+            let outer = (open, self.position_after(close));
+            candidates.extend([inner, outer]);
+            if contains(outer) {
+                break;
+            }
+            from = open;
+        }
+        let last = self.lines.len() - 1;
+        candidates.push((Cursor::default(), Cursor { line: last, col: self.lines[last].len() }));
+
+        // The smallest unit that's strictly bigger than the selection.
+        let Some(next) = candidates
+            .into_iter()
+            .filter(|&r| contains(r))
+            .min_by_key(|r| self.range_len(*r))
+        else {
+            return false;
+        };
+        self.expand_stack.push((self.anchor, self.cursor));
+        self.select(next.0, next.1);
+        self.expanded = Some((self.anchor, self.cursor));
+        true
+    }
+
+    /// Undo the last expand_selection(). Returns false if there's nothing to go back to.
+    pub fn shrink_selection(&mut self) -> bool {
+        if self.expanded != Some((self.anchor, self.cursor)) {
+            self.expand_stack.clear();
+        }
+        let Some((anchor, cursor)) = self.expand_stack.pop() else {
+            return false;
+        };
+        self.anchor = anchor;
+        self.cursor = cursor;
+        self.block = None;
+        self.expanded = Some((anchor, cursor));
+        true
+    }
+
+    // Identifier characters around `pos` (empty if there are none).
+    fn word_range(&self, pos: Cursor) -> (Cursor, Cursor) {
+        let line = &self.lines[pos.line];
+        let start = line[..pos.col]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word_char(c))
+            .last()
+            .map_or(pos.col, |(i, _)| i);
+        let end = line[pos.col..].find(|c: char| !is_word_char(c)).map_or(line.len(), |i| pos.col + i);
+        (Cursor { line: pos.line, col: start }, Cursor { line: pos.line, col: end })
+    }
+
+    /// The identifier to rename: the selection if it's exactly one, else the one at the cursor.
+    pub fn word_to_rename(&self) -> Option<String> {
+        let (start, end) = self.selection().unwrap_or_else(|| self.word_range(self.clamp(self.cursor)));
+        let text = self.text_range(start, end);
+        (!text.is_empty() && text.chars().all(is_word_char)).then_some(text)
+    }
+
+    /// Where `word` occurs as a whole word, not as part of a longer identifier.
+    pub fn word_occurrences(&self, word: &str) -> Vec<Cursor> {
+        if word.is_empty() {
+            return Vec::new();
+        }
+        let whole = scan::WholeWord(word);
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| scan::ranges(line, &whole).into_iter().map(move |r| Cursor { line: i, col: r.start }))
+            .collect()
+    }
+
+    /// Replace every whole-word occurrence of `word` with `to`, as one undo step. The cursor
+    /// and selection stay with the text they were on. Returns how many were replaced.
+    pub fn rename_word(&mut self, word: &str, to: &str) -> usize {
+        let found = self.word_occurrences(word);
+        if found.is_empty() || word == to {
+            return 0;
+        }
+        let moved = |c: Cursor| {
+            let mut col = c.col;
+            for o in found.iter().filter(|o| o.line == c.line && o.col < c.col) {
+                // From inside the word, to the end of the new one.
+                col = col - (c.col - o.col).min(word.len()) + to.len();
+            }
+            Cursor { line: c.line, col }
+        };
+        let cursor = moved(self.clamp(self.cursor));
+        let anchor = self.anchor.map(|a| moved(self.clamp(a)));
+
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        self.changed_from(found[0].line);
+        let lines = Arc::make_mut(&mut self.lines);
+        // Back to front, so earlier columns stay valid.
+        for o in found.iter().rev() {
+            lines[o.line].replace_range(o.col..o.col + word.len(), to);
+        }
+        self.cursor = cursor;
+        self.anchor = anchor;
+        found.len()
+    }
+
+    // Bytes in a range, counting line breaks as one.
+    fn range_len(&self, r: (Cursor, Cursor)) -> usize {
+        if r.0.line == r.1.line {
+            return r.1.col - r.0.col;
+        }
+        let middle: usize = self.lines[r.0.line + 1..r.1.line].iter().map(|l| l.len() + 1).sum();
+        self.lines[r.0.line].len() - r.0.col + 1 + middle + r.1.col
+    }
+
+    // Whole lines spanned by a range, without the line break.
+    fn line_range(&self, r: (Cursor, Cursor)) -> (Cursor, Cursor) {
+        (Cursor { line: r.0.line, col: 0 }, Cursor { line: r.1.line, col: self.lines[r.1.line].len() })
+    }
+
+    // The nearest unclosed bracket before `pos` and its partner.
+    fn enclosing_brackets(&self, pos: Cursor) -> Option<(Cursor, Cursor)> {
+        let mut depth = 0usize;
+        for line in (0..=pos.line).rev() {
+            let text = &self.lines[line];
+            let to = if line == pos.line { pos.col } else { text.len() };
+            for (i, ch) in text[..to].char_indices().rev() {
+                if BRACKETS.iter().any(|&(_, close)| ch == close) {
+                    depth += 1;
+                } else if BRACKETS.iter().any(|&(open, _)| ch == open) {
+                    if depth == 0 {
+                        let open = Cursor { line, col: i };
+                        return self.matching_bracket(open).map(|close| (open, close));
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// Text between two positions, lines joined with "\n".
+    pub fn text_range(&self, start: Cursor, end: Cursor) -> String {
+        let (start, end) = (self.clamp(start.min(end)), self.clamp(start.max(end)));
+        if start.line == end.line {
+            return self.lines[start.line][start.col..end.col].to_string();
+        }
+        let mut parts = vec![&self.lines[start.line][start.col..]];
+        parts.extend(self.lines[start.line + 1..end.line].iter().map(String::as_str));
+        parts.push(&self.lines[end.line][..end.col]);
+        parts.join("\n")
+    }
+
+    // Short lines contribute what they have (possibly nothing).
+    fn block_text(&self, b: BlockSelection) -> String {
+        let cols = b.cols();
+        b.lines()
+            .filter_map(|l| self.lines.get(l))
+            .map(|t| &t[char_to_byte(t, cols.start)..char_to_byte(t, cols.end)])
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /* ===== Editing ===== */
+
+    /// Insert at the cursor, replacing the selection. Text may contain newlines.
+    /// With a column selection, the text goes into every selected line instead.
+    pub fn insert(&mut self, text: &str) {
+        let kind = if text.contains('\n') { EditKind::Other } else { EditKind::Typing };
+        self.begin_edit(kind);
+        if let Some(b) = self.block {
+            self.block_replace(b, text);
+            return;
+        }
+        self.delete_selection();
+        let at = self.cursor;
+        self.cursor = self.replace_range(at, at, text);
+    }
+
+    /// Tab: with nothing selected and the cursor in a line's leading whitespace, indent to
+    /// the next multiple of `width` columns (a tab for tab indentation, else just enough
+    /// spaces). A selection over several lines indents each of them (blank ones aside) one
+    /// stop instead, as one undo step. Anywhere else, insert a whole `unit`.
+    pub fn tab(&mut self, unit: &str, width: usize) {
+        if self.block.is_none()
+            && let Some((start, end)) = self.selection()
+            && end.line > start.line
+        {
+            let tabs = unit == "\t";
+            let (first, last) = self.lines_to_indent();
+            self.reindent_lines(first..=last, true, |indent| transform::indent_indent(indent, width, tabs));
+            return;
+        }
+        let at = self.clamp(self.cursor);
+        let before = &self.lines[at.line][..at.col];
+        let leading = before.chars().all(|c| c == ' ' || c == '\t');
+        if unit == "\t" || !leading || self.block.is_some() || self.selection().is_some() {
+            self.insert(unit);
+            return;
+        }
+        let width = width.max(1);
+        let cols = transform::indent_columns(before, width);
+        self.insert(&" ".repeat(width - cols % width));
+    }
+
+    /// The identifier just before the cursor, for snippet triggers. None with a selection or
+    /// when the cursor isn't right after one.
+    pub fn word_before_cursor(&self) -> Option<String> {
+        if self.block.is_some() || self.selection().is_some() {
+            return None;
+        }
+        let at = self.clamp(self.cursor);
+        let (start, _) = self.word_range(at);
+        (start.col < at.col).then(|| self.lines[at.line][start.col..at.col].to_string())
+    }
+
+    /// Replace the `len` bytes before the cursor with `text`, as one undo step, and put the
+    /// cursor at `cursor` (line, byte column) within the new text.
+    pub fn replace_before_cursor(&mut self, len: usize, text: &str, cursor: (usize, usize)) {
+        let at = self.clamp(self.cursor);
+        let start = Cursor { line: at.line, col: at.col.saturating_sub(len) };
+        self.block = None;
+        self.begin_edit(EditKind::Other);
+        self.replace_range(start, at, text);
+        let col = if cursor.0 == 0 { start.col + cursor.1 } else { cursor.1 };
+        self.cursor = Cursor { line: start.line + cursor.0, col };
+        self.anchor = None;
+    }
+
+    /// Shift+Tab: take the cursor's line, or every line the selection touches, back one
+    /// indent stop. Returns false when none of them were indented.
+    pub fn outdent(&mut self, width: usize) -> bool {
+        let (first, last) = self.lines_to_indent();
+        self.reindent_lines(first..=last, false, |indent| transform::outdent_indent(indent, width)) > 0
+    }
+
+    // The first and last lines Tab and Shift+Tab indent: the selection's, or the cursor's
+    // line without one.
+    fn lines_to_indent(&self) -> (usize, usize) {
+        match self.selection() {
+            // A selection ending at column 0 doesn't take in that line.
+            Some((start, end)) if end.line > start.line && end.col == 0 => (start.line, end.line - 1),
+            Some((start, end)) => (start.line, end.line),
+            None => {
+                let line = self.clamp(self.cursor).line;
+                (line, line)
+            }
+        }
+    }
+
+    /// Split the line at the cursor (replacing the selection).
+    pub fn newline(&mut self) {
+        self.block = None;
+        self.begin_edit(EditKind::Other);
+        self.delete_selection();
+        let at = self.cursor;
+        self.cursor = self.replace_range(at, at, "\n");
+    }
+
+    /// Backspace: remove the selection, or the character before the cursor (joining
+    /// lines at column 0).
+    pub fn delete_backward(&mut self) {
+        let at_start = self.clamp(self.cursor) == Cursor::default();
+        if at_start && self.block.is_none() && self.selection().is_none() {
+            return;
+        }
+        self.begin_edit(EditKind::Deleting);
+        if let Some(b) = self.block {
+            self.block_backspace(b);
+            return;
+        }
+        if self.delete_selection() {
+            return;
+        }
+        let end = self.cursor;
+        let start = self.position_before(end);
+        self.cursor = self.replace_range(start, end, "");
+    }
+
+    /// Type one character with auto-closing: an opening character from `pairs` also inserts
+    /// its closer (or wraps the selection, see `surround`), and typing a closer that's already next to the
+    /// cursor steps over it. Quote-like pairs (same open and close) only auto-close outside
+    /// words, so `it's` stays as typed.
+    pub fn type_char(&mut self, c: char, pairs: &[(char, char)]) {
+        let plain = self.block.is_none() && self.selection().is_none();
+        let at = self.clamp(self.cursor);
+        let line = &self.lines[at.line];
+        let next = line[at.col..].chars().next();
+        let prev = line[..at.col].chars().next_back();
+
+        if self.surround(c, pairs) {
+            return;
+        }
+        if plain && next == Some(c) && pairs.iter().any(|&(_, close)| close == c) {
+            self.move_right(false);
+            return;
+        }
+
+        if let Some(&(open, close)) = pairs.iter().find(|&&(open, _)| open == c) {
+            let quote = open == close;
+            let closes_here = next.is_none_or(|n| n.is_whitespace() || pairs.iter().any(|&(_, cl)| cl == n));
+            let in_word = prev.is_some_and(|p| p.is_alphanumeric() || p == '_');
+            if plain && closes_here && !(quote && in_word) {
+                self.insert(&format!("{open}{close}"));
+                self.move_left(false);
+                return;
+            }
+        }
+
+        self.insert(c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Overwrite mode: type over the character under the cursor. A selection is replaced as
+    /// usual, and at the end of a line this just inserts.
+    pub fn overtype_char(&mut self, c: char) {
+        if self.surround(c, &[]) {
+            return;
+        }
+        let at = self.clamp(self.cursor);
+        if self.block.is_some() || self.selection().is_some() || at.col == self.lines[at.line].len() {
+            self.insert(c.encode_utf8(&mut [0; 4]));
+            return;
+        }
+        self.begin_edit(EditKind::Typing);
+        self.anchor = None;
+        let next = self.position_after(at);
+        self.cursor = self.replace_range(at, next, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Wrap the selection in the pair `c` opens (from `pairs` or SURROUND) as one undo step,
+    /// keeping the inner text selected. Returns false without a plain selection or pair.
+    pub fn surround(&mut self, c: char, pairs: &[(char, char)]) -> bool {
+        let Some(&(open, close)) = pairs.iter().chain(&SURROUND).find(|&&(open, _)| open == c) else {
+            return false;
+        };
+        let Some((start, end)) = self.selection().filter(|_| self.block.is_none()) else {
+            return false;
+        };
+        let inner = self.text_range(start, end);
+        self.insert(&format!("{open}{inner}{close}"));
+        let after = self.position_before(self.cursor);
+        let first = Cursor { line: start.line, col: start.col + open.len_utf8() };
+        self.select(first, after);
+        true
+    }
+
+    /// Backspace between an empty auto-closed pair removes both halves. Returns false (and
+    /// does nothing) when the cursor isn't inside one.
+    pub fn delete_pair(&mut self, pairs: &[(char, char)]) -> bool {
+        if self.block.is_some() || self.selection().is_some() {
+            return false;
+        }
+        let at = self.clamp(self.cursor);
+        let line = &self.lines[at.line];
+        let (Some(prev), Some(next)) = (line[..at.col].chars().next_back(), line[at.col..].chars().next()) else {
+            return false;
+        };
+        if !pairs.contains(&(prev, next)) {
+            return false;
+        }
+        self.begin_edit(EditKind::Deleting);
+        let start = Cursor { line: at.line, col: at.col - prev.len_utf8() };
+        let end = Cursor { line: at.line, col: at.col + next.len_utf8() };
+        self.cursor = self.replace_range(start, end, "");
+        true
+    }
+
+    /// Replace the selection with `f` of its text as one undo step, keeping the result
+    /// selected. Without a selection it works on the word at the cursor. Returns false if
+    /// there was nothing to change.
+    pub fn transform_selection(&mut self, f: impl FnOnce(&str) -> String) -> bool {
+        let cursor = self.clamp(self.cursor);
+        let selected = self.selection();
+        let (start, end) = match selected {
+            Some(r) => r,
+            None => self.word_range(cursor),
+        };
+        let old = self.text_range(start, end);
+        let new = f(&old);
+        if start == end || new == old {
+            return false;
+        }
+        let backward = self.anchor.is_some_and(|a| a > self.cursor);
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        let new_end = self.replace_range(start, end, &new);
+        match selected {
+            Some(_) if backward => self.select(new_end, start),
+            Some(_) => self.select(start, new_end),
+            None => {
+                self.anchor = None;
+                self.cursor = self.clamp(cursor.min(new_end));
+            }
+        }
+        true
+    }
+
+    /// Replace the lines the selection touches (all lines without one) with `f` of them, as
+    /// one undo step, and select the resulting lines. A selection ending at column 0 leaves
+    /// that last line out. Returns false if nothing changed.
+    pub fn transform_lines(&mut self, f: impl FnOnce(Vec<String>) -> Vec<String>) -> bool {
+        let (first, last) = self.selected_lines();
+        let old = self.lines[first..=last].to_vec();
+        let new = f(old.clone());
+        if new == old {
+            return false;
+        }
+        let new_last = first + new.len().max(1) - 1;
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        let end = Cursor { line: last, col: self.lines[last].len() };
+        self.replace_range(Cursor { line: first, col: 0 }, end, &new.join("\n"));
+        let end = Cursor { line: new_last, col: self.lines[new_last].len() };
+        self.select(Cursor { line: first, col: 0 }, end);
+        true
+    }
+
+    /// Rewrite the leading spaces and tabs of every line with `f` of them, as one undo step.
+    /// The cursor and selection keep their place in the text after the indentation. Returns
+    /// how many lines changed.
+    pub fn reindent(&mut self, f: impl Fn(&str) -> String) -> usize {
+        self.reindent_lines(0..=self.lines.len().saturating_sub(1), false, f)
+    }
+
+    // reindent() for just these lines, leaving out the blank ones (nothing but whitespace)
+    // when `skip_blank`.
+    fn reindent_lines(
+        &mut self,
+        range: std::ops::RangeInclusive<usize>,
+        skip_blank: bool,
+        f: impl Fn(&str) -> String,
+    ) -> usize {
+        let changes: Vec<(usize, usize, String)> = self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(*range.start())
+            .take(range.end().saturating_sub(*range.start()) + 1)
+            .filter_map(|(i, line)| {
+                let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+                if skip_blank && len == line.len() {
+                    return None;
+                }
+                let indent = f(&line[..len]);
+                (indent != line[..len]).then_some((i, len, indent))
+            })
+            .collect();
+        if changes.is_empty() {
+            return 0;
+        }
+        let moved = |c: Cursor| match changes.binary_search_by_key(&c.line, |(i, _, _)| *i) {
+            Ok(k) => {
+                let (_, old, new) = &changes[k];
+                let col = if c.col < *old { c.col.min(new.len()) } else { c.col - old + new.len() };
+                Cursor { line: c.line, col }
+            }
+            Err(_) => c,
+        };
+        let cursor = moved(self.clamp(self.cursor));
+        let anchor = self.anchor.map(|a| moved(self.clamp(a)));
+
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        self.changed_from(changes[0].0);
+        let lines = Arc::make_mut(&mut self.lines);
+        for (i, len, indent) in &changes {
+            lines[*i].replace_range(..*len, indent);
+        }
+        self.cursor = cursor;
+        self.anchor = anchor;
+        changes.len()
+    }
+
+    /// Join the cursor's line with the next one, or every line a multi-line selection
+    /// touches, replacing each line break and the following indentation with one space.
+    /// The cursor lands at the last join. Returns false on the last line.
+    pub fn join_lines(&mut self) -> bool {
+        let (first, last) = match self.selection() {
+            Some((start, end)) if end.line > start.line => (start.line, end.line),
+            _ => {
+                let line = self.clamp(self.cursor).line;
+                (line, line + 1)
+            }
+        };
+        if last >= self.lines.len() {
+            return false;
+        }
+        let mut joined = self.lines[first].clone();
+        let mut junction = joined.len();
+        for line in &self.lines[first + 1..=last] {
+            let next = line.trim_start();
+            junction = joined.len();
+            if !next.is_empty() && !joined.is_empty() && !joined.ends_with(char::is_whitespace) {
+                joined.push(' ');
+            }
+            joined.push_str(next);
+        }
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        self.anchor = None;
+        let end = Cursor { line: last, col: self.lines[last].len() };
+        self.replace_range(Cursor { line: first, col: 0 }, end, &joined);
+        self.cursor = Cursor { line: first, col: junction };
+        true
+    }
+
+    /// Delete the cursor's line, or every line the selection touches, as one undo step. The
+    /// cursor lands at the start of the line that followed; deleting the last line moves it
+    /// to the start of the one above, and deleting every line leaves one empty line.
+    pub fn delete_lines(&mut self) {
+        let (first, last) = match self.selection() {
+            Some(_) => self.selected_lines(),
+            None => {
+                let line = self.clamp(self.cursor).line;
+                (line, line)
+            }
+        };
+        let (start, end, cursor) = if last + 1 < self.lines.len() {
+            let start = Cursor { line: first, col: 0 };
+            (start, Cursor { line: last + 1, col: 0 }, start)
+        } else if first > 0 {
+            let above = Cursor { line: first - 1, col: self.lines[first - 1].len() };
+            (above, Cursor { line: last, col: self.lines[last].len() }, Cursor { line: first - 1, col: 0 })
+        } else {
+            let start = Cursor { line: 0, col: 0 };
+            (start, Cursor { line: last, col: self.lines[last].len() }, start)
+        };
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        self.anchor = None;
+        self.replace_range(start, end, "");
+        self.cursor = cursor;
+    }
+
+    /// Insert a copy of the selection right after it and select the copy. Without a
+    /// selection the cursor's line is duplicated below, keeping the cursor's column.
+    /// Returns false for column selections.
+    pub fn duplicate_selection(&mut self) -> bool {
+        if self.block.is_some() {
+            return false;
+        }
+        self.begin_edit(EditKind::Other);
+        match self.selection() {
+            Some((start, end)) => {
+                let text = self.text_range(start, end);
+                let copy_end = self.replace_range(end, end, &text);
+                self.select(end, copy_end);
+            }
+            None => {
+                let at = self.clamp(self.cursor);
+                let text = format!("\n{}", self.lines[at.line]);
+                let eol = Cursor { line: at.line, col: self.lines[at.line].len() };
+                self.replace_range(eol, eol, &text);
+                self.cursor = Cursor { line: at.line + 1, col: at.col };
+            }
+        }
+        true
+    }
+
+    // First and last line touched by the selection, or the whole buffer without one.
+    fn selected_lines(&self) -> (usize, usize) {
+        match self.selection() {
+            Some((start, end)) if end.line > start.line && end.col == 0 => (start.line, end.line - 1),
+            Some((start, end)) => (start.line, end.line),
+            None => (0, self.lines.len() - 1),
+        }
+    }
+
+    /// Swap in new text (a reload from disk) as one undo step, keeping the cursor where it
+    /// was as far as the new text allows. Returns false if the text was already this.
+    pub fn replace_all(&mut self, text: &str) -> bool {
+        if self.text() == text {
+            return false;
+        }
+        let cursor = self.cursor;
+        self.begin_edit(EditKind::Other);
+        self.block = None;
+        self.anchor = None;
+        let last = self.lines.len() - 1;
+        let end = Cursor { line: last, col: self.lines[last].len() };
+        self.replace_range(Cursor::default(), end, text);
+        self.cursor = self.clamp(cursor);
+        true
+    }
+
+    /// Remove the text between two positions as its own undo step.
+    pub fn delete(&mut self, start: Cursor, end: Cursor) {
+        self.apply_edit(&Edit { start, end, text: String::new() });
+    }
+
+    /// Apply a single replacement as its own undo step. The cursor ends up after the
+    /// inserted text.
+    pub fn apply_edit(&mut self, edit: &Edit) {
+        if edit.start == edit.end && edit.text.is_empty() {
+            return;
+        }
+        self.begin_edit(EditKind::Other);
+        self.anchor = None;
+        self.block = None;
+        self.cursor = self.replace_range(edit.start, edit.end, &edit.text);
+    }
+
+    /// Replace start..end with text and return the position just after the new text.
+    /// No undo bookkeeping; callers open the group.
+    fn replace_range(&mut self, start: Cursor, end: Cursor, text: &str) -> Cursor {
+        let (start, end) = (self.clamp(start.min(end)), self.clamp(start.max(end)));
+        if start == end && text.is_empty() {
+            return start;
+        }
+        self.changed_from(start.line);
+        let lines = Arc::make_mut(&mut self.lines);
+
+        let tail = lines[end.line][end.col..].to_string();
+        lines[start.line].truncate(start.col);
+        lines.drain(start.line + 1..=end.line);
+
+        let mut new_lines = text.split('\n');
+        lines[start.line].push_str(new_lines.next().unwrap_or(""));
+        let mut line = start.line;
+        for l in new_lines {
+            line += 1;
+            lines.insert(line, l.to_string());
+        }
+        let col = lines[line].len();
+        lines[line].push_str(&tail);
+        Cursor { line, col }
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+        self.cursor = self.replace_range(start, end, "");
+        self.anchor = None;
+        true
+    }
+
+    // Replace the selected column on every line (empty text just deletes), leaving a
+    // zero-width block after it so the next keystroke carries on down the column.
+    fn block_replace(&mut self, b: BlockSelection, text: &str) {
+        let cols = b.cols();
+        self.changed_from(*b.lines().start());
+        let lines = Arc::make_mut(&mut self.lines);
+        for l in b.lines() {
+            let Some(t) = lines.get_mut(l) else { break };
+            let range = char_to_byte(t, cols.start)..char_to_byte(t, cols.end);
+            t.replace_range(range, text);
+        }
+
+        let col = cols.start + text.chars().count();
+        self.set_block((b.anchor.0, col), (b.head.0, col));
+    }
+
+    fn block_backspace(&mut self, b: BlockSelection) {
+        let cols = b.cols();
+        if !cols.is_empty() {
+            self.block_replace(b, "");
+        } else if cols.start > 0 {
+            let wider = BlockSelection {
+                anchor: (b.anchor.0, cols.start - 1),
+                head: (b.head.0, cols.start),
+            };
+            self.block_replace(wider, "");
+        }
+    }
+
+    /* ===== Movement ===== */
+    // `select` extends the selection (Shift held); otherwise it's cleared.
+
+    pub fn move_left(&mut self, select: bool) {
+        let to = self.position_before(self.cursor);
+        self.move_to(to, select);
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        let to = self.position_after(self.cursor);
+        self.move_to(to, select);
+    }
+
+    pub fn move_up(&mut self, select: bool) {
+        let c = self.clamp(self.cursor);
+        if c.line > 0 {
+            let to = self.pos_at_char(c.line - 1, self.char_col(c));
+            self.move_to(to, select);
+        }
+    }
+
+    pub fn move_down(&mut self, select: bool) {
+        let c = self.clamp(self.cursor);
+        if c.line + 1 < self.lines.len() {
+            let to = self.pos_at_char(c.line + 1, self.char_col(c));
+            self.move_to(to, select);
+        }
+    }
+
+    fn move_to(&mut self, to: Cursor, select: bool) {
+        if select {
+            self.extend_selection(to);
+        } else {
+            self.set_cursor(to);
+        }
+    }
+
+    fn position_after(&self, pos: Cursor) -> Cursor {
+        let c = self.clamp(pos);
+        match self.lines[c.line][c.col..].chars().next() {
+            Some(ch) => Cursor { line: c.line, col: c.col + ch.len_utf8() },
+            None if c.line + 1 < self.lines.len() => Cursor { line: c.line + 1, col: 0 },
+            None => c,
+        }
+    }
+
+    fn position_before(&self, pos: Cursor) -> Cursor {
+        let c = self.clamp(pos);
+        match self.lines[c.line][..c.col].chars().next_back() {
+            Some(ch) => Cursor { line: c.line, col: c.col - ch.len_utf8() },
+            None if c.line > 0 => Cursor { line: c.line - 1, col: self.lines[c.line - 1].len() },
+            None => c,
+        }
+    }
+
+    /* ===== Brackets ===== */
+    // Plain character scans: brackets inside strings and comments count too.
+
+    /// Where the bracket at `at` is closed (or opened), across lines and skipping nested
+    /// pairs. None if `at` isn't a bracket or it's unbalanced.
+    pub fn matching_bracket(&self, at: Cursor) -> Option<Cursor> {
+        let at = self.clamp(at);
+        let c = self.lines[at.line][at.col..].chars().next()?;
+        let &(open, close) = BRACKETS.iter().find(|&&(o, cl)| c == o || c == cl)?;
+        let mut depth = 0usize;
+        if c == open {
+            for line in at.line..self.lines.len() {
+                let from = if line == at.line { at.col } else { 0 };
+                for (i, ch) in self.lines[line][from..].char_indices() {
+                    if ch == open {
+                        depth += 1;
+                    } else if ch == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(Cursor { line, col: from + i });
+                        }
+                    }
+                }
+            }
+        } else {
+            for line in (0..=at.line).rev() {
+                let text = &self.lines[line];
+                let to = if line == at.line { at.col + c.len_utf8() } else { text.len() };
+                for (i, ch) in text[..to].char_indices().rev() {
+                    if ch == close {
+                        depth += 1;
+                    } else if ch == open {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(Cursor { line, col: i });
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Move to the partner of the bracket beside the cursor. With the bracket after the
+    /// cursor it lands before the partner, with it before the cursor it lands after, so
+    /// pressing again jumps back. Returns false (not moving) when there's no bracket or no match.
+    pub fn jump_to_matching_bracket(&mut self) -> bool {
+        let c = self.clamp(self.cursor);
+        let is_bracket = |ch: char| BRACKETS.iter().any(|&(o, cl)| ch == o || ch == cl);
+        let line = &self.lines[c.line];
+        let target = if line[c.col..].chars().next().is_some_and(is_bracket) {
+            self.matching_bracket(c)
+        } else if let Some(ch) = line[..c.col].chars().next_back().filter(|&ch| is_bracket(ch)) {
+            let before = Cursor { line: c.line, col: c.col - ch.len_utf8() };
+            self.matching_bracket(before).map(|m| {
+                let partner = self.lines[m.line][m.col..].chars().next().map_or(0, char::len_utf8);
+                Cursor { line: m.line, col: m.col + partner }
+            })
+        } else {
+            None
+        };
+        match target {
+            Some(to) => {
+                self.set_cursor(to);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /* ===== Undo ===== */
+
+    // Start an undo group for the edit about to happen. Consecutive typing (or deleting)
+    // joins the open group; anything replacing a selection always starts a new one.
+    fn begin_edit(&mut self, kind: EditKind) {
+        if self.grouped {
+            return;
+        }
+        let joins = kind == self.last_edit
+            && kind != EditKind::Other
+            && self.selection().is_none()
+            && self.block.is_none();
+        if !joins {
+            if self.undo.len() >= UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+            // The group before this one is finished, so it can be described now.
+            if let Some(open) = self.undo.last_mut()
+                && open.label.is_none()
+            {
+                open.label = Some(describe_change(&open.lines, &self.lines));
+            }
+            let entry = self.snapshot();
+            self.undo.push(entry);
+        }
+        self.redo.clear();
+        self.last_edit = kind;
+    }
+
+    /// Run several edits as one undo step (macro playback, multi-step commands).
+    /// Leaves no undo entry if nothing changed.
+    pub fn group<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        if self.grouped {
+            return f(self);
+        }
+        self.begin_edit(EditKind::Other);
+        let before = self.undo.last().map(|e| (e.lines.clone(), e.crlf));
+        self.grouped = true;
+        let out = f(self);
+        self.grouped = false;
+        self.last_edit = EditKind::None;
+        if before.is_some_and(|(lines, crlf)| Arc::ptr_eq(&lines, &self.lines) && crlf == self.crlf) {
+            self.undo.pop();
+        }
+        out
+    }
+
+    fn snapshot(&self) -> UndoEntry {
+        UndoEntry {
+            lines: self.lines.clone(),
+            crlf: self.crlf,
+            cursor: self.cursor,
+            anchor: self.anchor,
+            label: None,
+        }
+    }
+
+    fn restore(&mut self, e: UndoEntry) {
+        let same = self.lines.iter().zip(e.lines.iter()).take_while(|(a, b)| a == b).count();
+        self.changed_from(same);
+        self.lines = e.lines;
+        self.crlf = e.crlf;
+        self.cursor = e.cursor;
+        self.anchor = e.anchor;
+        self.block = None;
+        self.last_edit = EditKind::None;
+    }
+
+    /// Returns false when there's nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(mut entry) = self.undo.pop() else {
+            return false;
+        };
+        let label = entry.label.take().unwrap_or_else(|| describe_change(&entry.lines, &self.lines));
+        let current = UndoEntry { label: Some(label), ..self.snapshot() };
+        self.redo.push(current);
+        self.restore(entry);
+        true
+    }
+
+    /// Returns false when there's nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(mut entry) = self.redo.pop() else {
+            return false;
+        };
+        let current = UndoEntry { label: entry.label.take(), ..self.snapshot() };
+        self.undo.push(current);
+        self.restore(entry);
+        true
+    }
+
+    /// Every undo group still held, oldest first: the ones in effect, then the undone ones
+    /// in the order redo would bring them back.
+    pub fn history(&self) -> Vec<HistoryStep> {
+        let done = self.undo.iter().enumerate().map(|(i, e)| {
+            let next = self.undo.get(i + 1).map_or(&self.lines, |n| &n.lines);
+            let label = e.label.clone().unwrap_or_else(|| describe_change(&e.lines, next));
+            HistoryStep { label, done: true }
+        });
+        let undone = self.redo.iter().rev().map(|e| HistoryStep {
+            label: e.label.clone().unwrap_or_default(),
+            done: false,
+        });
+        done.chain(undone).collect()
+    }
+
+    /// How many of history()'s steps are in effect.
+    pub fn history_position(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Undo or redo until exactly `position` steps of the history are in effect (0 goes back
+    /// to the oldest state held). Returns false if nothing changed.
+    pub fn jump_to_history(&mut self, position: usize) -> bool {
+        let mut changed = false;
+        while self.undo.len() > position && self.undo() {
+            changed = true;
+        }
+        while self.undo.len() < position && self.redo() {
+            changed = true;
+        }
+        changed
+    }
+
+    /// Take another view's text and undo history, keeping this view's own cursor.
+    /// Used to keep two tabs of the same file in step.
+    pub fn share_text(&mut self, from: &Buffer) {
+        self.lines = from.lines.clone();
+        self.crlf = from.crlf;
+        self.saved = from.saved.clone();
+        self.undo = from.undo.clone();
+        self.redo = from.redo.clone();
+        self.revision = from.revision;
+        self.edit_log = from.edit_log.clone();
+        self.edit_log_base = from.edit_log_base;
+        self.last_edit = EditKind::None;
+        self.cursor = self.clamp(self.cursor);
+        self.anchor = self.anchor.map(|a| self.clamp(a));
+        self.block = None;
+    }
+}
+
+// A short description of the edit that turned `before` into `after`, like "inserted 'hello'"
+// or "deleted 3 lines", for the undo history.
+fn describe_change(before: &[String], after: &[String]) -> String {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &before[prefix..before.len() - suffix];
+    let new = &after[prefix..after.len() - suffix];
+    let lines = |n: usize| if n == 1 { "line".to_string() } else { format!("{n} lines") };
+    match (old.len(), new.len()) {
+        (0, 0) => return "no change".to_string(),
+        (0, n) => return format!("inserted {}", lines(n)),
+        (n, 0) => return format!("deleted {}", lines(n)),
+        (a, b) if a > 1 && b > 1 => return format!("changed {}", lines(a.max(b))),
+        _ => {}
+    }
+
+    // Within a line or two, say what text went in or out.
+    let (old, new) = (old.join("\n"), new.join("\n"));
+    let head = old.chars().zip(new.chars()).take_while(|(a, b)| a == b).map(|(c, _)| c.len_utf8()).sum::<usize>();
+    let tail = old[head..]
+        .chars()
+        .rev()
+        .zip(new[head..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum::<usize>();
+    let removed = &old[head..old.len() - tail];
+    let inserted = &new[head..new.len() - tail];
+    match (removed.is_empty(), inserted.is_empty()) {
+        (true, _) => format!("inserted {}", quote_snippet(inserted)),
+        (_, true) => format!("deleted {}", quote_snippet(removed)),
+        _ => format!("replaced {} with {}", quote_snippet(removed), quote_snippet(inserted)),
+    }
+}
+
+// Line breaks shown as ⏎ and long text cut short, so a label stays on one line.
+fn quote_snippet(text: &str) -> String {
+    const MAX_CHARS: usize = 24;
+    let mut s: String = text.chars().take(MAX_CHARS).map(|c| if c == '\n' { '⏎' } else { c }).collect();
+    if text.chars().count() > MAX_CHARS {
+        s.push('…');
+    }
+    format!("'{s}'")
+}
+
+/// Byte offset of a character column, clamped to the end of the line.
+pub fn char_to_byte(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map(|(i, _)| i).unwrap_or(line.len())
+}
+
+/// Largest char boundary at or before `col`.
+fn floor_char_boundary(line: &str, col: usize) -> usize {
+    let mut col = col.min(line.len());
+    while !line.is_char_boundary(col) {
+        col -= 1;
+    }
+    col
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, col: usize) -> Cursor {
+        Cursor { line, col }
+    }
+
+    // The line after pressing Tab (or Shift+Tab) with the cursor at the end of `line`.
+    fn tab_at_end(line: &str, unit: &str) -> String {
+        let mut b = Buffer::from_text(line);
+        b.set_cursor(at(0, line.len()));
+        b.tab(unit, 4);
+        b.text()
+    }
+
+    fn outdented(line: &str) -> String {
+        let mut b = Buffer::from_text(line);
+        b.outdent(4);
+        b.text()
+    }
+
+    #[test]
+    fn tab_in_leading_whitespace_reaches_the_next_stop() {
+        for (before, after) in [("", "    "), (" ", "    "), ("   ", "    "), ("    ", "        "), ("     ", "        ")] {
+            assert_eq!(tab_at_end(before, "    "), after, "{before:?}");
+        }
+        // A tab always reaches the next stop by itself.
+        for before in ["", " ", "   ", "    ", "     ", "\t"] {
+            assert_eq!(tab_at_end(before, "\t"), format!("{before}\t"), "{before:?}");
+        }
+        // Past the indentation, a whole unit.
+        assert_eq!(tab_at_end("  x", "    "), "  x    ");
+        // Tabs in the indentation count as reaching their stop.
+        assert_eq!(tab_at_end("\t ", "    "), "\t    ");
+    }
+
+    #[test]
+    fn outdent_goes_back_to_the_previous_stop() {
+        for (before, after) in [("x", "x"), (" x", "x"), ("   x", "x"), ("    x", "x"), ("     x", "    x")] {
+            assert_eq!(outdented(before), after, "{before:?}");
+        }
+        for (before, after) in [("\tx", "x"), ("\t\tx", "\tx"), ("\t  x", "\tx"), (" \tx", "x")] {
+            assert_eq!(outdented(before), after, "{before:?}");
+        }
+        let mut b = Buffer::from_text("x");
+        assert!(!b.outdent(4));
+    }
+
+    #[test]
+    fn tab_and_outdent_on_a_multi_line_selection() {
+        let mut b = Buffer::from_text("a\n  b\n\n\tc\nd");
+        // Ending at column 0 of the last line leaves that line out.
+        b.select(at(0, 0), at(4, 0));
+        b.tab("    ", 4);
+        assert_eq!(b.text(), "    a\n    b\n\n\t    c\nd");
+        // Back to the previous stop, which for "  b" indented to 4 is 0.
+        assert!(b.outdent(4));
+        assert_eq!(b.text(), "a\nb\n\n\tc\nd");
+        assert!(b.outdent(4));
+        assert_eq!(b.text(), "a\nb\n\nc\nd");
+        // One undo step each.
+        assert!(b.undo());
+        assert_eq!(b.text(), "a\nb\n\n\tc\nd");
+        assert!(b.undo());
+        assert_eq!(b.text(), "    a\n    b\n\n\t    c\nd");
+
+        let mut b = Buffer::from_text("a\n  b");
+        b.select(at(0, 1), at(1, 2));
+        b.tab("\t", 4);
+        assert_eq!(b.text(), "\ta\n  \tb");
+    }
+
+    #[test]
+    fn visual_columns_expand_tabs() {
+        let b = Buffer::from_text("\tab\tc\n  \tx");
+        let cols: Vec<usize> = (0..=5).map(|col| b.visual_col(at(0, col), 4)).collect();
+        assert_eq!(cols, [0, 4, 5, 6, 8, 9]);
+        assert_eq!(b.visual_col(at(0, 1), 8), 8);
+        // A tab after two columns only reaches the stop at 4.
+        assert_eq!(b.visual_col(at(1, 3), 4), 4);
+    }
+
+    #[test]
+    fn clicks_land_on_the_nearer_side_of_a_tab() {
+        let b = Buffer::from_text("\tab\tc");
+        let landed: Vec<usize> = (0..=10).map(|col| b.pos_at_visual_col(0, col, 4).col).collect();
+        // Columns 0 and 1 are before the first tab, 2 and 3 after it; "ab" fill 4 and 5, and
+        // the second tab spans 6..8.
+        assert_eq!(landed, [0, 0, 1, 1, 1, 2, 3, 4, 4, 5, 5]);
+        for col in 0..=5 {
+            let pos = at(0, col);
+            assert_eq!(b.pos_at_visual_col(0, b.visual_col(pos, 4), 4), pos);
+        }
+    }
+
+    #[test]
+    fn first_changed_line_since_a_revision() {
+        let mut b = Buffer::from_text("a\nb\nc\nd");
+        let start = b.revision();
+        assert_eq!(b.first_changed_line(start), None);
+        b.set_cursor(at(2, 1));
+        b.insert("x");
+        let middle = b.revision();
+        assert_eq!(b.first_changed_line(start), Some(2));
+        b.set_cursor(at(1, 0));
+        b.insert("y");
+        assert_eq!(b.first_changed_line(start), Some(1));
+        assert_eq!(b.first_changed_line(middle), Some(1));
+        assert!(b.undo());
+        assert_eq!(b.first_changed_line(middle), Some(1));
+        assert_eq!(b.text(), "a\nb\ncx\nd");
+        // Another buffer's revisions, or ones too old to remember, say to start over.
+        assert_eq!(b.first_changed_line(Buffer::from_text("a").revision()), Some(0));
+        for _ in 0..EDIT_LOG_LIMIT {
+            b.insert("z");
+        }
+        assert_eq!(b.first_changed_line(middle), Some(0));
+    }
+
+    #[test]
+    fn line_ending_toggle_is_undoable() {
+        let mut b = Buffer::from_text("a\nb").with_crlf(true);
+        assert!(!b.is_modified());
+        b.set_crlf(false);
+        assert!(!b.crlf());
+        assert!(b.is_modified());
+        assert!(b.undo());
+        assert!(b.crlf());
+        assert!(!b.is_modified());
+        assert!(b.redo());
+        assert!(!b.crlf());
+        assert_eq!(b.text(), "a\nb");
+    }
+
+    #[test]
+    fn typing_over_multiline_selection_is_one_undo_step() {
+        let mut b = Buffer::from_text("one\ntwo\nthree");
+        let (start, end) = (Cursor { line: 0, col: 1 }, Cursor { line: 2, col: 2 });
+        b.select(start, end);
+        b.insert("x");
+        assert_eq!(b.text(), "oxree");
+        assert!(b.undo());
+        assert_eq!(b.text(), "one\ntwo\nthree");
+        assert_eq!(b.cursor(), end);
+        assert!(!b.undo());
+    }
+
+    #[test]
+    fn backspace_over_multiline_selection_is_one_undo_step() {
+        let mut b = Buffer::from_text("one\ntwo\nthree");
+        let (start, end) = (Cursor { line: 0, col: 1 }, Cursor { line: 2, col: 2 });
+        b.select(start, end);
+        b.delete_backward();
+        assert_eq!(b.text(), "oree");
+        assert!(b.undo());
+        assert_eq!(b.text(), "one\ntwo\nthree");
+        assert_eq!(b.cursor(), end);
+        assert!(!b.undo());
+    }
+
+    #[test]
+    fn recovered_text_stays_modified_after_edit_and_undo() {
+        let mut b = Buffer::from_text("a\nb").with_crlf(true);
+        assert!(b.replace_all("a\nrecovered"));
+        b.insert("x");
+        assert!(b.undo());
+        assert_eq!(b.text(), "a\nrecovered");
+        assert!(b.is_modified());
+        assert!(b.crlf());
+    }
+
+    #[test]
+    fn toggling_back_leaves_buffer_unmodified() {
+        let mut b = Buffer::from_text("a");
+        b.set_crlf(true);
+        b.set_crlf(false);
+        assert!(!b.is_modified());
+    }
+
+    #[test]
+    fn undo_back_to_saved_text_is_unmodified() {
+        let mut b = Buffer::from_text("a");
+        b.insert("x");
+        b.mark_saved();
+        assert!(!b.is_modified());
+        assert!(b.undo());
+        assert!(b.is_modified());
+        assert!(b.redo());
+        assert!(!b.is_modified());
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+// Command line: `side [FILE[:LINE[:COL]]]...`
+// Lines and columns are 1-based like compiler and grep output.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenRequest {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+
+/// Files named on the command line, handed to the app through the root context.
+#[derive(Clone, Debug, Default)]
+pub struct StartupFiles(pub Vec<OpenRequest>);
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> StartupFiles {
+    StartupFiles(
+        args.filter(|a| !a.starts_with("--"))
+            .map(|a| parse_open_arg(&a))
+            .collect(),
+    )
+}
+
+/// Split `path:line:col`. An argument that exists as-is is always a plain path, and a
+/// lone drive letter is never taken as the path, so `C:\src\main.rs:42` works on Windows.
+pub fn parse_open_arg(arg: &str) -> OpenRequest {
+    let plain = |s: &str| OpenRequest {
+        path: absolute(s),
+        line: None,
+        col: None,
+    };
+
+    if PathBuf::from(arg).exists() {
+        return plain(arg);
+    }
+
+    let number = |s: &str| s.parse::<usize>().ok();
+    let Some((head, last)) = arg.rsplit_once(':') else {
+        return plain(arg);
+    };
+    let Some(last) = number(last) else {
+        return plain(arg);
+    };
+
+    let (path, line, col) = match head.rsplit_once(':').and_then(|(p, l)| Some((p, number(l)?))) {
+        Some((path, line)) => (path, Some(line), Some(last)),
+        None => (head, Some(last), None),
+    };
+
+    let is_drive_letter = path.len() == 1 && path.chars().all(|c| c.is_ascii_alphabetic());
+    if path.is_empty() || is_drive_letter {
+        return plain(arg);
+    }
+
+    OpenRequest {
+        path: absolute(path),
+        line,
+        col,
+    }
+}
+
+// std::path::absolute rather than canonicalize: no \\?\ prefixes on Windows, and it works
+// for files that don't exist yet.
+fn absolute(p: &str) -> PathBuf {
+    std::path::absolute(p).unwrap_or_else(|_| PathBuf::from(p))
+}
@@ -0,0 +1,180 @@
+use similar::{DiffTag, TextDiff};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Side-by-side line diff for the read-only compare view. Where lines were removed and others
+// added in their place, they're paired up row by row as changed lines, so an edited line sits
+// next to its old version.
+
+// Past this the diff stops looking for the smallest result; it's still correct, just less tidy.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowKind {
+    Same,
+    Removed,
+    Added,
+    Changed,
+}
+
+impl RowKind {
+    pub fn class(self) -> &'static str {
+        match self {
+            RowKind::Same => "same",
+            RowKind::Removed => "removed",
+            RowKind::Added => "added",
+            RowKind::Changed => "changed",
+        }
+    }
+}
+
+/// One row of the view: a line of each side (1-based number and text), or None on the side
+/// that doesn't have one.
+#[derive(Debug, Clone)]
+pub struct DiffRow {
+    pub kind: RowKind,
+    pub left: Option<(usize, String)>,
+    pub right: Option<(usize, String)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub rows: Vec<DiffRow>,
+    pub summary: Summary,
+}
+
+impl FileDiff {
+    pub fn new(left: PathBuf, left_text: &str, right: PathBuf, right_text: &str) -> Self {
+        let rows = side_by_side(left_text, right_text);
+        let summary = summarize(&rows);
+        Self { left, right, rows, summary }
+    }
+
+    pub fn title(&self) -> String {
+        let name = |p: &PathBuf| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        format!("{} ↔ {}", name(&self.left), name(&self.right))
+    }
+}
+
+pub fn side_by_side(old: &str, new: &str) -> Vec<DiffRow> {
+    let diff = TextDiff::configure().timeout(DIFF_TIMEOUT).diff_lines(old, new);
+    let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+    let line = |lines: &[&str], i: usize| (i + 1, lines[i].trim_end_matches(['\n', '\r']).to_string());
+
+    let mut rows = Vec::new();
+    for op in diff.ops() {
+        let (tag, old, new) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            rows.extend(old.zip(new).map(|(o, n)| DiffRow {
+                kind: RowKind::Same,
+                left: Some(line(old_lines, o)),
+                right: Some(line(new_lines, n)),
+            }));
+            continue;
+        }
+        for k in 0..old.len().max(new.len()) {
+            let left = (k < old.len()).then(|| line(old_lines, old.start + k));
+            let right = (k < new.len()).then(|| line(new_lines, new.start + k));
+            let kind = match (&left, &right) {
+                (Some(_), Some(_)) => RowKind::Changed,
+                (Some(_), None) => RowKind::Removed,
+                _ => RowKind::Added,
+            };
+            rows.push(DiffRow { kind, left, right });
+        }
+    }
+    rows
+}
+
+pub fn summarize(rows: &[DiffRow]) -> Summary {
+    let mut s = Summary::default();
+    for row in rows {
+        match row.kind {
+            RowKind::Same => {}
+            RowKind::Removed => s.removed += 1,
+            RowKind::Added => s.added += 1,
+            RowKind::Changed => s.changed += 1,
+        }
+    }
+    s
+}
+
+/// A compact unified listing of what changed from `old` to `new`: each change as its removed
+/// ('-') then added ('+') lines, with `context` unchanged (' ') lines around it and None where
+/// unchanged lines were left out.
+pub fn unified(old: &str, new: &str, context: usize) -> Vec<Option<(char, String)>> {
+    let rows = side_by_side(old, new);
+    let near_change = |i: usize| {
+        let end = (i + context + 1).min(rows.len());
+        rows[i.saturating_sub(context)..end].iter().any(|r| r.kind != RowKind::Same)
+    };
+
+    let mut out = Vec::new();
+    // Added halves of the current change, listed after its removed lines.
+    let mut added = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        if row.kind != RowKind::Same {
+            out.extend(row.left.as_ref().map(|(_, t)| Some(('-', t.clone()))));
+            added.extend(row.right.as_ref().map(|(_, t)| Some(('+', t.clone()))));
+            continue;
+        }
+        out.append(&mut added);
+        if near_change(i) {
+            out.push(row.left.as_ref().map(|(_, t)| (' ', t.clone())));
+        } else if !matches!(out.last(), Some(None)) {
+            out.push(None);
+        }
+    }
+    out.append(&mut added);
+    out
+}
+
+/// Which of the `new` lines differ from the `old` ones, as (0-based line, kind) in line order,
+/// for marking them beside the editor. Removed lines have no line of their own in `new`, so
+/// they mark the line that now follows them (one past the end if they were last).
+pub fn line_changes(old: &[String], new: &[String]) -> Vec<(usize, RowKind)> {
+    // Every line ends in "\n", so adding a line after the last doesn't make it look changed.
+    let text = |lines: &[String]| lines.iter().map(|l| format!("{l}\n")).collect::<String>();
+    let mut out: Vec<(usize, RowKind)> = Vec::new();
+    // The line after the last one of `new` seen so far.
+    let mut next = 0;
+    for row in side_by_side(&text(old), &text(new)) {
+        if let Some((n, _)) = row.right {
+            next = n;
+            if row.kind != RowKind::Same {
+                out.push((n - 1, row.kind));
+            }
+        } else if out.last() != Some(&(next, RowKind::Removed)) {
+            out.push((next, RowKind::Removed));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn line_changes_by_line_of_the_new_text() {
+        let changes = |old: &str, new: &str| line_changes(&lines(old), &lines(new));
+        assert_eq!(changes("a\nb\nc", "a\nb\nc"), []);
+        assert_eq!(changes("a\nb\nc", "a\nB\nc\nd"), [(1, RowKind::Changed), (3, RowKind::Added)]);
+        // Two removed lines mark the line after them once.
+        assert_eq!(changes("a\nb\nc\nd", "a\nd"), [(1, RowKind::Removed)]);
+        assert_eq!(changes("a\nb", "a"), [(1, RowKind::Removed)]);
+    }
+}
@@ -0,0 +1,97 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Just enough EditorConfig (https://editorconfig.org) to read per-file properties: walks up
+// from the file collecting .editorconfig files until one says `root = true`, and lets
+// sections in nearer files override farther ones. Keys and values come back lowercased.
+
+pub fn properties(path: &Path) -> HashMap<String, String> {
+    let mut configs = Vec::new();
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if let Ok(text) = fs::read_to_string(d.join(".editorconfig")) {
+            let root = is_root(&text);
+            configs.push((d.to_path_buf(), text));
+            if root {
+                break;
+            }
+        }
+        dir = d.parent();
+    }
+
+    let mut props = HashMap::new();
+    for (dir, text) in configs.iter().rev() {
+        let Ok(rel) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        let mut matches = false;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                matches = glob_regex(section).is_some_and(|re| re.is_match(&rel));
+                continue;
+            }
+            if matches && let Some((k, v)) = line.split_once('=') {
+                props.insert(k.trim().to_ascii_lowercase(), v.trim().to_ascii_lowercase());
+            }
+        }
+    }
+    props
+}
+
+// `root = true` only counts in the preamble, before the first section.
+fn is_root(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .take_while(|l| !l.starts_with('['))
+        .filter_map(|l| l.split_once('='))
+        .any(|(k, v)| k.trim().eq_ignore_ascii_case("root") && v.trim().eq_ignore_ascii_case("true"))
+}
+
+// Section globs: `*`, `**`, `?`, `{a,b}` and `[...]`. A glob without a slash matches the
+// file name in any folder below the .editorconfig.
+fn glob_regex(glob: &str) -> Option<Regex> {
+    let mut re = String::new();
+    let mut chars = glob.trim_start_matches('/').chars().peekable();
+    let mut braces = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '{' => {
+                braces += 1;
+                re.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                re.push(')');
+            }
+            ',' if braces > 0 => re.push('|'),
+            '[' => {
+                re.push('[');
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(if c == '!' && re.ends_with('[') { '^' } else { c });
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    let prefix = if glob.contains('/') { "^" } else { "^(?:.*/)?" };
+    Regex::new(&format!("{prefix}{re}$")).ok()
+}
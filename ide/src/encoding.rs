@@ -0,0 +1,235 @@
+// Line endings, text encodings and the final-newline policy tracked per tab.
+// Buffers are always held as plain '\n'-split UTF-8 lines; these only matter at load and save.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::Crlf,
+            LineEnding::Crlf => LineEnding::Lf,
+        }
+    }
+
+    /// The ending a buffer writes with; see Buffer::crlf.
+    pub fn of_crlf(crlf: bool) -> Self {
+        if crlf { LineEnding::Crlf } else { LineEnding::Lf }
+    }
+
+    pub fn is_crlf(self) -> bool {
+        self == LineEnding::Crlf
+    }
+
+    /// Whichever ending the majority of lines use. Files with no line breaks count as LF.
+    pub fn detect(text: &str) -> Self {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        if crlf > lf { LineEnding::Crlf } else { LineEnding::Lf }
+    }
+}
+
+/// What saving does about the line break at the very end of the file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FinalNewline {
+    /// Write whatever the buffer ends with.
+    #[default]
+    Keep,
+    /// Add one if the last line has text.
+    Ensure,
+    /// Drop trailing line breaks.
+    Strip,
+}
+
+impl FinalNewline {
+    pub fn label(self) -> &'static str {
+        match self {
+            FinalNewline::Keep => "EOF: keep",
+            FinalNewline::Ensure => "EOF: newline",
+            FinalNewline::Strip => "EOF: no newline",
+        }
+    }
+
+    pub fn cycled(self) -> Self {
+        match self {
+            FinalNewline::Keep => FinalNewline::Ensure,
+            FinalNewline::Ensure => FinalNewline::Strip,
+            FinalNewline::Strip => FinalNewline::Keep,
+        }
+    }
+
+    /// Manifest spelling: "keep", "ensure" or "strip".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Some(FinalNewline::Keep),
+            "ensure" => Some(FinalNewline::Ensure),
+            "strip" => Some(FinalNewline::Strip),
+            _ => None,
+        }
+    }
+
+    /// The lines to write. Buffers end with an empty line exactly when the text ends in a
+    /// line break, so this only ever adds or trims empty lines at the end.
+    pub fn apply(self, lines: &[String]) -> Vec<&str> {
+        let mut out: Vec<&str> = lines.iter().map(String::as_str).collect();
+        match self {
+            FinalNewline::Keep => {}
+            FinalNewline::Ensure => {
+                if out.last().is_some_and(|l| !l.is_empty()) {
+                    out.push("");
+                }
+            }
+            FinalNewline::Strip => {
+                while out.len() > 1 && out.last() == Some(&"") {
+                    out.pop();
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    // Single-byte legacy encodings. Never detected; picked by hand with Reopen with Encoding.
+    Latin1,
+    Windows1252,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 6] = [
+        TextEncoding::Utf8,
+        TextEncoding::Utf8Bom,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+        TextEncoding::Latin1,
+        TextEncoding::Windows1252,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf8Bom => "UTF-8 with BOM",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+            TextEncoding::Latin1 => "Latin-1",
+            TextEncoding::Windows1252 => "Windows-1252",
+        }
+    }
+
+    /// Read `bytes` as this encoding. A BOM is skipped for the encodings that write one.
+    /// None when they aren't valid in it; the single-byte encodings take any bytes.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            TextEncoding::Utf8Bom => {
+                let rest = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+                String::from_utf8(rest.to_vec()).ok()
+            }
+            TextEncoding::Utf16Le => decode_utf16(bytes.strip_prefix(&[0xFF, 0xFE]).unwrap_or(bytes), u16::from_le_bytes),
+            TextEncoding::Utf16Be => decode_utf16(bytes.strip_prefix(&[0xFE, 0xFF]).unwrap_or(bytes), u16::from_be_bytes),
+            TextEncoding::Latin1 => Some(bytes.iter().map(|&b| b as char).collect()),
+            TextEncoding::Windows1252 => Some(bytes.iter().map(|&b| windows_1252_char(b)).collect()),
+        }
+    }
+
+    /// Whether every character of `text` has a byte in this encoding. Saving checks this
+    /// first, since encode() writes '?' for the ones that don't.
+    pub fn can_encode(self, text: &str) -> bool {
+        match self {
+            TextEncoding::Latin1 => text.chars().all(|c| (c as u32) < 0x100),
+            TextEncoding::Windows1252 => text.chars().all(|c| windows_1252_byte(c).is_some()),
+            _ => true,
+        }
+    }
+
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Utf8Bom => {
+                let mut out = vec![0xEF, 0xBB, 0xBF];
+                out.extend_from_slice(text.as_bytes());
+                out
+            }
+            TextEncoding::Utf16Le => {
+                let mut out = vec![0xFF, 0xFE];
+                out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+                out
+            }
+            TextEncoding::Utf16Be => {
+                let mut out = vec![0xFE, 0xFF];
+                out.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+                out
+            }
+            TextEncoding::Latin1 => text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect(),
+            TextEncoding::Windows1252 => text.chars().map(|c| windows_1252_byte(c).unwrap_or(b'?')).collect(),
+        }
+    }
+}
+
+// Windows-1252 is Latin-1 except for 0x80..=0x9F, which hold these instead of C1 controls.
+// The five bytes it leaves undefined keep their Latin-1 meaning, as browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+fn windows_1252_char(b: u8) -> char {
+    match b {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+        _ => b as char,
+    }
+}
+
+fn windows_1252_byte(c: char) -> Option<u8> {
+    if let Some(i) = WINDOWS_1252_HIGH.iter().position(|&h| h == c) {
+        return Some(0x80 + i as u8);
+    }
+    u8::try_from(c).ok().filter(|b| !(0x80..=0x9F).contains(b))
+}
+
+/// Decode by byte-order mark. Returns None when there's no BOM so the caller can
+/// fall back to its own UTF-8 / binary sniffing.
+pub fn decode_with_bom(bytes: &[u8]) -> Option<(String, TextEncoding)> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return Some((String::from_utf8(rest.to_vec()).ok()?, TextEncoding::Utf8Bom));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return Some((decode_utf16(rest, u16::from_le_bytes)?, TextEncoding::Utf16Le));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return Some((decode_utf16(rest, u16::from_be_bytes)?, TextEncoding::Utf16Be));
+    }
+    None
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]])).collect();
+    String::from_utf16(&units).ok()
+}
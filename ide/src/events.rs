@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+
+// Lifecycle events the editor reports as it works, so an app embedding it can follow along
+// (a recent-files list, build-on-save, status in its own chrome, ...) without reaching into
+// the editor's state. Subscribe by providing an `EventSink` as Dioxus context.
+
+/// Something that happened in the editor. Tabs are named by their id, which stays the same
+/// for the tab's lifetime while its position in the tab bar can change.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditorEvent {
+    /// A file was read into a new tab.
+    FileOpened { tab: u64, path: PathBuf },
+    /// A tab's text was written to `path` (its own file, or a new one from Save As).
+    FileSaved { tab: u64, path: PathBuf },
+    /// A tab was closed. `path` is None for an untitled buffer.
+    TabClosed { tab: u64, path: Option<PathBuf> },
+    /// The cursor moved in the active tab. Both are 0-based; `col` counts characters.
+    CursorMoved { tab: u64, line: usize, col: usize },
+}
+
+/// Where the editor sends its events: a callback, or the sending end of a channel. Provide
+/// one with `LaunchBuilder::with_context` or `use_context_provider`; without one, events
+/// go nowhere.
+#[derive(Clone)]
+pub struct EventSink(Arc<dyn Fn(&EditorEvent) + Send + Sync>);
+
+impl EventSink {
+    /// Call `f` with every event, on the UI thread, so keep it quick.
+    pub fn new(f: impl Fn(&EditorEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// A sink that queues events for reading elsewhere. Events sent after the receiver is
+    /// dropped are discarded.
+    pub fn channel() -> (Self, mpsc::Receiver<EditorEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let sink = Self::new(move |e| {
+            let _ = tx.send(e.clone());
+        });
+        (sink, rx)
+    }
+
+    pub fn emit(&self, event: EditorEvent) {
+        (self.0)(&event);
+    }
+}
+
+impl std::fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventSink")
+    }
+}
@@ -0,0 +1,54 @@
+// Read-only hex view for binary files.
+// Rows are rendered lazily through the same visible_range virtualization as text,
+// so a 50 MB binary only ever formats the handful of rows on screen.
+
+pub const BYTES_PER_ROW: usize = 16;
+
+// How much of the file we sniff before deciding it's binary.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct HexRow {
+    pub offset: String,
+    pub hex: String,
+    pub ascii: String,
+}
+
+/// A file is treated as binary if it has a NUL byte near the start or isn't valid UTF-8.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    head.contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+pub fn row_count(len: usize) -> usize {
+    len.div_ceil(BYTES_PER_ROW).max(1)
+}
+
+pub fn format_row(bytes: &[u8], row: usize) -> HexRow {
+    let start = row * BYTES_PER_ROW;
+    let end = (start + BYTES_PER_ROW).min(bytes.len());
+    let chunk = if start < end { &bytes[start..end] } else { &[][..] };
+
+    let mut hex = String::with_capacity(BYTES_PER_ROW * 3 + 1);
+    for i in 0..BYTES_PER_ROW {
+        // Extra gap in the middle so the two 8-byte halves are easy to count.
+        if i == BYTES_PER_ROW / 2 {
+            hex.push(' ');
+        }
+        match chunk.get(i) {
+            Some(b) => hex.push_str(&format!("{b:02X} ")),
+            None => hex.push_str("   "),
+        }
+    }
+
+    let ascii = chunk
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect();
+
+    HexRow {
+        offset: format!("{start:08X}"),
+        hex,
+        ascii,
+    }
+}
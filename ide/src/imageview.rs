@@ -0,0 +1,94 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::path::Path;
+
+// Image preview tabs: the bytes are handed to the webview as a data URI,
+// same trick bundled_css uses for the font.
+
+/// MIME type for file extensions we preview as images, or None for everything else.
+pub fn image_mime(path: &Path) -> Option<&'static str> {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "bmp" => Some("image/bmp"),
+        "webp" => Some("image/webp"),
+        "ico" => Some("image/x-icon"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+pub fn data_uri(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{mime};base64,{}", STANDARD.encode(bytes))
+}
+
+/// Pixel size read straight from the file header. Only the common formats are understood;
+/// anything else just doesn't show dimensions.
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]) as u32);
+    let be32 = |i: usize| Some(u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?));
+    let le32 = |i: usize| Some(i32::from_le_bytes(bytes.get(i..i + 4)?.try_into().ok()?).unsigned_abs());
+
+    // PNG: width/height live in the IHDR chunk right after the signature.
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be32(16)?, be32(20)?));
+    }
+
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some((le16(6)?, le16(8)?));
+    }
+
+    // BMP height is negative for top-down bitmaps.
+    if bytes.starts_with(b"BM") {
+        return Some((le32(18)?, le32(22)?));
+    }
+
+    // JPEG: walk the segments until a start-of-frame marker.
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        let mut i = 2;
+        while i + 9 < bytes.len() {
+            if bytes[i] != 0xFF {
+                return None;
+            }
+            let marker = bytes[i + 1];
+            // Fill bytes between segments.
+            if marker == 0xFF {
+                i += 1;
+                continue;
+            }
+            let is_sof = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+            if is_sof {
+                return Some((be16(i + 7)?, be16(i + 5)?));
+            }
+            i += 2 + be16(i + 2)? as usize;
+        }
+        return None;
+    }
+
+    svg_dimensions(bytes)
+}
+
+// Only plain numeric width/height attributes on the root <svg>; percentages and units are skipped.
+fn svg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let start = text.find("<svg")?;
+    let end = start + text[start..].find('>')?;
+    let tag = &text[start..end];
+
+    let attr = |name: &str| -> Option<u32> {
+        let needle = format!(" {name}=\"");
+        let at = tag.find(&needle)? + needle.len();
+        let value = &tag[at..at + tag[at..].find('"')?];
+        let value = value.strip_suffix("px").unwrap_or(value);
+        value.parse::<f64>().ok().map(|v| v.round() as u32)
+    };
+
+    Some((attr("width")?, attr("height")?))
+}
@@ -0,0 +1,113 @@
+use crate::cli::{OpenRequest, StartupFiles};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// Single-instance handoff, like `code file` opening in an existing window.
+// The first window listens on a loopback port recorded in the config dir; later launches
+// send it their file arguments and exit. Off unless the single_instance setting is on.
+
+const HELLO: &str = "SIDE-OPEN 1";
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Requests forwarded by later launches, handed to the app through the root context.
+#[derive(Clone, Default)]
+pub struct Incoming(pub Arc<Mutex<Option<UnboundedReceiver<OpenRequest>>>>);
+
+impl Incoming {
+    pub fn take(&self) -> Option<UnboundedReceiver<OpenRequest>> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+fn port_file() -> Option<PathBuf> {
+    crate::settings::config_dir().map(|d| d.join("instance.port"))
+}
+
+/// Hand the files to a running window. Returns false if there isn't one (or it didn't answer),
+/// in which case this process should carry on and become the window itself.
+pub fn forward(files: &StartupFiles) -> bool {
+    let Some(port) = port_file()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .and_then(|s| s.trim().parse::<u16>().ok())
+    else {
+        return false;
+    };
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(CONNECT_TIMEOUT));
+
+    let mut msg = format!("{HELLO}\n");
+    for req in &files.0 {
+        msg.push_str(&encode(req));
+        msg.push('\n');
+    }
+    if stream.write_all(msg.as_bytes()).is_err() || stream.shutdown(std::net::Shutdown::Write).is_err() {
+        return false;
+    }
+
+    // A stale port file can point at some other program; only trust our own reply.
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).is_ok() && reply.trim() == "ok"
+}
+
+/// Become the instance later launches talk to. Errors just mean no handoff this session.
+pub fn listen() -> Result<Incoming, String> {
+    let path = port_file().ok_or("no config directory")?;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| format!("listen: {e}"))?;
+    let port = listener.local_addr().map_err(|e| format!("listen: {e}"))?.port();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("create {}: {e}", dir.display()))?;
+    }
+    std::fs::write(&path, port.to_string()).map_err(|e| format!("write {}: {e}", path.display()))?;
+
+    let (tx, rx) = unbounded_channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(&stream);
+            let mut hello = String::new();
+            if reader.read_line(&mut hello).is_err() || hello.trim_end() != HELLO {
+                continue;
+            }
+            let reqs: Vec<OpenRequest> = reader
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|l| decode(&l))
+                .collect();
+            let _ = (&stream).write_all(b"ok\n");
+
+            // An empty list still goes through as a bare "come to the front".
+            if reqs.is_empty() {
+                let _ = tx.send(OpenRequest { path: PathBuf::new(), line: None, col: None });
+            }
+            for req in reqs {
+                if tx.send(req).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(Incoming(Arc::new(Mutex::new(Some(rx)))))
+}
+
+// One request per line: path, line and column separated by tabs (empty when absent).
+fn encode(req: &OpenRequest) -> String {
+    let num = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_default();
+    format!("{}\t{}\t{}", req.path.display(), num(req.line), num(req.col))
+}
+
+fn decode(line: &str) -> Option<OpenRequest> {
+    let mut parts = line.split('\t');
+    let path = parts.next().filter(|p| !p.is_empty())?;
+    let line = parts.next().and_then(|n| n.parse().ok());
+    let col = parts.next().and_then(|n| n.parse().ok());
+    Some(OpenRequest { path: PathBuf::from(path), line, col })
+}
@@ -0,0 +1,7 @@
+// Editor core shared by the SIDE binary and anything that wants to drive it headless.
+pub mod buffer;
+pub mod events;
+pub mod scan;
+pub mod stats;
+pub mod transform;
+pub mod vim;
@@ -1,37 +1,73 @@
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use dioxus::prelude::*;
 use rfd::AsyncFileDialog;
-use std::{path::PathBuf, sync::Arc};
+use std::{path::{Path, PathBuf}, sync::Arc};
 use semver::Version;
-use std::time::Duration;
-
+use std::time::{Duration, SystemTime};
+
+use encoding::{FinalNewline, LineEnding, TextEncoding};
+use settings::{Settings, SidebarSort, FONT_FAMILIES, FONT_SIZES, INDENT_SIZES, SCROLL_SPEEDS};
+use side::buffer::{Buffer, Cursor};
+use side::events::{EditorEvent, EventSink};
+use side::stats::TextStats;
+use side::transform;
+use side::vim::{Vim, VimAction, VimKey};
+
+mod alternate;
+mod cli;
+mod diff;
+mod editorconfig;
+mod encoding;
+mod hexview;
+mod imageview;
+mod instance;
+mod markdown;
+mod recovery;
+mod session;
+mod settings;
+mod snippets;
 mod syntax;
+mod templates;
+#[cfg(feature = "tree-sitter")]
+mod treesitter;
 
-#[derive(Clone, Copy, Debug, Default)]
-struct Cursor {
-    line: usize,
-    col: usize,
-}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 struct EditorState {
-    lines: Arc<Vec<String>>,
-    cursor: Cursor,
+    buffer: Buffer,
     scroll_x: f64,
     scroll_y: f64,
+    // Set by a click past the end of a line when the virtual_space setting is on.
+    virtual_space: Option<VirtualSpace>,
 }
 
-impl Default for EditorState {
-    fn default() -> Self {
-        Self {
-            lines: Arc::new(vec![String::new()]),
-            cursor: Cursor::default(),
-            scroll_x: 0.0,
-            scroll_y: 0.0,
-        }
+/// The caret sitting `cols` columns past the end of its line. Only good while the buffer
+/// hasn't changed and the cursor is still at `at`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct VirtualSpace {
+    at: Cursor,
+    revision: u64,
+    cols: usize,
+}
+
+impl EditorState {
+    /// Columns the caret is drawn past the end of its line; 0 outside virtual space.
+    fn virtual_cols(&self) -> usize {
+        let b = &self.buffer;
+        self.virtual_space
+            .filter(|v| v.at == b.cursor() && v.revision == b.revision() && b.selection().is_none())
+            .map_or(0, |v| v.cols)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TabView {
+    Text,
+    Hex,
+    Image,
+    // Read-only side-by-side comparison of two files.
+    Diff,
+}
+
 #[derive(Clone, Debug)]
 struct Tab {
     id: u64,
@@ -39,8 +75,89 @@ struct Tab {
     language: String,
     dirty: bool,
     editor: EditorState,
+    view: TabView,
+    // Raw file bytes for the hex/image views (binary files, or a text tab toggled to hex).
+    bytes: Option<Arc<Vec<u8>>>,
+    // data: URI for image tabs, built once at open so renders don't re-encode.
+    image_uri: Option<Arc<String>>,
+    encoding: TextEncoding,
+    final_newline: FinalNewline,
+    // The encoding or final-newline policy changed since the last load or save. The buffer
+    // keeps track of its own text and line ending; see refresh_dirty.
+    format_changed: bool,
+    // The file as last read or written, None for untitled tabs.
+    disk_stamp: Option<DiskStamp>,
+    // What a Diff tab shows.
+    diff: Option<Arc<diff::FileDiff>>,
+    // Opened with File > Open as Read-Only (or switched with Make Read-Only): edits are
+    // refused until it's made editable again.
+    read_only: bool,
+    // What Tab inserts and what Convert Indentation uses, decided when the tab opens.
+    indent: Indent,
+}
+
+/// A tab's indentation: tabs or spaces, and columns per level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Indent {
+    tabs: bool,
+    width: usize,
+    // Which rule decided each half; see resolve_indent.
+    tabs_from: IndentSource,
+    width_from: IndentSource,
+}
+
+impl Indent {
+    /// The text one indent level inserts.
+    fn unit(&self) -> String {
+        if self.tabs { "\t".to_string() } else { " ".repeat(self.width) }
+    }
+
+    fn label(&self) -> String {
+        if self.tabs { format!("Tabs: {}", self.width) } else { format!("Spaces: {}", self.width) }
+    }
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Self { tabs: false, width: INDENT_WIDTH, tabs_from: IndentSource::Settings, width_from: IndentSource::Settings }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IndentSource {
+    EditorConfig,
+    File,
+    Language,
+    Settings,
+}
+
+impl IndentSource {
+    fn label(self) -> &'static str {
+        match self {
+            IndentSource::EditorConfig => ".editorconfig",
+            IndentSource::File => "the file",
+            IndentSource::Language => "the language",
+            IndentSource::Settings => "the default",
+        }
+    }
+}
+
+/// Size and modification time of a file, to notice other programs changing it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DiskStamp {
+    len: u64,
+    modified: Option<SystemTime>,
 }
 
+impl DiskStamp {
+    fn read(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
 
 impl Tab {
     fn new_untitled(id: u64) -> Self {
@@ -50,10 +167,33 @@ impl Tab {
             language: "plain".to_string(),
             dirty: false,
             editor: EditorState::default(),
+            view: TabView::Text,
+            bytes: None,
+            image_uri: None,
+            encoding: TextEncoding::default(),
+            final_newline: FinalNewline::default(),
+            format_changed: false,
+            disk_stamp: None,
+            diff: None,
+            read_only: false,
+            indent: *DEFAULT_INDENT.peek(),
         }
     }
 
+    fn line_ending(&self) -> LineEnding {
+        LineEnding::of_crlf(self.editor.buffer.crlf())
+    }
+
+    /// Work out the dirty flag again from what's changed since the last load or save, for
+    /// edits that can take the tab back to that state (undo, redo, toggling line endings).
+    fn refresh_dirty(&mut self) {
+        self.dirty = self.format_changed || self.editor.buffer.is_modified();
+    }
+
     fn title(&self) -> String {
+        if let Some(d) = &self.diff {
+            return d.title();
+        }
         let name = self
             .path
             .as_ref()
@@ -63,17 +203,84 @@ impl Tab {
         let star = if self.dirty { "*" } else { "" };
         format!("{name}{star}")
     }
+
+    /// How the tab's text is read and saved, on one line: language, encoding, line ending,
+    /// indentation and whether it's read-only. For the tab's tooltip and the status bar.
+    fn format_summary(&self) -> String {
+        let view = match self.view {
+            TabView::Text => None,
+            TabView::Hex => Some("Hex view"),
+            TabView::Image => Some("Image"),
+            TabView::Diff => Some("Comparison"),
+        };
+        if let Some(view) = view {
+            return view.to_string();
+        }
+        let mut parts = vec![
+            self.language.clone(),
+            self.encoding.label().to_string(),
+            self.line_ending().label().to_string(),
+            self.indent.label(),
+        ];
+        if self.read_only {
+            parts.push("Read-only".to_string());
+        }
+        parts.join(" · ")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct DirEntry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+    // None when metadata couldn't be read; the sidebar just leaves it blank.
+    size: Option<u64>,
+    modified: Option<std::time::SystemTime>,
+}
+
+/// A top-level folder in the sidebar. Several can be open at once.
+#[derive(Clone, Debug, PartialEq)]
+struct WorkspaceRoot {
+    path: PathBuf,
+    contents: Vec<DirEntry>,
+    collapsed: bool,
+}
+
+/// Something layered over the editor that takes keyboard input while it's open.
+/// The editor keydown handler ignores everything unless this is None.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Overlay {
+    None,
+    Confirm,
+    // Startup offer to bring back buffers a crash left in the recovery folder.
+    Recover,
+    // Saving this tab would overwrite changes another program made to its file.
+    Overwrite(usize),
+    // This dirty tab's file changed on disk; reload it or keep the edits?
+    Reload(usize),
+    // The active tab's undo groups, to jump back (or forward) to any of them.
+    History,
+    // The active file has no counterpart yet; create the one in `alternate_offer`?
+    CreateAlternate,
+    // Rename the identifier in `rename` across the active file.
+    Rename,
+    // Pick an encoding to read the active file's bytes again with.
+    ReopenEncoding,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 enum PendingAction {
     None,
     CloseTab(usize),
+    // Tabs (by id) from a batch close that still have unsaved changes.
+    CloseTabs(Vec<u64>),
     ExitApp,
 }
 
 /* ===== METRICS ===== */
-const FONT_PX: f64 = 14.0;
+// Font sizes outside this range (from a hand-edited settings file) are clamped.
+const FONT_PX_RANGE: (f64, f64) = (8.0, 48.0);
 const LINE_HEIGHT_EM: f64 = 1.4;
 const PAD_X_PX: f64 = 10.0;
 const PAD_Y_PX: f64 = 8.0;
@@ -81,50 +288,353 @@ const CHAR_WIDTH_RATIO: f64 = 0.60;
 
 // Keep the editor keyboard-ready even if nothing is focused.
 // Dioxus Desktop key events only go to the focused element.
-const FOCUS_SCRIPT: &str = "(function(){\n  const focusEditor = () => {\n    const el = document.getElementById('scrollpane');\n    if(!el) return;\n    const a = document.activeElement;\n    const tag = a && a.tagName ? a.tagName.toLowerCase() : '';\n    if(tag === 'input' || tag === 'textarea' || (a && a.isContentEditable)) return;\n    try { el.focus({preventScroll:true}); } catch(_) { el.focus(); }\n  };\n  window.addEventListener('focus', () => setTimeout(focusEditor, 0));\n  document.addEventListener('mousedown', () => setTimeout(focusEditor, 0), true);\n  setTimeout(focusEditor, 0);\n})();";
+const FOCUS_SCRIPT: &str = "(function(){\n  const focusEditor = () => {\n    const el = document.getElementById('scrollpane');\n    if(!el) return;\n    const a = document.activeElement;\n    const tag = a && a.tagName ? a.tagName.toLowerCase() : '';\n    if(tag === 'input' || tag === 'textarea' || (a && a.isContentEditable)) return;\n    if(document.querySelector('.modal-backdrop')) return;\n    try { el.focus({preventScroll:true}); } catch(_) { el.focus(); }\n  };\n  window.addEventListener('focus', () => setTimeout(focusEditor, 0));\n  document.addEventListener('mousedown', () => setTimeout(focusEditor, 0), true);\n  setTimeout(focusEditor, 0);\n})();";
+
+// Keep the Markdown preview roughly lined up with the source by matching scroll ratios.
+const MD_SYNC_SCRIPT: &str = "(function(){\n  const s = document.getElementById('scrollpane');\n  const p = document.getElementById('md-preview');\n  if(!s || !p) return;\n  const r = s.scrollTop / Math.max(1, s.scrollHeight - s.clientHeight);\n  p.scrollTop = r * (p.scrollHeight - p.clientHeight);\n})();";
+
+// Scroll #scrollpane just enough to bring the caret into view, keeping `margin` px of room
+// on the left/right so the text around it stays readable. Waits a frame for the caret to move.
+// Measures against where a smooth scroll is heading, not where it is mid-animation.
+fn reveal_caret_script(margin: f64) -> String {
+    format!(
+        "await new Promise(r => requestAnimationFrame(r));\nconst el = document.getElementById('scrollpane');\nconst c = el && el.querySelector('.caret');\nif(!c) return;\nconst S = window.sideScroll;\nconst moving = S && S.y !== null;\nconst tx = moving ? S.x : el.scrollLeft, ty = moving ? S.y : el.scrollTop;\nconst r = el.getBoundingClientRect();\nconst b = c.getBoundingClientRect();\nconst x = b.left - r.left + el.scrollLeft - tx, y = b.top - r.top + el.scrollTop - ty;\nlet nx = tx, ny = ty;\nif(x < {margin}) nx = Math.max(0, tx + x - {margin});\nelse if(x + b.width > el.clientWidth - {margin}) nx = tx + x + b.width - el.clientWidth + {margin};\nif(y < 0) ny = ty + y;\nelse if(y + b.height > el.clientHeight) ny = ty + y + b.height - el.clientHeight;\nif(nx === tx && ny === ty) return;\nif(moving) S.to(el, nx, ny); else {{ el.scrollLeft = nx; el.scrollTop = ny; }}"
+    )
+}
+
+// Scroll #scrollpane to a spot (go to line), animated when smooth scrolling is on.
+fn scroll_to_script(left: f64, top: f64) -> String {
+    format!(
+        "const el = document.getElementById('scrollpane');\nif(!el) return;\nif(window.sideScroll) window.sideScroll.to(el, {left}, {top}); else {{ el.scrollLeft = {left}; el.scrollTop = {top}; }}"
+    )
+}
+
+// Scroll #scrollpane by a page, animated when smooth scrolling is on.
+fn scroll_by_script(dy: f64) -> String {
+    format!(
+        "const el = document.getElementById('scrollpane');\nif(!el) return;\nif(window.sideScroll) window.sideScroll.by(el, 0, {dy}); else el.scrollTop += {dy};"
+    )
+}
+
+// Put #scrollpane back where a tab was left and hand focus back to it. Waits a frame so
+// the new tab's content height is in the DOM before we scroll into it, then reports the
+// viewport height since it may have changed while another tab was showing.
+fn restore_scroll_script(left: f64, top: f64) -> String {
+    format!(
+        "await new Promise(r => requestAnimationFrame(r));\nconst el = document.getElementById('scrollpane');\nif(!el) return 0;\nel.scrollLeft = {left};\nel.scrollTop = {top};\nconst a = document.activeElement;\nconst tag = a && a.tagName ? a.tagName.toLowerCase() : '';\nif(tag !== 'input' && tag !== 'textarea') {{ try {{ el.focus({{preventScroll:true}}); }} catch(_) {{ el.focus(); }} }}\nreturn el.clientHeight;"
+    )
+}
+
+// How long typing has to pause before the Markdown preview re-renders.
+const MD_PREVIEW_DEBOUNCE_MS: u64 = 150;
+
+// How long typing or selecting has to pause before the status bar word count updates.
+const WORD_COUNT_DEBOUNCE_MS: u64 = 250;
+
+// How often unsaved buffers are copied to the recovery folder.
+const RECOVERY_INTERVAL_SECS: u64 = 5;
+
+// How often each sidebar folder's open tabs are checked for changes to remember.
+const FOLDER_TABS_INTERVAL_SECS: u64 = 5;
+
+// Languages whose highlighting compiles in the background at startup, so the first file of
+// each type opens without a pause. Opening a file warms its own language either way.
+const WARM_LANGUAGES: [&str; 5] = ["rust", "python", "javascript", "markdown", "html"];
+
+// Give the confirm modal keyboard focus as soon as it opens (Save is the default button).
+const MODAL_FOCUS_SCRIPT: &str = "requestAnimationFrame(() => {\n  const input = document.querySelector('.modal input');\n  if(input) { input.focus(); input.select(); return; }\n  const b = document.querySelector('.modal .btn-primary');\n  if(b) b.focus();\n});";
+
+// Hand focus back to the editor once an overlay closes.
+const EDITOR_FOCUS_SCRIPT: &str = "requestAnimationFrame(() => {\n  const el = document.getElementById('scrollpane');\n  if(!el) return;\n  try { el.focus({preventScroll:true}); } catch(_) { el.focus(); }\n});";
+
+// Measure the real character advance with a hidden run of text inside the editor, so it
+// uses whatever font actually got picked (fallback monospace included). Reports once the
+// fonts have loaded and again whenever the window resizes (which covers zoom changes) or
+// the font settings change.
+const CHAR_MEASURE_SCRIPT: &str = "const measure = () => {\n  const host = document.getElementById('scrollpane');\n  if(!host) return 0;\n  const probe = document.createElement('span');\n  probe.style.cssText = 'position:absolute;visibility:hidden;white-space:pre;left:0;top:0;';\n  probe.textContent = 'M'.repeat(100);\n  host.appendChild(probe);\n  const w = probe.getBoundingClientRect().width / 100;\n  probe.remove();\n  return w;\n};\nawait document.fonts.ready;\ndioxus.send(measure());\nwindow.addEventListener('resize', () => dioxus.send(measure()));\nwindow.addEventListener('side-font', async () => {\n  await document.fonts.ready;\n  dioxus.send(measure());\n});\nawait new Promise(() => {});";
+
+// Tell CHAR_MEASURE_SCRIPT to measure again once the new font settings are on screen.
+const FONT_CHANGED_SCRIPT: &str = "requestAnimationFrame(() => window.dispatchEvent(new Event('side-font')));";
+
+// Wheel speed and smooth scrolling for #scrollpane. Installs window.sideScroll, whose to/by
+// animate toward a target a quarter of the way per frame when smooth scrolling is on (and
+// just jump otherwise), then waits for [speed, smooth] updates from the settings. The wheel
+// is left native at 1x without smoothing.
+const SCROLL_SETUP_SCRIPT: &str = "const S = window.sideScroll = { speed: 1, smooth: false, x: null, y: null, raf: 0 };\nS.to = (el, left, top) => {\n  left = Math.max(0, Math.min(left, el.scrollWidth - el.clientWidth));\n  top = Math.max(0, Math.min(top, el.scrollHeight - el.clientHeight));\n  if(!S.smooth) { el.scrollLeft = left; el.scrollTop = top; return; }\n  S.x = left; S.y = top;\n  if(S.raf) return;\n  const step = () => {\n    const dx = S.x - el.scrollLeft, dy = S.y - el.scrollTop;\n    if(Math.abs(dx) < 1 && Math.abs(dy) < 1) { el.scrollLeft = S.x; el.scrollTop = S.y; S.x = S.y = null; S.raf = 0; return; }\n    const ease = d => Math.abs(d) < 1 ? d : Math.sign(d) * Math.max(1, Math.abs(d) * 0.25);\n    el.scrollLeft += ease(dx); el.scrollTop += ease(dy);\n    S.raf = requestAnimationFrame(step);\n  };\n  S.raf = requestAnimationFrame(step);\n};\nS.by = (el, dx, dy) => S.to(el, (S.x ?? el.scrollLeft) + dx, (S.y ?? el.scrollTop) + dy);\ndocument.addEventListener('wheel', e => {\n  const el = e.target.closest && e.target.closest('#scrollpane');\n  if(!el || e.ctrlKey || (S.speed === 1 && !S.smooth)) return;\n  e.preventDefault();\n  const unit = e.deltaMode === 1 ? 16 : e.deltaMode === 2 ? el.clientHeight : 1;\n  let dx = e.deltaX * unit * S.speed, dy = e.deltaY * unit * S.speed;\n  if(e.shiftKey && !dx) { dx = dy; dy = 0; }\n  S.by(el, dx, dy);\n}, { passive: false });\nwhile(true) {\n  const [speed, smooth] = await dioxus.recv();\n  S.speed = speed; S.smooth = smooth;\n}";
+
+// The wheel over the tab strip scrolls it sideways; horizontal wheels and trackpads already do.
+const TAB_WHEEL_SCRIPT: &str = "document.addEventListener('wheel', e => {\n  const el = e.target.closest && e.target.closest('.tab-strip');\n  if(!el || e.ctrlKey || Math.abs(e.deltaX) >= Math.abs(e.deltaY)) return;\n  e.preventDefault();\n  const unit = e.deltaMode === 1 ? 16 : e.deltaMode === 2 ? el.clientWidth : 1;\n  el.scrollLeft += e.deltaY * unit;\n}, { passive: false });";
+
+// Scroll the sidebar to the entry Reveal in Sidebar marked, once it's drawn.
+const SIDEBAR_REVEAL_SCRIPT: &str = "requestAnimationFrame(() => {\n  const el = document.querySelector('.sidebar-item.revealed');\n  if(el) el.scrollIntoView({ block: 'nearest' });\n});";
+
+// Tell Rust whenever the window gets focus back.
+const WINDOW_FOCUS_SCRIPT: &str = "window.addEventListener('focus', () => dioxus.send(true));\nawait new Promise(() => {});";
+
+// Put text on the system clipboard. The text arrives over the eval channel so it needs no
+// escaping; the textarea fallback covers webviews that refuse the async clipboard API.
+const CLIPBOARD_WRITE_SCRIPT: &str = "const text = await dioxus.recv();\ntry {\n  await navigator.clipboard.writeText(text);\n} catch(_) {\n  const ta = document.createElement('textarea');\n  ta.value = text;\n  document.body.appendChild(ta);\n  ta.select();\n  document.execCommand('copy');\n  ta.remove();\n  const el = document.getElementById('scrollpane');\n  if(el) el.focus({preventScroll:true});\n}";
+
+fn copy_to_clipboard(text: String) {
+    let eval = document::eval(CLIPBOARD_WRITE_SCRIPT);
+    let _ = eval.send(text);
+}
+
+// F6 / Shift+F6: move focus to the next region (menu bar, tabs, sidebar, editor), skipping
+// any that aren't on screen. The tab bar focuses its active tab.
+fn region_focus_script(back: bool) -> String {
+    format!(
+        "(function(){{\n  const regions = ['.menubar', '.tabbar', '.sidebar', '#scrollpane'].map(s => document.querySelector(s)).filter(Boolean);\n  const n = regions.length;\n  const cur = regions.findIndex(r => r.contains(document.activeElement));\n  const from = cur < 0 ? n - 1 : cur;\n  for(let k = 1; k <= n; k++) {{\n    const r = regions[((from + ({back} ? -k : k)) % n + n) % n];\n    const t = r.matches('[tabindex]') ? r : (r.querySelector('.tab.active') || r.querySelector('button, [tabindex=\"0\"]'));\n    if(t) {{ t.focus(); return; }}\n  }}\n}})();"
+    )
+}
+
+// Arrow keys in menus and the tab bar: move focus `step` places among the `items` inside the
+// focused `container`, wrapping around. Outside one, start from the first container on the page.
+fn focus_step_script(container: &str, items: &str, step: i32) -> String {
+    format!(
+        "(function(){{\n  const a = document.activeElement;\n  const box = (a && a.closest('{container}')) || document.querySelector('{container}');\n  if(!box) return;\n  const list = Array.from(box.querySelectorAll('{items}'));\n  if(!list.length) return;\n  const i = list.indexOf(a);\n  const n = list.length;\n  const next = i < 0 ? (({step}) > 0 ? 0 : n - 1) : ((i + ({step})) % n + n) % n;\n  list[next].focus();\n}})();"
+    )
+}
+
+// Up/Down walk a dropdown's items; Left/Right walk the menu bar's buttons when `in_menubar`.
+fn menu_nav_script(key: &Key, in_menubar: bool) -> Option<String> {
+    match key {
+        Key::ArrowDown => Some(focus_step_script(".dropdown", ".menu-item", 1)),
+        Key::ArrowUp => Some(focus_step_script(".dropdown", ".menu-item", -1)),
+        Key::ArrowRight if in_menubar => Some(focus_step_script(".menubar", ".menu-button", 1)),
+        Key::ArrowLeft if in_menubar => Some(focus_step_script(".menubar", ".menu-button", -1)),
+        _ => None,
+    }
+}
+
+// Scroll the tab strip just far enough to show the tab with this id (see the tab's `id`).
+fn tab_reveal_script(id: u64) -> String {
+    format!(
+        "requestAnimationFrame(() => {{\n  const t = document.getElementById('tab-{id}');\n  if(t) t.scrollIntoView({{ block: 'nearest', inline: 'nearest' }});\n}});"
+    )
+}
+
+// Tab / Shift+Tab wrap around inside the open modal instead of escaping to the page.
+fn modal_tab_script(back: bool) -> String {
+    format!(
+        "(function(){{\n  const btns = Array.from(document.querySelectorAll('.modal input, .modal button'));\n  if(!btns.length) return;\n  const i = btns.indexOf(document.activeElement);\n  const n = btns.length;\n  const next = i < 0 ? 0 : ({back} ? (i - 1 + n) % n : (i + 1) % n);\n  btns[next].focus();\n}})();"
+    )
+}
+
+// Lines of unsaved changes the confirm dialog lists before cutting off.
+const CONFIRM_DIFF_MAX_LINES: usize = 400;
+
+// Sidebar width in px: the default, and how far the resize drag can go.
+const SIDEBAR_DEFAULT_W: f64 = 280.0;
+const SIDEBAR_MIN_W: f64 = 180.0;
+const SIDEBAR_MAX_W: f64 = 620.0;
+
+// Columns kept visible beside the caret when scrolling it into view horizontally.
+const REVEAL_MARGIN_COLS: usize = 4;
 
 // Click forgiveness so you can click slightly left and still land on the intended column.
 const CLICK_COL_BIAS_PX: f64 = 2.0;
 
+// Editor font size, from the settings. A global signal like CHAR_PX, so line_px() users
+// re-render when it changes.
+static FONT_PX: GlobalSignal<f64> = Signal::global(|| Settings::default().font_size);
+
+// Indentation from the settings, the last resort of resolve_indent(). A global so tabs can
+// be built without the settings signal at hand.
+static DEFAULT_INDENT: GlobalSignal<Indent> = Signal::global(Indent::default);
+
 fn line_px() -> f64 {
-    (FONT_PX * LINE_HEIGHT_EM).round()
+    (FONT_PX() * LINE_HEIGHT_EM).round()
 }
 
+// Advance width of one editor character, as measured in the webview. Starts at the
+// CHAR_WIDTH_RATIO guess, which is also what we keep if measuring fails. A global signal,
+// so everything positioned with char_px() re-renders when a measurement lands.
+static CHAR_PX: GlobalSignal<f64> = Signal::global(|| Settings::default().font_size * CHAR_WIDTH_RATIO);
+
 fn char_px() -> f64 {
-    FONT_PX * CHAR_WIDTH_RATIO
+    CHAR_PX()
+}
+
+/// Text column under a mouse x position (relative to the line element).
+fn column_at(x: f64, scroll_left: f64) -> usize {
+    let view_start_col = ((scroll_left / char_px()).floor() as isize).max(0) as usize;
+    let local_x = (x + CLICK_COL_BIAS_PX).max(0.0);
+    view_start_col + (local_x / char_px()).floor() as usize
 }
 
+/// Where a line's row starts, below the top padding. The spacer above the rendered lines, the
+/// caret and the current-line bands in the gutter and the text all go by this, so they stay
+/// on the same row at the edges of the rendered range too.
+fn row_top(line: usize) -> f64 {
+    line as f64 * line_px()
+}
 
+/// Lines start..end to render for a scroll position, with the heights of the spacers above
+/// and below them. Always start <= end <= total_lines, and start < end for a non-empty
+/// document: a scroll position left past the end (the document just shrank, or scroll
+/// past end is on) still renders the last line rather than an empty slice.
 fn visible_range(scroll_top: f64, viewport_h: f64, total_lines: usize) -> (usize, usize, f64, f64) {
+    lines_in_view(scroll_top, viewport_h, total_lines, line_px())
+}
+
+// visible_range() for lines `lp` px tall.
+fn lines_in_view(scroll_top: f64, viewport_h: f64, total_lines: usize, lp: f64) -> (usize, usize, f64, f64) {
     if total_lines == 0 {
         return (0, 0, 0.0, 0.0);
     }
-    let lp = line_px();
     // Add a buffer so scrolling doesn't cause constant re-renders.
     let buffer: usize = 20;
-    let start = ((scroll_top / lp).floor() as isize).max(0) as usize;
+    let start = (((scroll_top / lp).floor() as isize).max(0) as usize).min(total_lines - 1);
     let visible = ((viewport_h / lp).ceil() as usize).saturating_add(buffer);
     let end = (start + visible).min(total_lines);
 
-    let top_h = (start as f64) * lp;
+    let top_h = start as f64 * lp;
     let bottom_h = ((total_lines - end) as f64) * lp;
     (start, end, top_h, bottom_h)
 }
 
-fn join_lines(lines: &[String]) -> String {
-    lines.join("\n")
+/// Blank space below the last line when scrolling past the end is on: enough for the last
+/// line to reach the top of the viewport.
+fn overscroll_px(scroll_past_end: bool, viewport_h: f64) -> f64 {
+    if scroll_past_end {
+        (viewport_h - line_px() - PAD_Y_PX).max(0.0)
+    } else {
+        0.0
+    }
 }
 
-fn split_lines_vec(text: &str) -> Vec<String> {
-    let mut v: Vec<String> = text.split('\n').map(|s| s.to_string()).collect();
-    if v.is_empty() {
-        v.push(String::new());
+/// A run of marked lines on the overview strip beside the editor's scrollbar.
+#[derive(Clone, Debug, PartialEq)]
+struct OverviewMark {
+    start: usize,
+    len: usize,
+    class: &'static str,
+}
+
+/// Merge marked lines (line, class), in order, into runs for the overview strip, so a long
+/// change is one element rather than hundreds.
+fn overview_marks(lines: impl Iterator<Item = (usize, &'static str)>) -> Vec<OverviewMark> {
+    let mut marks: Vec<OverviewMark> = Vec::new();
+    for (line, class) in lines {
+        match marks.last_mut() {
+            Some(m) if m.class == class && m.start + m.len == line => m.len += 1,
+            _ => marks.push(OverviewMark { start: line, len: 1, class }),
+        }
+    }
+    marks
+}
+
+// Width of one indent level in columns, for tabs that don't get one from the file, the
+// language or .editorconfig.
+const INDENT_WIDTH: usize = 4;
+
+// Indented lines looked at when checking a file for mixed tabs and spaces.
+const INDENT_SAMPLE_LINES: usize = 500;
+
+// Text color for the part of a line past the long-line limit.
+const LONG_LINE_COLOR: &str = "#F48771";
+
+/// Recolor everything past visual column `limit`, splitting the span the limit falls in.
+/// Tabs count to the next multiple of `tab_width`, as they're drawn.
+fn mark_long_line(spans: Vec<crate::syntax::HighlightSpan>, limit: usize, tab_width: usize) -> Vec<crate::syntax::HighlightSpan> {
+    let tab_width = tab_width.max(1);
+    let mut out = Vec::with_capacity(spans.len() + 1);
+    let mut col = 0;
+    for sp in spans {
+        let mut split = None;
+        for (i, ch) in sp.text.char_indices() {
+            if col >= limit {
+                split = Some(i);
+                break;
+            }
+            col += if ch == '\t' { tab_width - col % tab_width } else { 1 };
+        }
+        let Some(i) = split else {
+            out.push(sp);
+            continue;
+        };
+        if i > 0 {
+            out.push(crate::syntax::HighlightSpan { text: sp.text[..i].to_string(), color: sp.color });
+        }
+        out.push(crate::syntax::HighlightSpan { text: sp.text[i..].to_string(), color: LONG_LINE_COLOR.to_string() });
+    }
+    out
+}
+
+// What to draw in place of a character that would otherwise be invisible or throw off the
+// one-column-per-character layout: C0 controls as their Unicode control pictures (␀, ␇,
+// ␌, ...), other controls and zero-width or direction-changing format characters as a dot.
+fn control_placeholder(c: char) -> Option<char> {
+    match c {
+        '\t' => None,
+        '\0'..='\u{1f}' => char::from_u32(0x2400 + c as u32),
+        '\u{7f}' => Some('␡'),
+        '\u{80}'..='\u{9f}'
+        | '\u{ad}'
+        | '\u{200b}'..='\u{200f}'
+        | '\u{202a}'..='\u{202e}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{2066}'..='\u{2069}'
+        | '\u{feff}' => Some('·'),
+        _ => None,
+    }
+}
+
+/// Split out each character control_placeholder() covers as its own span showing the
+/// placeholder, paired with the real character. Only the drawing changes; the buffer keeps
+/// the real bytes, and the placeholder takes the one column the caret math gives it.
+fn mark_control_chars(spans: Vec<crate::syntax::HighlightSpan>) -> Vec<(crate::syntax::HighlightSpan, Option<char>)> {
+    let mut out = Vec::with_capacity(spans.len());
+    for sp in spans {
+        if !sp.text.chars().any(|c| control_placeholder(c).is_some()) {
+            out.push((sp, None));
+            continue;
+        }
+        let mut run = String::new();
+        for c in sp.text.chars() {
+            let Some(p) = control_placeholder(c) else {
+                run.push(c);
+                continue;
+            };
+            if !run.is_empty() {
+                out.push((crate::syntax::HighlightSpan { text: std::mem::take(&mut run), color: sp.color.clone() }, None));
+            }
+            out.push((crate::syntax::HighlightSpan { text: p.to_string(), color: sp.color.clone() }, Some(c)));
+        }
+        if !run.is_empty() {
+            out.push((crate::syntax::HighlightSpan { text: run, color: sp.color }, None));
+        }
     }
-    v
+    out
 }
 
-fn split_lines(text: &str) -> Arc<Vec<String>> {
-    Arc::new(split_lines_vec(text))
+/// Indent guide count for each line in start..end, one per `width` columns. Blank lines
+/// carry on the guides of the block around them (the shallower of the nearest non-blank lines
+/// above and below).
+fn indent_guide_levels(lines: &[String], start: usize, end: usize, width: usize) -> Vec<usize> {
+    // How far to look past a run of blank lines before giving up.
+    const SCAN: usize = 200;
+
+    // Leading whitespace in columns, or None for blank lines.
+    let indent_columns = |line: &str| {
+        let text = line.trim_start_matches([' ', '\t']);
+        (!text.is_empty()).then(|| transform::indent_columns(&line[..line.len() - text.len()], width))
+    };
+    let nearest = |range: &mut dyn Iterator<Item = usize>| {
+        range.take(SCAN).find_map(|j| indent_columns(&lines[j])).unwrap_or(0)
+    };
+
+    (start..end)
+        .map(|i| {
+            let cols = indent_columns(&lines[i]).unwrap_or_else(|| {
+                let above = nearest(&mut (0..i).rev());
+                let below = nearest(&mut (i + 1..lines.len()));
+                above.min(below)
+            });
+            cols / width.max(1)
+        })
+        .collect()
 }
 
 fn next_tab_id(tabs: &[Tab]) -> u64 {
@@ -135,15 +645,130 @@ fn find_open_tab_index(tabs: &[Tab], path: &PathBuf) -> Option<usize> {
     tabs.iter().position(|t| t.path.as_ref() == Some(path))
 }
 
+/// Apply a change to the settings and write them out right away.
+fn update_settings<F: FnOnce(&mut Settings)>(mut settings: Signal<Settings>, mut status: Signal<String>, f: F) {
+    let mut s = settings();
+    f(&mut s);
+    if let Err(err) = settings::save(&s) {
+        status.set(format!("Couldn't save settings: {err}"));
+    }
+    settings.set(s);
+}
+
 fn set_active_tab_editor<F: FnOnce(&mut Tab)>(mut tabs: Signal<Vec<Tab>>, active: Signal<usize>, f: F) {
     let mut v = tabs();
     let idx = active();
     if let Some(t) = v.get_mut(idx) {
+        let before = t.editor.buffer.revision();
+        // A read-only tab gets its text back if `f` changed it; cursor moves still apply.
+        let kept = t.read_only.then(|| (t.editor.buffer.clone(), t.dirty));
         f(t);
+        if t.editor.buffer.revision() != before {
+            match kept {
+                Some((buffer, dirty)) => {
+                    t.editor.buffer = buffer;
+                    t.dirty = dirty;
+                }
+                None => sync_views(&mut v, idx),
+            }
+        }
         tabs.set(v);
     }
 }
 
+// Several tabs can show the same file (File > New View of File). They're linked by path:
+// each keeps its own cursor and scroll, while the text, undo history and dirty flag are
+// copied across after every edit and save, so saving from any of them saves all of them.
+fn other_views(v: &[Tab], idx: usize) -> Vec<usize> {
+    let Some(path) = v.get(idx).and_then(|t| t.path.as_ref()) else {
+        return Vec::new();
+    };
+    (0..v.len())
+        .filter(|&i| i != idx && v[i].view == TabView::Text && v[i].path.as_ref() == Some(path))
+        .collect()
+}
+
+/// Copy tab `idx`'s text and dirty flag to the other views of its file.
+fn sync_views(v: &mut [Tab], idx: usize) {
+    if v[idx].view != TabView::Text {
+        return;
+    }
+    for i in other_views(v, idx) {
+        let (src, dst) = if i < idx {
+            let (a, b) = v.split_at_mut(idx);
+            (&b[0], &mut a[i])
+        } else {
+            let (a, b) = v.split_at_mut(i);
+            (&a[idx], &mut b[0])
+        };
+        dst.editor.buffer.share_text(&src.editor.buffer);
+        dst.dirty = src.dirty;
+        dst.disk_stamp = src.disk_stamp;
+    }
+}
+
+/// Closing a dirty tab only risks losing work when no other view of the file is open.
+fn close_needs_confirm(v: &[Tab], idx: usize) -> bool {
+    v[idx].dirty && other_views(v, idx).is_empty()
+}
+
+/// Switch the active tab between read-only and editable.
+fn toggle_read_only(mut tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut v = tabs();
+    let Some(t) = v.get_mut(active_tab()).filter(|t| t.view == TabView::Text) else {
+        return;
+    };
+    t.read_only = !t.read_only;
+    status.set(if t.read_only { "Tab is read-only" } else { "Tab is editable" }.to_string());
+    tabs.set(v);
+}
+
+/// Open a second tab on the active file, sharing its text but with its own cursor and scroll.
+fn open_second_view(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut v = tabs();
+    let idx = active_tab();
+    let Some(t) = v.get(idx) else {
+        return;
+    };
+    if t.view != TabView::Text || t.path.is_none() {
+        status.set("Only saved text files can have a second view".to_string());
+        return;
+    }
+    let mut view = t.clone();
+    view.id = next_tab_id(&v);
+    v.insert(idx + 1, view);
+    tabs.set(v);
+    active_tab.set(idx + 1);
+    status.set("Opened a second view".to_string());
+}
+
+/// A scratch copy of the active text tab: a new untitled tab with its own copy of the text,
+/// cursor and undo history, so edits there never reach the original or its file.
+fn duplicate_tab(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut v = tabs();
+    let idx = active_tab();
+    let Some(t) = v.get(idx) else {
+        return;
+    };
+    if t.view != TabView::Text {
+        status.set("Only text tabs can be duplicated".to_string());
+        return;
+    }
+    let copy = Tab {
+        language: t.language.clone(),
+        dirty: true,
+        editor: t.editor.clone(),
+        encoding: t.encoding,
+        final_newline: t.final_newline,
+        indent: t.indent,
+        ..Tab::new_untitled(next_tab_id(&v))
+    };
+    let title = t.title();
+    v.insert(idx + 1, copy);
+    tabs.set(v);
+    active_tab.set(idx + 1);
+    status.set(format!("Duplicated {title}"));
+}
 
 fn maybe_disable_highlighting(path: &PathBuf, language: String) -> String {
     // Disable syntax highlighting for huge files because rendering and tokenising
@@ -160,118 +785,557 @@ fn maybe_disable_highlighting(path: &PathBuf, language: String) -> String {
 /* ===== DIRECTORY FUNCTIONS ===== */
 
 async fn open_directory(
-    mut current_dir: Signal<Option<PathBuf>>,
-    mut dir_contents: Signal<Vec<(String, PathBuf)>>,
-    mut status: Signal<String>,
+    roots: Signal<Vec<WorkspaceRoot>>,
+    status: Signal<String>,
+    sort: SidebarSort,
 ) {
-    if let Some(handle) = AsyncFileDialog::new().pick_folder().await {
+    if let Some(handle) = file_dialog().pick_folder().await {
         let path = handle.path().to_path_buf();
-        match list_directory_contents(&path) {
-            Ok(contents) => {
-                current_dir.set(Some(path.clone()));
-                dir_contents.set(contents);
-                status.set(format!("Opened directory: {}", path.display()));
-            }
-            Err(err) => status.set(format!("Failed to list directory: {err}")),
-        }
+        remember_dialog_dir(&path);
+        show_directory(roots, status, path, sort);
     }
 }
 
-fn list_directory_contents(path: &PathBuf) -> std::io::Result<Vec<(String, PathBuf)>> {
-    let mut contents = Vec::new();
-
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        let p = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-        contents.push((name, p));
+/// Pick the user syntax folder, remember it in the settings and load its definitions.
+async fn choose_syntax_dir(tabs: Signal<Vec<Tab>>, settings: Signal<Settings>, status: Signal<String>) {
+    let dialog = match settings.peek().syntax_dir.clone() {
+        Some(dir) => AsyncFileDialog::new().set_directory(dir),
+        None => file_dialog(),
+    };
+    if let Some(handle) = dialog.set_title("Syntax Folder").pick_folder().await {
+        let dir = handle.path().to_path_buf();
+        update_settings(settings, status, |s| s.syntax_dir = Some(dir.clone()));
+        reload_syntax(tabs, status, Some(dir));
     }
+}
 
-    // Sort by name
-    contents.sort_by(|a, b| a.0.cmp(&b.0));
-    Ok(contents)
+/// Switch to the syntax folder `dir` (or none) and reload every definition. Open files get
+/// their language detected again, since the manifest may map extensions differently now, and
+/// anything their .sidel files had to skip is reported.
+fn reload_syntax(mut tabs: Signal<Vec<Tab>>, mut status: Signal<String>, dir: Option<PathBuf>) {
+    let result = crate::syntax::set_user_dir(dir);
+    let mut v = tabs();
+    for t in v.iter_mut().filter(|t| t.view == TabView::Text) {
+        if let Some(path) = &t.path {
+            t.language = maybe_disable_highlighting(path, crate::syntax::detect_language_from_path(path));
+        }
+    }
+    let mut languages: Vec<String> = v.iter().map(|t| t.language.clone()).collect();
+    languages.sort();
+    languages.dedup();
+    tabs.set(v);
+    let skipped: Vec<String> = languages.iter().flat_map(|l| crate::syntax::sidel_errors(l)).collect();
+    match (result, crate::syntax::summarize(&skipped)) {
+        (Ok(()), None) => status.set("Reloaded syntax definitions".to_string()),
+        (Ok(()), Some(skipped)) => status.set(format!("Reloaded syntax definitions, skipping {skipped}")),
+        (Err(err), _) => status.set(format!("Syntax reload: {err}")),
+    }
 }
 
-fn close_directory(
-    mut current_dir: Signal<Option<PathBuf>>,
-    mut dir_contents: Signal<Vec<(String, PathBuf)>>,
+/// Add a folder to the workspace, or just expand it if it's already a root.
+fn show_directory(
+    mut roots: Signal<Vec<WorkspaceRoot>>,
     mut status: Signal<String>,
+    path: PathBuf,
+    sort: SidebarSort,
 ) {
-    current_dir.set(None);
-    dir_contents.set(Vec::new());
-    status.set("Directory closed".to_string());
-}
+    if let Some(root) = roots.write().iter_mut().find(|r| r.path == path) {
+        root.collapsed = false;
+        status.set(format!("Showing directory: {}", path.display()));
+        return;
+    }
 
+    match list_directory_contents(&path, sort) {
+        Ok(contents) => {
+            roots.write().push(WorkspaceRoot {
+                path: path.clone(),
+                contents,
+                collapsed: false,
+            });
+            save_workspace_roots(roots, status);
+            status.set(format!("Opened directory: {}", path.display()));
+        }
+        Err(err) => status.set(format!("Failed to list directory: {err}")),
+    }
+}
 
-// version checking
+/// Roots from the last session. Folders that have gone missing are dropped quietly.
+fn restore_workspace_roots(sort: SidebarSort) -> Vec<WorkspaceRoot> {
+    session::load()
+        .roots
+        .into_iter()
+        .filter_map(|path| {
+            let contents = list_directory_contents(&path, sort).ok()?;
+            Some(WorkspaceRoot {
+                path,
+                contents,
+                collapsed: false,
+            })
+        })
+        .collect()
+}
 
-async fn fetch_current_ver_remote(raw_current_ver_url: &str) -> Result<Version, String> {
-    let client = reqwest::Client::builder()
-        .user_agent("SIDE-update-check/1.0")
-        .timeout(Duration::from_secs(6))
-        .build()
-        .map_err(|e| format!("reqwest client: {e}"))?;
+fn save_workspace_roots(roots: Signal<Vec<WorkspaceRoot>>, mut status: Signal<String>) {
+    let mut session = session::load();
+    session.roots = roots.peek().iter().map(|r| r.path.clone()).collect();
+    if let Err(err) = session::save(&session) {
+        status.set(format!("Couldn't save session: {err}"));
+    }
+}
 
-    let text = client
-        .get(raw_current_ver_url)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP error: {e}"))?
-        .error_for_status()
-        .map_err(|e| format!("HTTP status: {e}"))?
-        .text()
-        .await
-        .map_err(|e| format!("Read body: {e}"))?;
-    
-        let s = text.trim().trim_start_matches("v").trim();
+/// What each of `roots` has open now: every file tab inside the folder (the first root it's in,
+/// when folders nest), with its cursor. Diff tabs and second views aren't counted.
+fn folder_tabs(tabs: &[Tab], active: usize, roots: &[PathBuf]) -> Vec<session::FolderTabs> {
+    let mut sets: Vec<session::FolderTabs> = roots
+        .iter()
+        .map(|root| session::FolderTabs { root: root.clone(), ..Default::default() })
+        .collect();
+    for (i, t) in tabs.iter().enumerate() {
+        let Some(path) = t.path.as_ref().filter(|_| t.view != TabView::Diff) else {
+            continue;
+        };
+        let Some(set) = sets.iter_mut().find(|s| path.starts_with(&s.root)) else {
+            continue;
+        };
+        if set.files.iter().any(|f| &f.path == path) {
+            continue;
+        }
+        if i == active {
+            set.active = Some(set.files.len());
+        }
+        let pos = t.editor.buffer.cursor();
+        set.files.push(session::OpenFile { path: path.clone(), line: pos.line, col: t.editor.buffer.char_col(pos) });
+    }
+    sets
+}
 
-        Version::parse(s).map_err(|e| format!("Bad remote version '{s}': {e}"))
+/// Store `sets` in the session, replacing what those folders had before. Folders not open
+/// now keep theirs for the next time they are.
+fn save_folder_tabs(sets: &[session::FolderTabs]) -> Result<(), String> {
+    let mut session = session::load();
+    session.open_tabs.retain(|saved| !sets.iter().any(|s| s.root == saved.root));
+    session.open_tabs.extend(sets.iter().filter(|s| !s.files.is_empty()).cloned());
+    session::save(&session)
 }
 
-async fn check_for_update_current_ver(raw_current_ver_url: &str) -> Result<Option<Version>, String> {
-    let local = Version::parse(env!("CARGO_PKG_VERSION"))
-        .map_err(|e| format!("Bad local version: {e}"))?;
+/// Reopen the files `root` had open last session, cursors where they were, and switch to the
+/// one that was active. Files already open stay as they are; ones deleted since are skipped
+/// with a note in the status bar.
+fn restore_folder_tabs(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, mut status: Signal<String>, root: &Path) {
+    let Some(saved) = session::load().open_tabs.into_iter().find(|s| s.root == root) else {
+        return;
+    };
+    let mut v = tabs();
+    let blank_start = is_blank_start(&v);
+    let (mut opened, mut missing, mut active) = (0, 0, None);
+    for (i, file) in saved.files.into_iter().enumerate() {
+        let idx = match find_open_tab_index(&v, &file.path) {
+            Some(idx) => idx,
+            None => {
+                let id = next_tab_id(&v);
+                let Ok(mut tab) = tab_from_path(id, &file.path) else {
+                    missing += 1;
+                    continue;
+                };
+                if tab.view == TabView::Text {
+                    let b = &mut tab.editor.buffer;
+                    let pos = b.pos_at_char(file.line, file.col);
+                    b.set_cursor(pos);
+                }
+                v.push(tab);
+                opened += 1;
+                emit(EditorEvent::FileOpened { tab: id, path: file.path });
+                v.len() - 1
+            }
+        };
+        if saved.active == Some(i) {
+            active = Some(idx);
+        }
+    }
+    if opened == 0 && missing == 0 {
+        return;
+    }
+    if blank_start && opened > 0 {
+        v.remove(0);
+        active = Some(active.map_or(0, |i| i - 1));
+    }
+    tabs.set(v);
+    if let Some(i) = active {
+        active_tab.set(i);
+    }
 
-    let remote = fetch_current_ver_remote(raw_current_ver_url).await?;
+    let name = root.file_name().map_or_else(|| root.display().to_string(), |n| n.to_string_lossy().into_owned());
+    let mut msg = format!("Reopened {opened} file(s) from {name}");
+    if missing > 0 {
+        msg.push_str(&format!("; {missing} no longer exist"));
+    }
+    status.set(msg);
+}
 
-    Ok((remote > local).then_some(remote))
+// Just the untitled, empty tab every launch starts with, which restored tabs can replace.
+fn is_blank_start(tabs: &[Tab]) -> bool {
+    tabs.len() == 1 && tabs[0].path.is_none() && !tabs[0].dirty && tabs[0].editor.buffer.text().is_empty()
 }
 
-/// Build CSS + bundled font
-/// Place JetBrainsMono-Regular.ttf at: assets/fonts/JetBrainsMono-Regular.ttf
-fn bundled_css() -> String {
-    const FONT_BYTES: &[u8] = include_bytes!("../assets/fonts/JetBrainsMono-Regular.ttf");
-    let b64 = STANDARD.encode(FONT_BYTES);
+/* ===== FILE DIALOGS ===== */
 
-    let template = r#"
-@font-face {
-  font-family: "BundledMono";
-  src: url("data:font/ttf;base64,__B64__") format("truetype");
+// Open dialogs start in the folder the last dialog ended up in, else the first sidebar folder.
+fn file_dialog() -> AsyncFileDialog {
+    let session = session::load();
+    match session.last_dir.into_iter().chain(session.roots).find(|d| d.is_dir()) {
+        Some(dir) => AsyncFileDialog::new().set_directory(dir),
+        None => AsyncFileDialog::new(),
+    }
 }
 
-:root {
-  --bg: #0f1117;
-  --panel: #0b0d12;
-  --text: #e6e6e6;
-  --muted: #8a93a6;
-  --border: #232a3a;
-  --linehl: rgba(88, 135, 255, 0.12);
-  --caret: rgba(230, 230, 230, 0.9);
-
-  --pad-x: __PAD_X__px;
-  --pad-y: __PAD_Y__px;
-  --line-h: __LINE_PX__px;
-  --font-size: __FONT_PX__px;
+// Save dialogs start next to the tab's file (or like open dialogs for untitled tabs), with
+// its name filled in. Untitled tabs suggest untitled.<their language's extension>, or
+// untitled.txt, so the name has an extension for language detection to go on.
+fn save_dialog(tab: &Tab) -> AsyncFileDialog {
+    let dialog = match tab.path.as_deref().and_then(Path::parent) {
+        Some(dir) => AsyncFileDialog::new().set_directory(dir),
+        None => file_dialog(),
+    };
+    let name = tab
+        .path
+        .as_deref()
+        .and_then(Path::file_name)
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("untitled.{}", crate::syntax::default_extension(&tab.language).as_deref().unwrap_or("txt")));
+    dialog.set_file_name(name)
+}
 
-  --menubar-h: 34px;
-  --tabbar-h: 30px;
+// `dir` is a picked folder, or the folder of a picked file.
+fn remember_dialog_dir(dir: &Path) {
+    let mut session = session::load();
+    session.last_dir = Some(dir.to_path_buf());
+    // Only a convenience; a failed write just means the next dialog starts elsewhere.
+    let _ = session::save(&session);
 }
 
-* {
-  font-family: "BundledMono", monospace;
-  font-variant-ligatures: none;
-  font-feature-settings: "liga" 0, "calt" 0;
-  box-sizing: border-box;
+// Pick a file to save a tab as, remembering its folder.
+async fn pick_save_path(tab: &Tab) -> Option<PathBuf> {
+    let path = save_dialog(tab).save_file().await?.path().to_path_buf();
+    if let Some(dir) = path.parent() {
+        remember_dialog_dir(dir);
+    }
+    Some(path)
+}
+
+/// Remember the sidebar layout for the next launch. Skips the write when nothing changed.
+fn save_sidebar_layout(width: f64, collapsed: bool, mut status: Signal<String>) {
+    let mut session = session::load();
+    if session.sidebar_width == Some(width) && session.sidebar_collapsed == collapsed {
+        return;
+    }
+    session.sidebar_width = Some(width);
+    session.sidebar_collapsed = collapsed;
+    if let Err(err) = session::save(&session) {
+        status.set(format!("Couldn't save session: {err}"));
+    }
+}
+
+fn list_directory_contents(path: &PathBuf, sort: SidebarSort) -> std::io::Result<Vec<DirEntry>> {
+    let mut contents = Vec::new();
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let p = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let meta = std::fs::metadata(&p).ok();
+        contents.push(DirEntry {
+            name,
+            is_dir: p.is_dir(),
+            path: p,
+            size: meta.as_ref().filter(|m| m.is_file()).map(|m| m.len()),
+            modified: meta.and_then(|m| m.modified().ok()),
+        });
+    }
+
+    sort_dir_entries(&mut contents, sort);
+    Ok(contents)
+}
+
+/// Folders always come first. Name sorting is natural (file2 before file10);
+/// modified and size put the newest/largest first and fall back to name.
+fn sort_dir_entries(entries: &mut [DirEntry], sort: SidebarSort) {
+    entries.sort_by(|a, b| {
+        let by_key = match sort {
+            SidebarSort::Name => std::cmp::Ordering::Equal,
+            SidebarSort::Modified => b.modified.cmp(&a.modified),
+            SidebarSort::Size => b.size.cmp(&a.size),
+        };
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then(by_key)
+            .then_with(|| natural_cmp(&a.name, &b.name))
+    });
+}
+
+/// Breadcrumb segments for a file: (label, folder it points at). Paths under a workspace
+/// root start at that root's name (the innermost one if roots are nested), anything else
+/// shows the full absolute path. The last segment is the file itself and has no folder.
+fn breadcrumb_segments(path: &std::path::Path, roots: &[WorkspaceRoot]) -> Vec<(String, Option<PathBuf>)> {
+    let mut dirs: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .collect();
+    dirs.reverse();
+
+    let root = roots
+        .iter()
+        .map(|r| &r.path)
+        .filter(|r| path.starts_with(r))
+        .max_by_key(|r| r.components().count());
+    if let Some(root) = root {
+        dirs.retain(|d| d.starts_with(root));
+    }
+
+    let mut segments: Vec<(String, Option<PathBuf>)> = dirs
+        .into_iter()
+        .map(|d| {
+            let label = d
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| d.display().to_string());
+            (label, Some(d))
+        })
+        .collect();
+
+    if let Some(name) = path.file_name() {
+        segments.push((name.to_string_lossy().to_string(), None));
+    }
+    segments
+}
+
+/// Compact "size · age" line for the sidebar details view.
+fn dir_entry_details(entry: &DirEntry) -> String {
+    let size = entry.size.map(format_size);
+    let age = entry.modified.and_then(|m| m.elapsed().ok()).map(format_age);
+    match (size, age) {
+        (Some(s), Some(a)) => format!("{s} · {a}"),
+        (Some(s), None) => s,
+        (None, Some(a)) => a,
+        (None, None) => String::new(),
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        86400..2592000 => format!("{} days ago", secs / 86400),
+        2592000..31536000 => format!("{} months ago", secs / 2592000),
+        _ => format!("{} years ago", secs / 31536000),
+    }
+}
+
+/// Case-insensitive comparison that orders runs of digits by numeric value.
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let mut ai = a.chars().peekable();
+    let mut bi = b.chars().peekable();
+
+    loop {
+        match (ai.peek().copied(), bi.peek().copied()) {
+            (None, None) => return a.cmp(b), // equal ignoring case; keep it deterministic
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut da = String::new();
+                while let Some(c) = ai.peek().copied().filter(char::is_ascii_digit) {
+                    da.push(c);
+                    ai.next();
+                }
+                let mut db = String::new();
+                while let Some(c) = bi.peek().copied().filter(char::is_ascii_digit) {
+                    db.push(c);
+                    bi.next();
+                }
+
+                // Compare by value without parsing, so huge numbers can't overflow.
+                let na = da.trim_start_matches('0');
+                let nb = db.trim_start_matches('0');
+                let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ord = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                ai.next();
+                bi.next();
+            }
+        }
+    }
+}
+
+fn close_directory(
+    mut roots: Signal<Vec<WorkspaceRoot>>,
+    mut status: Signal<String>,
+) {
+    roots.set(Vec::new());
+    save_workspace_roots(roots, status);
+    status.set("Directories closed".to_string());
+}
+
+fn remove_root(mut roots: Signal<Vec<WorkspaceRoot>>, mut status: Signal<String>, idx: usize) {
+    if idx >= roots.peek().len() {
+        return;
+    }
+    let removed = roots.write().remove(idx);
+    save_workspace_roots(roots, status);
+    status.set(format!("Removed from workspace: {}", removed.path.display()));
+}
+
+/// Show where the active tab's file lives: expand the innermost workspace folder holding it
+/// and mark its entry there, or the entry of the subfolder it's in, since the sidebar lists
+/// one level. `quiet` (for following tab switches) leaves the status bar alone.
+fn reveal_in_sidebar(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut roots: Signal<Vec<WorkspaceRoot>>,
+    mut revealed: Signal<Option<PathBuf>>,
+    sort: SidebarSort,
+    mut status: Signal<String>,
+    quiet: bool,
+) {
+    let Some(path) = tabs.peek().get(*active_tab.peek()).and_then(|t| t.path.clone()) else {
+        if !quiet {
+            status.set("Nothing to reveal: the tab isn't saved to a file".to_string());
+        }
+        return;
+    };
+    let root_idx = roots
+        .peek()
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| path.starts_with(&r.path))
+        .max_by_key(|(_, r)| r.path.components().count())
+        .map(|(i, _)| i);
+    let Some(ri) = root_idx else {
+        if !quiet {
+            status.set(format!("{} isn't in an open folder", path.display()));
+        }
+        return;
+    };
+
+    let mut list = roots.write();
+    let root = &mut list[ri];
+    let rel = path.strip_prefix(&root.path).unwrap_or(&path).to_path_buf();
+    let Some(first) = rel.components().next() else {
+        return;
+    };
+    let entry = root.path.join(first);
+    root.collapsed = false;
+    // A file made since the folder was listed isn't in it yet.
+    if !root.contents.iter().any(|e| e.path == entry)
+        && let Ok(contents) = list_directory_contents(&root.path, sort)
+    {
+        root.contents = contents;
+    }
+    drop(list);
+
+    revealed.set(Some(entry.clone()));
+    document::eval(SIDEBAR_REVEAL_SCRIPT);
+    if quiet {
+        return;
+    }
+    if entry == path {
+        status.set(format!("Revealed {} in the sidebar", rel.display()));
+    } else {
+        status.set(format!("{} is in the {} folder", rel.display(), first.as_os_str().to_string_lossy()));
+    }
+}
+
+
+// version checking
+
+async fn fetch_current_ver_remote(raw_current_ver_url: &str) -> Result<Version, String> {
+    let client = reqwest::Client::builder()
+        .user_agent("SIDE-update-check/1.0")
+        .timeout(Duration::from_secs(6))
+        .build()
+        .map_err(|e| format!("reqwest client: {e}"))?;
+
+    let text = client
+        .get(raw_current_ver_url)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP error: {e}"))?
+        .error_for_status()
+        .map_err(|e| format!("HTTP status: {e}"))?
+        .text()
+        .await
+        .map_err(|e| format!("Read body: {e}"))?;
+    
+        let s = text.trim().trim_start_matches("v").trim();
+
+        Version::parse(s).map_err(|e| format!("Bad remote version '{s}': {e}"))
+}
+
+async fn check_for_update_current_ver(raw_current_ver_url: &str) -> Result<Option<Version>, String> {
+    let local = Version::parse(env!("CARGO_PKG_VERSION"))
+        .map_err(|e| format!("Bad local version: {e}"))?;
+
+    let remote = fetch_current_ver_remote(raw_current_ver_url).await?;
+
+    Ok((remote > local).then_some(remote))
+}
+
+// Embedded when assets/fonts/JetBrainsMono-Regular.ttf exists at build time (see build.rs).
+#[cfg(bundled_font)]
+const FONT_BYTES: Option<&[u8]> = Some(include_bytes!("../assets/fonts/JetBrainsMono-Regular.ttf"));
+#[cfg(not(bundled_font))]
+const FONT_BYTES: Option<&[u8]> = None;
+
+/// Build CSS + bundled font
+/// Place JetBrainsMono-Regular.ttf at: assets/fonts/JetBrainsMono-Regular.ttf
+/// Without it there's no @font-face and "BundledMono" falls through to the system monospace.
+fn bundled_css() -> String {
+    let font_face = FONT_BYTES
+        .map(|bytes| {
+            format!(
+                "@font-face {{\n  font-family: \"BundledMono\";\n  src: url(\"data:font/ttf;base64,{}\") format(\"truetype\");\n}}\n",
+                STANDARD.encode(bytes)
+            )
+        })
+        .unwrap_or_default();
+
+    let template = r#"
+__FONT_FACE__
+:root {
+  --bg: #0f1117;
+  --panel: #0b0d12;
+  --text: #e6e6e6;
+  --muted: #8a93a6;
+  --border: #232a3a;
+  --linehl: rgba(88, 135, 255, 0.12);
+  --caret: rgba(230, 230, 230, 0.9);
+  --indent-guide: rgba(255, 255, 255, 0.07);
+  --ruler: rgba(255, 255, 255, 0.10);
+  --blocksel: rgba(88, 135, 255, 0.35);
+
+  --pad-x: __PAD_X__px;
+  --pad-y: __PAD_Y__px;
+
+  --menubar-h: 34px;
+  --tabbar-h: 30px;
+}
+
+* {
+  font-family: var(--editor-font);
+  font-variant-ligatures: none;
+  font-feature-settings: "liga" 0, "calt" 0;
+  box-sizing: border-box;
 }
 
 html, body {
@@ -344,12 +1408,59 @@ html, body {
   background: rgba(255,255,255,0.06);
 }
 
+/* Keyboard focus on menus and tabs (mouse clicks don't show it). */
+.menu-button:focus-visible, .menu-item:focus-visible, .tab:focus-visible, .status-item:focus-visible {
+  outline: 1px solid rgba(88, 135, 255, 0.8);
+  outline-offset: -1px;
+}
+
 .menu-sep {
   height: 1px;
   background: var(--border);
   margin: 6px 0;
 }
 
+.menu-note {
+  max-width: 260px;
+  padding: 4px 10px;
+  color: var(--muted);
+  font-size: 11px;
+  white-space: normal;
+}
+
+.dropdown-right {
+  left: auto;
+  right: 0;
+  min-width: 200px;
+}
+
+.status-item.warn {
+  color: #ffcc80;
+}
+
+.status-items {
+  margin-left: auto;
+  display: flex;
+  align-items: center;
+  gap: 4px;
+}
+
+.status-item {
+  height: 22px;
+  padding: 0 8px;
+  background: transparent;
+  border: 1px solid transparent;
+  color: var(--muted);
+  font-size: 12px;
+  cursor: pointer;
+  white-space: nowrap;
+}
+
+.status-item:hover {
+  border-color: var(--border);
+  color: var(--text);
+}
+
 .file-indicator {
   margin-left: 12px;
   color: var(--muted);
@@ -366,9 +1477,42 @@ html, body {
   align-items: stretch;
   background: #0c0f16;
   border-bottom: 1px solid var(--border);
+  user-select: none;
+}
+
+.tab-strip {
+  flex: 1;
+  min-width: 0;
+  display: flex;
+  align-items: stretch;
   overflow-x: auto;
   overflow-y: hidden;
-  user-select: none;
+}
+
+.tab-overflow {
+  height: 100%;
+  width: 30px;
+  background: transparent;
+  border: none;
+  border-left: 1px solid var(--border);
+  color: var(--muted);
+  cursor: pointer;
+}
+
+.tab-overflow:hover {
+  background: rgba(255,255,255,0.04);
+  color: var(--text);
+}
+
+.tab-list {
+  max-height: 60vh;
+  overflow-y: auto;
+}
+
+/* Right-click menus, placed at the pointer. */
+.context-menu {
+  position: fixed;
+  min-width: 200px;
 }
 
 .tab {
@@ -394,6 +1538,11 @@ html, body {
   color: var(--text);
 }
 
+.tab-lock {
+  margin-right: 4px;
+  font-size: 10px;
+}
+
 .tab-title {
   max-width: 220px;
   overflow: hidden;
@@ -436,6 +1585,45 @@ html, body {
   color: var(--text);
 }
 
+/* ===== BREADCRUMBS ===== */
+.breadcrumbs {
+  height: 24px;
+  display: flex;
+  align-items: center;
+  gap: 2px;
+  padding: 0 8px;
+  background: var(--bg);
+  border-bottom: 1px solid var(--border);
+  overflow: hidden;
+  white-space: nowrap;
+  user-select: none;
+  flex-shrink: 0;
+}
+
+.crumb {
+  padding: 1px 4px;
+  background: transparent;
+  border: none;
+  color: var(--muted);
+  font-size: 12px;
+  cursor: pointer;
+}
+
+.crumb:hover {
+  color: var(--text);
+  background: rgba(255,255,255,0.05);
+}
+
+.crumb-file {
+  color: var(--text);
+  cursor: default;
+}
+
+.crumb-sep {
+  color: var(--muted);
+  font-size: 12px;
+}
+
 /* ===== EDITOR LAYOUT ===== */
 .editor-wrap {
   flex: 1;
@@ -467,6 +1655,8 @@ html, body {
 }
 
 .gutter {
+  position: relative;
+  isolation: isolate;
   width: 56px;
   background: var(--panel);
   border-right: 1px solid var(--border);
@@ -483,12 +1673,24 @@ html, body {
 }
 
 .ln.active {
-  background: var(--linehl);
   color: #cfe0ff;
 }
 
+/* The gutter's half of the current-line band, placed like .active-line rather than
+   painted on the row, so the two halves always line up. */
+.gutter-active {
+  position: absolute;
+  left: 0;
+  right: 0;
+  height: var(--line-h);
+  background: var(--linehl);
+  pointer-events: none;
+  z-index: -1;
+}
+
 .textpane {
   position: relative;
+  isolation: isolate;
   flex: 1;
   padding: var(--pad-y) var(--pad-x);
   white-space: pre;
@@ -503,12 +1705,70 @@ html, body {
   pointer-events: auto;
   cursor: text;
   white-space: pre;
-  tab-size: 4;
+  tab-size: var(--tab-size, 4);
   width: 100%;
 }
 
-.line.active {
+/* Placeholder for a control or zero-width character: exactly one column wide whatever the
+   font has for it, so the text after it stays where the caret math puts it. */
+.ctrl-char {
+  display: inline-block;
+  width: 1ch;
+  overflow: hidden;
+  text-align: center;
+  opacity: 0.7;
+  background: rgba(244, 135, 113, 0.18);
+}
+
+/* One band behind the cursor line, drawn under the text and wide enough to cover
+   the padding and any horizontally scrolled-in part of long lines. */
+.active-line {
+  position: absolute;
+  left: 0;
+  height: var(--line-h);
   background: var(--linehl);
+  pointer-events: none;
+  z-index: -1;
+}
+
+/* Faint vertical line at each indent level; one element per line, the guides are a
+   repeating gradient sized to the indent width. */
+.indent-guides {
+  position: absolute;
+  left: var(--pad-x);
+  height: var(--line-h);
+  background-image: linear-gradient(to right, var(--indent-guide) 1px, transparent 1px);
+  pointer-events: none;
+  z-index: -1;
+}
+
+/* Vertical line at each ruler column, the full height of the text. */
+.ruler {
+  position: absolute;
+  top: 0;
+  bottom: 0;
+  width: 1px;
+  background: var(--ruler);
+  pointer-events: none;
+  z-index: -1;
+}
+
+.selection {
+  position: absolute;
+  height: var(--line-h);
+  background: var(--blocksel);
+  pointer-events: none;
+  z-index: -1;
+}
+
+/* Alt+drag column selection; a zero-width block shows as a thin bar on each line. */
+.block-sel {
+  position: absolute;
+  height: var(--line-h);
+  min-width: 2px;
+  background: var(--blocksel);
+  pointer-events: none;
+  z-index: -1;
 }
 
 .caret {
@@ -519,16 +1779,208 @@ html, body {
   pointer-events: none;
 }
 
-/* Fix Scrolling maybe */
-.scroll, .textpane, .editor-content, .gutter {
-  overflow-anchor: none;
+.caret.overwrite {
+  opacity: 0.45;
 }
 
-/* ===== SIDEBAR ===== */
-.sidebar-resize {
-  width: 280px;
-  min-width: 180px;
-  max-width: 620px;
+/* ===== HEX VIEW ===== */
+.hexpane {
+  cursor: default;
+}
+
+.hexrow {
+  height: var(--line-h);
+  white-space: pre;
+}
+
+.hex-offset {
+  color: var(--muted);
+  margin-right: 16px;
+}
+
+.hex-bytes {
+  color: #9cdcfe;
+  margin-right: 16px;
+}
+
+.hex-ascii {
+  color: #ce9178;
+}
+
+/* ===== DIFF VIEW ===== */
+.diffpane {
+  cursor: default;
+}
+
+.overview {
+  position: relative;
+  width: 10px;
+  flex-shrink: 0;
+  background: var(--panel);
+  border-left: 1px solid var(--border);
+  cursor: pointer;
+}
+
+.overview-mark {
+  position: absolute;
+  left: 1px;
+  right: 1px;
+  min-height: 2px;
+  pointer-events: none;
+}
+
+.overview-mark.removed {
+  background: rgba(244, 135, 113, 0.8);
+}
+
+.overview-mark.added {
+  background: rgba(155, 185, 85, 0.8);
+}
+
+.overview-mark.changed {
+  background: rgba(88, 135, 255, 0.8);
+}
+
+.overview-mark.match {
+  left: 3px;
+  right: 3px;
+  background: rgba(234, 196, 83, 0.9);
+}
+
+.diffrow {
+  display: grid;
+  grid-template-columns: 5ch minmax(0, 1fr) 5ch minmax(0, 1fr);
+  height: var(--line-h);
+  white-space: pre;
+}
+
+.diff-num {
+  color: var(--muted);
+  text-align: right;
+  padding-right: 1ch;
+}
+
+.diff-text {
+  overflow: hidden;
+}
+
+.diffrow.removed .diff-text:nth-child(2),
+.diffrow.changed .diff-text:nth-child(2) {
+  background: rgba(244, 135, 113, 0.18);
+}
+
+.diffrow.added .diff-text:nth-child(4),
+.diffrow.changed .diff-text:nth-child(4) {
+  background: rgba(155, 185, 85, 0.22);
+}
+
+/* ===== IMAGE PREVIEW ===== */
+.image-view {
+  flex: 1;
+  display: flex;
+  align-items: center;
+  justify-content: center;
+  padding: 20px;
+  min-width: 0;
+}
+
+.image-view img {
+  max-width: 100%;
+  max-height: 100%;
+  object-fit: contain;
+  /* checkerboard so transparent images are readable */
+  background-color: #1a1f2b;
+  background-image:
+    linear-gradient(45deg, #232a3a 25%, transparent 25%),
+    linear-gradient(-45deg, #232a3a 25%, transparent 25%),
+    linear-gradient(45deg, transparent 75%, #232a3a 75%),
+    linear-gradient(-45deg, transparent 75%, #232a3a 75%);
+  background-size: 16px 16px;
+  background-position: 0 0, 0 8px, 8px -8px, -8px 0;
+}
+
+/* Solid for half a second after any activity, then blink. */
+@keyframes caret-blink-a {
+  0%, 50% { opacity: 1; }
+  50.01%, 100% { opacity: 0; }
+}
+
+@keyframes caret-blink-b {
+  0%, 50% { opacity: 1; }
+  50.01%, 100% { opacity: 0; }
+}
+
+.caret.blink-a {
+  animation: caret-blink-a 1.06s 0.5s infinite;
+}
+
+.caret.blink-b {
+  animation: caret-blink-b 1.06s 0.5s infinite;
+}
+
+/* Focus is elsewhere (sidebar, dialog): keep showing where the caret is, quietly. */
+.caret.unfocused {
+  opacity: 0.35;
+  animation: none;
+}
+
+/* Fix Scrolling maybe */
+.scroll, .textpane, .editor-content, .gutter {
+  overflow-anchor: none;
+}
+
+/* ===== MARKDOWN PREVIEW ===== */
+.md-preview {
+  flex: 1;
+  min-width: 0;
+  overflow: auto;
+  padding: 12px 20px;
+  border-left: 1px solid var(--border);
+  background: var(--panel);
+  line-height: 1.5;
+  white-space: normal;
+}
+
+.md-preview, .md-preview * {
+  font-family: system-ui, "Segoe UI", sans-serif;
+}
+
+.md-preview code, .md-preview pre, .md-preview code * {
+  font-family: var(--editor-font);
+}
+
+.md-preview pre {
+  padding: 8px 10px;
+  background: rgba(255,255,255,0.04);
+  border: 1px solid var(--border);
+  overflow-x: auto;
+}
+
+.md-preview a {
+  color: #79a8ff;
+}
+
+.md-preview table {
+  border-collapse: collapse;
+}
+
+.md-preview th, .md-preview td {
+  border: 1px solid var(--border);
+  padding: 4px 8px;
+}
+
+.md-preview blockquote {
+  margin-left: 0;
+  padding-left: 12px;
+  border-left: 3px solid var(--border);
+  color: var(--muted);
+}
+
+/* ===== SIDEBAR ===== */
+.sidebar-resize {
+  width: 280px;
+  min-width: 180px;
+  max-width: 620px;
   background: var(--panel);
   border-right: 1px solid var(--border);
   display: flex;
@@ -619,6 +2071,36 @@ html, body {
   padding: 8px 0;
 }
 
+.sidebar-root {
+  display: flex;
+  align-items: center;
+  gap: 6px;
+  padding: 6px 8px 6px 10px;
+  font-size: 11px;
+  font-weight: 600;
+  text-transform: uppercase;
+  letter-spacing: 0.04em;
+  color: var(--muted);
+  cursor: pointer;
+  white-space: nowrap;
+}
+
+.sidebar-root:hover {
+  background: rgba(255,255,255,0.04);
+  color: var(--text);
+}
+
+.sidebar-root-arrow {
+  font-size: 9px;
+  width: 10px;
+}
+
+.sidebar-root-name {
+  flex: 1;
+  overflow: hidden;
+  text-overflow: ellipsis;
+}
+
 .sidebar-item {
   width: 100%;
   text-align: left;
@@ -637,6 +2119,26 @@ html, body {
   background: rgba(255,255,255,0.06);
 }
 
+.sidebar-item.picked {
+  background: var(--blocksel);
+}
+
+.sidebar-item.revealed {
+  background: rgba(88, 135, 255, 0.18);
+}
+
+.sidebar-compare {
+  color: rgb(88, 135, 255);
+}
+
+.sidebar-meta {
+  display: block;
+  margin-top: 2px;
+  color: var(--muted);
+  font-size: 11px;
+  min-height: 1em;
+}
+
 .sidebar-empty {
   padding: 20px;
   text-align: center;
@@ -675,12 +2177,109 @@ html, body {
   font-size: 12px;
 }
 
+.modal-input {
+  box-sizing: border-box;
+  width: 100%;
+  margin-bottom: 12px;
+  padding: 5px 8px;
+  border: 1px solid var(--border);
+  background: var(--bg);
+  color: var(--text);
+  font-family: var(--editor-font);
+  font-size: 13px;
+  outline: none;
+}
+
+.modal-input:focus {
+  border-color: rgba(88,135,255,0.6);
+}
+
+.modal-sub.warn {
+  color: #ffcc80;
+}
+
+.modal-list {
+  margin: 0 0 12px;
+  padding-left: 18px;
+  max-height: 160px;
+  overflow: auto;
+  font-size: 12px;
+}
+
+.history-list,
+.encoding-list {
+  display: flex;
+  flex-direction: column;
+  margin: 0 0 12px;
+  max-height: 280px;
+  overflow: auto;
+  border: 1px solid var(--border);
+  font-size: 12px;
+}
+
+.history-step,
+.encoding-choice {
+  padding: 4px 8px;
+  border: 0;
+  background: transparent;
+  color: var(--text);
+  text-align: left;
+  white-space: pre;
+  cursor: pointer;
+}
+
+.history-step:hover,
+.history-step:focus,
+.encoding-choice:hover,
+.encoding-choice:focus {
+  background: var(--blocksel);
+}
+
+.history-step.current,
+.encoding-choice.current {
+  color: rgb(88, 135, 255);
+}
+
+.history-step.undone {
+  color: var(--muted);
+}
+
 .modal-actions {
   display: flex;
   gap: 10px;
   justify-content: flex-end;
 }
 
+.modal-changes {
+  margin-right: auto;
+}
+
+.modal-diff {
+  margin: 0 0 12px;
+  max-height: 240px;
+  overflow: auto;
+  border: 1px solid var(--border);
+  font-size: 12px;
+  line-height: 1.5;
+  white-space: pre;
+}
+
+.modal-diff-line {
+  padding: 0 6px;
+}
+
+.modal-diff-line.removed {
+  background: rgba(244, 135, 113, 0.18);
+}
+
+.modal-diff-line.added {
+  background: rgba(155, 185, 85, 0.22);
+}
+
+.modal-diff-line.skipped {
+  color: var(--muted);
+}
+
 .btn {
   padding: 8px 12px;
   border: 1px solid var(--border);
@@ -693,6 +2292,11 @@ html, body {
   background: rgba(255,255,255,0.06);
 }
 
+.btn:focus-visible {
+  outline: 2px solid rgba(88,135,255,0.8);
+  outline-offset: 1px;
+}
+
 .btn-danger {
   background: rgba(255,80,80,0.12);
   border-color: rgba(255,80,80,0.35);
@@ -755,11 +2359,51 @@ html, body {
 "#;
 
     template
-        .replace("__B64__", &b64)
+        .replace("__FONT_FACE__", &font_face)
         .replace("__PAD_X__", &format!("{PAD_X_PX}"))
         .replace("__PAD_Y__", &format!("{PAD_Y_PX}"))
-        .replace("__LINE_PX__", &format!("{}", line_px()))
-        .replace("__FONT_PX__", &format!("{FONT_PX}"))
+}
+
+// A font name as it can go inside a quoted CSS font-family, or None if nothing's left.
+fn css_font_name(name: &str) -> Option<String> {
+    let name: String = name.chars().filter(|c| !matches!(c, '"' | '\\' | ';' | '{' | '}' | '<' | '>')).collect();
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// The font settings as CSS variables, layered over bundled_css(). A chosen font falls back
+// to the bundled one, then to the system monospace.
+fn font_css(family: Option<&str>) -> String {
+    let chosen = family.and_then(css_font_name).map(|f| format!("\"{f}\", ")).unwrap_or_default();
+    format!(
+        ":root {{\n  --font-size: {}px;\n  --line-h: {}px;\n  --editor-font: {chosen}\"BundledMono\", monospace;\n}}",
+        FONT_PX(),
+        line_px()
+    )
+}
+
+// Whether the webview has the font at all (text in it measures differently from the generic
+// fallbacks) and whether it's monospaced, as [installed, monospaced].
+fn font_check_script(name: &str) -> String {
+    format!(
+        "const ctx = document.createElement('canvas').getContext('2d');\nconst width = (font, text) => {{ ctx.font = '20px ' + font; return ctx.measureText(text).width; }};\nconst name = '\"' + {name:?} + '\"';\nconst sample = 'mmmmmiiiiiWWWWW';\nconst installed = ['monospace', 'serif', 'sans-serif'].some(g => width(name + ', ' + g, sample) !== width(g, sample));\nconst mono = Math.abs(width(name + ', monospace', 'iiiii') - width(name + ', monospace', 'MMMMM')) < 0.5;\ndioxus.send([installed, mono]);"
+    )
+}
+
+// Warn in the status bar when the chosen font can't be used as intended: columns, the caret
+// and selections are all placed assuming every character is char_px() wide.
+fn check_font(mut status: Signal<String>, family: String) {
+    let Some(name) = css_font_name(&family) else {
+        return;
+    };
+    spawn(async move {
+        let mut eval = document::eval(&font_check_script(&name));
+        match eval.recv::<(bool, bool)>().await {
+            Ok((false, _)) => status.set(format!("Font \"{name}\" isn't installed; using the bundled font")),
+            Ok((true, false)) => status.set(format!("Font \"{name}\" isn't monospaced; columns and selections won't line up")),
+            _ => {}
+        }
+    });
 }
 
 /* ===== FILE OPS (TABS) ===== */
@@ -774,16 +2418,56 @@ fn create_new_tab(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, mut
     status.set("New tab".to_string());
 }
 
+/// New untitled tab holding a language's template, with the cursor at its `$0`.
+fn create_tab_from_template(
+    mut tabs: Signal<Vec<Tab>>,
+    mut active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    language: &str,
+) {
+    let Some(template) = templates::load(language) else {
+        status.set(format!("No template for {language}"));
+        return;
+    };
+    crate::syntax::warm(language);
+    let mut buffer = Buffer::from_text(&template.text);
+    let (line, col) = template.cursor;
+    buffer.set_cursor(Cursor { line, col });
+
+    let mut v = tabs();
+    let id = next_tab_id(&v);
+    v.push(Tab {
+        language: language.to_string(),
+        indent: resolve_indent(None, language, buffer.lines()),
+        editor: EditorState { buffer, ..EditorState::default() },
+        final_newline: crate::syntax::final_newline_policy(language).unwrap_or_default(),
+        ..Tab::new_untitled(id)
+    });
+    let new_idx = v.len().saturating_sub(1);
+    tabs.set(v);
+    active_tab.set(new_idx);
+    status.set(format!("New {language} file"));
+}
+
+/// Pick a file and open it in a tab (or focus the tab it's already in). With `read_only`
+/// the tab refuses edits until it's made editable.
 async fn open_dialog_add_tab(
     mut tabs: Signal<Vec<Tab>>,
     mut active_tab: Signal<usize>,
     mut status: Signal<String>,
+    read_only: bool,
 ) {
-    if let Some(handle) = AsyncFileDialog::new().pick_file().await {
+    if let Some(handle) = file_dialog().pick_file().await {
         let path = handle.path().to_path_buf();
+        if let Some(dir) = path.parent() {
+            remember_dialog_dir(dir);
+        }
 
         // already open? just focus
         if let Some(idx) = find_open_tab_index(&tabs(), &path) {
+            if read_only {
+                tabs.write()[idx].read_only = true;
+            }
             active_tab.set(idx);
             status.set(format!("Focused {}", path.display()));
             return;
@@ -791,86 +2475,737 @@ async fn open_dialog_add_tab(
 
         status.set(format!("Opening {} ...", path.display()));
 
-        match std::fs::read_to_string(&path) {
-            Ok(contents) => {
-                let lines = split_lines(&contents);
-
-                let mut v = tabs();
-                let id = next_tab_id(&v);
-                let language = maybe_disable_highlighting(
-                    &path,
-                    crate::syntax::detect_language_from_path(&path),
-                );
-
-                v.push(Tab {
-                    id,
-                    path: Some(path.clone()),
-                    language,
-                    dirty: false,
-                    editor: EditorState {
-                        lines,
-                        cursor: Cursor { line: 0, col: 0 },
-                        scroll_x: 0.0,
-                        scroll_y: 0.0,
-                    },
-                });
+        let mut v = tabs();
+        let id = next_tab_id(&v);
+        match tab_from_path(id, &path) {
+            Ok(tab) => {
+                let mut msg = opened_message(&tab, &path);
+                if read_only {
+                    msg.push_str(" (read-only)");
+                }
+                v.push(Tab { read_only, ..tab });
 
                 let new_idx = v.len().saturating_sub(1);
                 tabs.set(v);
                 active_tab.set(new_idx);
-                status.set(format!("Opened {}", path.display()));
+                status.set(msg);
+                emit(EditorEvent::FileOpened { tab: id, path });
             }
             Err(err) => status.set(format!("Open failed: {err}")),
         }
     }
 }
 
+/// A file's text as a text tab would read it, for comparing.
+fn read_text_file(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+    match encoding::decode_with_bom(&bytes) {
+        Some((text, _)) => Ok(text.replace("\r\n", "\n")),
+        None if hexview::looks_binary(&bytes) => Err(format!("Can't compare {}: it's a binary file", path.display())),
+        None => Ok(String::from_utf8_lossy(&bytes).replace("\r\n", "\n")),
+    }
+}
 
-async fn open_path_in_tab(
+fn open_diff_tab(
     mut tabs: Signal<Vec<Tab>>,
     mut active_tab: Signal<usize>,
     mut status: Signal<String>,
-    path: PathBuf,
+    diff: diff::FileDiff,
 ) {
-    if path.is_dir() {
-        status.set(format!("Directory click does nothing (yet): {}", path.display()));
-        return;
+    let s = diff.summary;
+    status.set(format!("{}: {} added, {} removed, {} changed", diff.title(), s.added, s.removed, s.changed));
+    let mut v = tabs();
+    let id = next_tab_id(&v);
+    v.push(Tab {
+        view: TabView::Diff,
+        diff: Some(Arc::new(diff)),
+        read_only: false,
+        ..Tab::new_untitled(id)
+    });
+    let new_idx = v.len() - 1;
+    tabs.set(v);
+    active_tab.set(new_idx);
+}
+
+/// Open a tab comparing two files, `left` as the old side.
+fn compare_files(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    left: PathBuf,
+    right: PathBuf,
+) {
+    match read_text_file(&left).and_then(|l| Ok((l, read_text_file(&right)?))) {
+        Ok((l, r)) => open_diff_tab(tabs, active_tab, status, diff::FileDiff::new(left, &l, right, &r)),
+        Err(err) => status.set(err),
     }
+}
 
-    if let Some(idx) = find_open_tab_index(&tabs(), &path) {
-        active_tab.set(idx);
-        status.set(format!("Focused {}", path.display()));
+/// Compare the active tab's text, unsaved edits included, with a file picked from a dialog.
+async fn compare_active_with(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    let Some((left, text)) = tabs.peek().get(*active_tab.peek()).and_then(|t| {
+        let name = t.path.clone().unwrap_or_else(|| PathBuf::from(t.title()));
+        (t.view == TabView::Text).then(|| (name, t.editor.buffer.text()))
+    }) else {
+        status.set("Only text tabs can be compared".to_string());
         return;
+    };
+    let Some(handle) = file_dialog().set_title("Compare With").pick_file().await else {
+        return;
+    };
+    let right = handle.path().to_path_buf();
+    if let Some(dir) = right.parent() {
+        remember_dialog_dir(dir);
     }
-
-    status.set(format!("Opening {} ...", path.display()));
-    match std::fs::read_to_string(&path) {
-        Ok(contents) => {
-            let mut v = tabs();
-            let id = next_tab_id(&v);
-            let language = maybe_disable_highlighting(&path, crate::syntax::detect_language_from_path(&path));
-            v.push(Tab {
-                id,
-                path: Some(path.clone()),
-                language,
-                dirty: false,
-                editor: EditorState {
-                    lines: split_lines(&contents),
-                    cursor: Cursor { line: 0, col: 0 },
-                    scroll_x: 0.0,
-                    scroll_y: 0.0,
-                },
-            });
-            let new_idx = v.len().saturating_sub(1);
-            tabs.set(v);
-            active_tab.set(new_idx);
-            status.set(format!("Opened {}", path.display()));
-        }
-        Err(err) => status.set(format!("Open failed: {err}")),
+    match read_text_file(&right) {
+        Ok(r) => open_diff_tab(tabs, active_tab, status, diff::FileDiff::new(left, &text, right, &r)),
+        Err(err) => status.set(err),
     }
 }
 
+
+/// Alt+O: open the active file's counterpart (header/source, module/test, ...), the first
+/// candidate that exists. When none do, offer to create the likeliest one.
+fn open_alternate_file(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    mut offer: Signal<Option<PathBuf>>,
+    rules: &[Vec<String>],
+) {
+    let Some(path) = tabs().get(active_tab()).and_then(|t| t.path.clone()) else {
+        status.set("Save the file first to find its counterpart".to_string());
+        return;
+    };
+    let candidates = alternate::candidates(&path, rules);
+    if let Some(found) = candidates.iter().find(|p| p.is_file()).cloned() {
+        spawn(async move { open_path_in_tab(tabs, active_tab, status, found).await });
+    } else if let Some(first) = candidates.into_iter().next() {
+        offer.set(Some(first));
+        overlay.set(Overlay::CreateAlternate);
+    } else {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        status.set(format!("No counterpart rule matches {name}"));
+    }
+}
+
+/// Create an empty counterpart file (and its folder) and open it.
+fn create_alternate_file(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>, path: PathBuf) {
+    let created = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::OpenOptions::new().write(true).create_new(true).open(&path).map(|_| ()));
+    match created {
+        Ok(()) => {
+            spawn(async move { open_path_in_tab(tabs, active_tab, status, path).await });
+        }
+        Err(err) => status.set(format!("Couldn't create {}: {err}", path.display())),
+    }
+}
+
+async fn open_path_in_tab(
+    mut tabs: Signal<Vec<Tab>>,
+    mut active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    path: PathBuf,
+) {
+    if path.is_dir() {
+        status.set(format!("Directory click does nothing (yet): {}", path.display()));
+        return;
+    }
+
+    if let Some(idx) = find_open_tab_index(&tabs(), &path) {
+        active_tab.set(idx);
+        status.set(format!("Focused {}", path.display()));
+        return;
+    }
+
+    status.set(format!("Opening {} ...", path.display()));
+    let mut v = tabs();
+    let id = next_tab_id(&v);
+    match tab_from_path(id, &path) {
+        Ok(tab) => {
+            let msg = opened_message(&tab, &path);
+            v.push(tab);
+            let new_idx = v.len().saturating_sub(1);
+            tabs.set(v);
+            active_tab.set(new_idx);
+            status.set(msg);
+            emit(EditorEvent::FileOpened { tab: id, path });
+        }
+        Err(err) => status.set(format!("Open failed: {err}")),
+    }
+}
+
+/// Put the active tab's cursor on a 1-based line/column (clamped to the buffer) and scroll
+/// so that line sits in the middle of the viewport. Columns count characters, not bytes.
+fn goto_line_col(
+    mut tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut scroll_top: Signal<f64>,
+    shown_tab_id: Signal<Option<u64>>,
+    viewport_h: f64,
+    line: usize,
+    col: usize,
+) {
+    let idx = *active_tab.peek();
+    let mut v = tabs.write();
+    let Some(t) = v.get_mut(idx) else {
+        return;
+    };
+    if t.view != TabView::Text {
+        return;
+    }
+
+    let s = &mut t.editor;
+    let pos = s.buffer.pos_at_char(line.saturating_sub(1), col.saturating_sub(1));
+    s.buffer.set_cursor(pos);
+    let line = pos.line;
+
+    let lp = line_px();
+    let top = (PAD_Y_PX + line as f64 * lp + lp / 2.0 - viewport_h / 2.0).max(0.0);
+    s.scroll_y = top;
+
+    // A tab that isn't on screen yet gets scrolled by the tab-switch effect instead.
+    if *shown_tab_id.peek() == Some(t.id) {
+        let left = s.scroll_x;
+        drop(v);
+        scroll_top.set(top);
+        document::eval(&scroll_to_script(left, top));
+    }
+}
+
+/// Read a file into a new tab. Images get a preview and other binary files go to the
+/// hex viewer instead of the text editor.
+fn tab_from_path(id: u64, path: &PathBuf) -> std::io::Result<Tab> {
+    let bytes = std::fs::read(path)?;
+    let disk_stamp = DiskStamp::read(path);
+
+    if let Some(mime) = imageview::image_mime(path) {
+        return Ok(Tab {
+            id,
+            path: Some(path.clone()),
+            language: "plain".to_string(),
+            dirty: false,
+            editor: EditorState::default(),
+            view: TabView::Image,
+            image_uri: Some(Arc::new(imageview::data_uri(mime, &bytes))),
+            bytes: Some(Arc::new(bytes)),
+            encoding: TextEncoding::default(),
+            final_newline: FinalNewline::default(),
+            format_changed: false,
+            disk_stamp,
+            diff: None,
+            read_only: false,
+            indent: Indent::default(),
+        });
+    }
+
+    let (contents, encoding) = match encoding::decode_with_bom(&bytes) {
+        Some(decoded) => decoded,
+        None if hexview::looks_binary(&bytes) => {
+            return Ok(Tab {
+                id,
+                path: Some(path.clone()),
+                language: "plain".to_string(),
+                dirty: false,
+                editor: EditorState::default(),
+                view: TabView::Hex,
+                bytes: Some(Arc::new(bytes)),
+                image_uri: None,
+                encoding: TextEncoding::default(),
+                final_newline: FinalNewline::default(),
+                format_changed: false,
+                disk_stamp,
+                diff: None,
+                read_only: false,
+                indent: Indent::default(),
+            });
+        }
+        // looks_binary already rejected invalid UTF-8
+        None => (String::from_utf8_lossy(&bytes).into_owned(), TextEncoding::Utf8),
+    };
+
+    let detected = crate::syntax::detect_language_from_path(path);
+    let final_newline = final_newline_for(path, &detected);
+    let buffer = Buffer::from_text(&contents).with_crlf(LineEnding::detect(&contents).is_crlf());
+    let indent = resolve_indent(Some(path), &detected, buffer.lines());
+    let language = maybe_disable_highlighting(path, detected);
+    crate::syntax::warm(&language);
+    Ok(Tab {
+        id,
+        path: Some(path.clone()),
+        language,
+        dirty: false,
+        editor: EditorState {
+            buffer,
+            ..EditorState::default()
+        },
+        view: TabView::Text,
+        bytes: None,
+        image_uri: None,
+        encoding,
+        final_newline,
+        format_changed: false,
+        disk_stamp,
+        diff: None,
+        read_only: false,
+        indent,
+    })
+}
+
+/// Text tabs whose file another program changed since it was opened or last saved.
+fn changed_on_disk(v: &[Tab]) -> Vec<usize> {
+    (0..v.len())
+        .filter(|&i| {
+            let t = &v[i];
+            let (Some(path), Some(stamp)) = (&t.path, t.disk_stamp) else {
+                return false;
+            };
+            t.view == TabView::Text && DiskStamp::read(path).is_some_and(|now| now != stamp)
+        })
+        .collect()
+}
+
+/// Re-read a tab's file into its buffer (one undo step) and pass it on to its other views.
+fn reload_tab(v: &mut [Tab], idx: usize) -> Result<(), String> {
+    let path = v[idx].path.clone().ok_or("untitled tab")?;
+    let fresh = tab_from_path(v[idx].id, &path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    if fresh.view != TabView::Text {
+        return Err(format!("{} is no longer a text file", path.display()));
+    }
+    let t = &mut v[idx];
+    t.editor.buffer.group(|b| {
+        b.replace_all(&fresh.editor.buffer.text());
+        b.set_crlf(fresh.editor.buffer.crlf());
+    });
+    t.editor.buffer.mark_saved();
+    t.encoding = fresh.encoding;
+    t.format_changed = false;
+    t.disk_stamp = fresh.disk_stamp;
+    t.dirty = false;
+    sync_views(v, idx);
+    Ok(())
+}
+
+/// Window focus with reload_on_focus on: clean tabs whose files changed get reloaded quietly,
+/// and the first changed dirty tab gets the reload prompt. Resolving the prompt runs this
+/// again for the next one.
+fn reload_changed_tabs(mut tabs: Signal<Vec<Tab>>, mut status: Signal<String>, mut overlay: Signal<Overlay>) {
+    let mut v = tabs();
+    let mut reloaded = 0;
+    let mut dirty = None;
+    for i in changed_on_disk(&v) {
+        if v[i].dirty {
+            dirty.get_or_insert(i);
+            continue;
+        }
+        // Reloading one view already updated the others.
+        if v[i].disk_stamp != v[i].path.as_deref().and_then(DiskStamp::read) {
+            match reload_tab(&mut v, i) {
+                Ok(()) => reloaded += 1,
+                Err(err) => {
+                    status.set(format!("Reload failed: {err}"));
+                    // Don't try this one again until it changes once more.
+                    v[i].disk_stamp = v[i].path.as_deref().and_then(DiskStamp::read);
+                }
+            }
+        }
+    }
+    tabs.set(v);
+
+    if reloaded > 0 {
+        status.set(format!("Reloaded {reloaded} file(s) changed on disk"));
+    }
+    if let Some(i) = dirty
+        && *overlay.peek() == Overlay::None
+    {
+        overlay.set(Overlay::Reload(i));
+    }
+}
+
+/// Close the reload prompt and move on to the next changed tab. Keeping the edits stops the
+/// asking for this version of the file, so saving will write over it without the overwrite
+/// prompt either.
+fn answer_reload_prompt(
+    mut tabs: Signal<Vec<Tab>>,
+    mut status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    idx: usize,
+    reload: bool,
+) {
+    overlay.set(Overlay::None);
+    let mut v = tabs();
+    if idx >= v.len() {
+        return;
+    }
+    if reload {
+        match reload_tab(&mut v, idx) {
+            Ok(()) => status.set(format!("Reloaded {}", v[idx].title())),
+            Err(err) => status.set(format!("Reload failed: {err}")),
+        }
+    } else {
+        v[idx].disk_stamp = v[idx].path.as_deref().and_then(DiskStamp::read);
+        sync_views(&mut v, idx);
+    }
+    tabs.set(v);
+    reload_changed_tabs(tabs, status, overlay);
+}
+
+/// Final-newline policy for a file: .editorconfig's insert_final_newline first, then the
+/// language's manifest entry, else leave the end of the file as it is.
+fn final_newline_for(path: &Path, language: &str) -> FinalNewline {
+    match editorconfig::properties(path).get("insert_final_newline").map(String::as_str) {
+        Some("true") => FinalNewline::Ensure,
+        Some("false") => FinalNewline::Strip,
+        _ => crate::syntax::final_newline_policy(language).unwrap_or_default(),
+    }
+}
+
+/// Decide a tab's indentation. Style and width are settled separately, each by the first of
+/// these that has an answer: .editorconfig (indent_style; indent_size, or tab_width when
+/// indent_size is "tab"), the file's own indented lines, the language's indent_style and
+/// indent_size in manifest.toml, then the default from the settings.
+fn resolve_indent(path: Option<&Path>, language: &str, lines: &[String]) -> Indent {
+    let props = path.map(editorconfig::properties).unwrap_or_default();
+    let mix = transform::indent_mix(lines, INDENT_SAMPLE_LINES);
+    let (lang_tabs, lang_width) = crate::syntax::indent_policy(language);
+    let default = *DEFAULT_INDENT.peek();
+
+    let (tabs, tabs_from) = match props.get("indent_style").map(String::as_str) {
+        Some("tab") => (true, IndentSource::EditorConfig),
+        Some("space") => (false, IndentSource::EditorConfig),
+        _ if mix.tabs + mix.spaces > 0 => (mix.prefers_tabs(), IndentSource::File),
+        _ => match lang_tabs {
+            Some(t) => (t, IndentSource::Language),
+            None => (default.tabs, IndentSource::Settings),
+        },
+    };
+
+    let configured = props
+        .get("indent_size")
+        .filter(|v| v.as_str() != "tab")
+        .or(props.get("tab_width"))
+        .and_then(|v| v.parse().ok())
+        .filter(|&w| w > 0);
+    // Tab-indented lines don't say how wide a level is.
+    let detected = if tabs { None } else { transform::indent_unit(lines, INDENT_SAMPLE_LINES) };
+    let (width, width_from) = match (configured, detected, lang_width) {
+        (Some(w), _, _) => (w, IndentSource::EditorConfig),
+        (None, Some(w), _) => (w, IndentSource::File),
+        (None, None, Some(w)) => (w, IndentSource::Language),
+        (None, None, None) => (default.width, IndentSource::Settings),
+    };
+
+    Indent { tabs, width, tabs_from, width_from }
+}
+
+/// Rewrite the active tab's indentation as tabs or as spaces, reporting how many lines changed.
+fn convert_indentation(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>, to_tabs: bool) {
+    let mut changed = None;
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view != TabView::Text {
+            return;
+        }
+        let width = t.indent.width;
+        let n = if to_tabs {
+            t.editor.buffer.reindent(|indent| transform::indent_to_tabs(indent, width))
+        } else {
+            t.editor.buffer.reindent(|indent| transform::indent_to_spaces(indent, width))
+        };
+        if n > 0 {
+            t.dirty = true;
+        }
+        changed = Some((n, width));
+    });
+    let kind = if to_tabs { "tabs" } else { "spaces" };
+    match changed {
+        Some((0, _)) => status.set(format!("Indentation is already {kind}")),
+        Some((n, width)) => status.set(format!("Converted indentation to {kind} on {n} line(s) ({width} columns per level)")),
+        None => {}
+    }
+}
+
+/// F2: rename the identifier under the cursor (or the selected one) across the active file.
+/// Opens the rename modal with the current name filled in.
+fn start_rename(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    mut rename: Signal<(String, String)>,
+) {
+    let Some(t) = tabs().get(active_tab()).filter(|t| t.view == TabView::Text).cloned() else {
+        return;
+    };
+    if t.read_only {
+        status.set("Tab is read-only".to_string());
+        return;
+    }
+    match t.editor.buffer.word_to_rename() {
+        Some(word) => {
+            rename.set((word.clone(), word));
+            overlay.set(Overlay::Rename);
+        }
+        None => status.set("Put the cursor on a name to rename it".to_string()),
+    }
+}
+
+/// Replace every whole-word occurrence of the old name with the new one, as one undo step.
+fn apply_rename(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    rename: Signal<(String, String)>,
+) {
+    let (from, to) = rename();
+    if to.is_empty() {
+        status.set("Type a new name first".to_string());
+        return;
+    }
+    overlay.set(Overlay::None);
+    if to == from {
+        return;
+    }
+    let mut n = 0;
+    set_active_tab_editor(tabs, active_tab, |t| {
+        n = t.editor.buffer.rename_word(&from, &to);
+        if n > 0 {
+            t.dirty = true;
+        }
+    });
+    status.set(format!("Renamed {n} occurrence(s) of {from} to {to}"));
+}
+
+/// The bytes a text tab writes to disk, using its line ending, encoding and final-newline policy.
+fn tab_text_bytes(t: &Tab) -> Vec<u8> {
+    let lines = t.final_newline.apply(t.editor.buffer.lines());
+    t.encoding.encode(&lines.join(t.line_ending().as_str()))
+}
+
+/// Step the active tab's final-newline policy, overriding the file type's default.
+fn cycle_final_newline(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut msg = String::new();
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view != TabView::Text {
+            return;
+        }
+        let lines = t.editor.buffer.lines();
+        let before = t.final_newline.apply(lines).len();
+        t.final_newline = t.final_newline.cycled();
+        // Only dirty if the next save would actually write something different.
+        if t.final_newline.apply(lines).len() != before {
+            t.format_changed = true;
+            t.dirty = true;
+        }
+        msg = format!("On save: {}", t.final_newline.label());
+    });
+    if !msg.is_empty() {
+        status.set(msg);
+    }
+}
+
+/// Flip the active tab between LF and CRLF, as an undo step of its own. Flipping back (or
+/// undoing) to the ending on disk leaves the tab clean again.
+fn toggle_line_ending(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut msg = String::new();
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view != TabView::Text {
+            return;
+        }
+        t.editor.buffer.set_crlf(t.line_ending().toggled().is_crlf());
+        t.refresh_dirty();
+        msg = format!("Line endings: {}", t.line_ending().label());
+    });
+    if !msg.is_empty() {
+        status.set(msg);
+    }
+}
+
+fn set_tab_encoding(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    encoding: TextEncoding,
+) {
+    let mut msg = String::new();
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view != TabView::Text || t.encoding == encoding {
+            return;
+        }
+        t.encoding = encoding;
+        t.format_changed = true;
+        t.dirty = true;
+        msg = format!("Encoding set to {}; save to write it", encoding.label());
+    });
+    if !msg.is_empty() {
+        status.set(msg);
+    }
+}
+
+/// Decode the active tab's file again as `encoding`, for when detection got it wrong. A hex
+/// tab uses the bytes it holds, so a legacy file opened as binary can come back as text;
+/// a text tab reads its file again. Unsaved edits are dropped. Saves then use `encoding`.
+fn reopen_with_encoding(mut tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>, encoding: TextEncoding) {
+    let idx = active_tab();
+    let mut v = tabs();
+    let Some(t) = v.get(idx) else {
+        return;
+    };
+    if !matches!(t.view, TabView::Text | TabView::Hex) {
+        status.set("Only text and hex tabs can be reopened with an encoding".to_string());
+        return;
+    }
+    let bytes = match (&t.bytes, &t.path) {
+        (Some(bytes), _) if t.view == TabView::Hex => bytes.as_ref().clone(),
+        (_, Some(path)) => match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                status.set(format!("Couldn't read {}: {err}", path.display()));
+                return;
+            }
+        },
+        _ => {
+            status.set("Save the file first to reopen it with another encoding".to_string());
+            return;
+        }
+    };
+    let Some(text) = encoding.decode(&bytes) else {
+        status.set(format!("The file isn't valid {}", encoding.label()));
+        return;
+    };
+
+    let t = &mut v[idx];
+    let crlf = LineEnding::detect(&text).is_crlf();
+    let text = text.replace("\r\n", "\n");
+    if t.view == TabView::Hex {
+        let cursor = t.editor.buffer.cursor();
+        t.editor.buffer = Buffer::from_text(&text).with_crlf(crlf);
+        t.editor.buffer.set_cursor(cursor);
+        t.view = TabView::Text;
+        t.bytes = None;
+        if let Some(path) = &t.path {
+            t.language = crate::syntax::detect_language_from_path(path);
+            crate::syntax::warm(&t.language);
+            t.indent = resolve_indent(Some(path), &t.language, t.editor.buffer.lines());
+        }
+    } else {
+        t.editor.buffer.group(|b| {
+            b.replace_all(&text);
+            b.set_crlf(crlf);
+        });
+    }
+    t.editor.buffer.mark_saved();
+    t.encoding = encoding;
+    t.format_changed = false;
+    t.dirty = false;
+    t.disk_stamp = t.path.as_deref().and_then(DiskStamp::read);
+    sync_views(&mut v, idx);
+    tabs.set(v);
+    status.set(format!("Reopened as {}", encoding.label()));
+}
+
+fn opened_message(tab: &Tab, path: &PathBuf) -> String {
+    match tab.view {
+        TabView::Hex => format!("Opened {} (binary, read-only hex view)", path.display()),
+        TabView::Image => format!("Opened {} ({})", path.display(), image_info(tab)),
+        TabView::Text | TabView::Diff => format!("Opened {}", path.display()),
+    }
+}
+
+/// "1920×1080 · 245.1 KB" for image tabs; just the size if the header couldn't be read.
+fn image_info(tab: &Tab) -> String {
+    let bytes = tab.bytes.clone().unwrap_or_default();
+    let size = format_size(bytes.len() as u64);
+    match imageview::image_dimensions(&bytes) {
+        Some((w, h)) => format!("{w}×{h} · {size}"),
+        None => size,
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Switch the active tab between the text editor and the hex view.
+fn toggle_hex_view(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    let mut msg = String::new();
+    set_active_tab_editor(tabs, active_tab, |t| match t.view {
+        // Images drop to their raw bytes; from there the usual hex <-> text rules apply.
+        TabView::Image => {
+            t.view = TabView::Hex;
+            msg = "Hex view".to_string();
+        }
+        TabView::Text => {
+            t.bytes = Some(Arc::new(tab_text_bytes(t)));
+            t.view = TabView::Hex;
+            msg = "Hex view".to_string();
+        }
+        TabView::Hex => {
+            let bytes = t.bytes.clone().unwrap_or_default();
+            let decoded = encoding::decode_with_bom(&bytes).or_else(|| {
+                std::str::from_utf8(&bytes)
+                    .ok()
+                    .map(|text| (text.to_string(), TextEncoding::Utf8))
+            });
+            match decoded {
+                Some((text, encoding)) => {
+                    t.encoding = encoding;
+                    let cursor = t.editor.buffer.cursor();
+                    t.editor.buffer = Buffer::from_text(&text).with_crlf(LineEnding::detect(&text).is_crlf());
+                    t.editor.buffer.set_cursor(cursor);
+                    t.view = TabView::Text;
+                    t.bytes = None;
+                    msg = "Text view".to_string();
+                }
+                None => msg = "Binary file can't be shown as text".to_string(),
+            }
+        }
+        TabView::Diff => msg = "A comparison has no hex view".to_string(),
+    });
+    if !msg.is_empty() {
+        status.set(msg);
+    }
+}
+
+/// Save a tab, first asking (through the overwrite modal) if its file changed on disk since
+/// it was opened or last saved. Returns false when it's left waiting on that answer.
 async fn save_tab_to_path(
+    tabs: Signal<Vec<Tab>>,
+    tab_index: usize,
+    status: Signal<String>,
+    path: PathBuf,
+    mut overlay: Signal<Overlay>,
+) -> bool {
+    let v = tabs();
+    let Some(t) = v.get(tab_index) else {
+        return true;
+    };
+    let recorded = t.disk_stamp.filter(|_| t.path.as_ref() == Some(&path));
+    // A file that's gone since has nothing to clobber.
+    if let (Some(recorded), Some(now)) = (recorded, DiskStamp::read(&path))
+        && now != recorded
+    {
+        overlay.set(Overlay::Overwrite(tab_index));
+        return false;
+    }
+    write_tab_to_path(tabs, tab_index, status, path).await;
+    true
+}
+
+async fn write_tab_to_path(
     mut tabs: Signal<Vec<Tab>>,
     tab_index: usize,
     mut status: Signal<String>,
@@ -881,23 +3216,89 @@ async fn save_tab_to_path(
         return;
     }
 
-    let text = v[tab_index].editor.lines.as_ref().join("\n");
-    match std::fs::write(&path, text) {
-        Ok(()) => {
+    if v[tab_index].view != TabView::Text {
+        status.set("This view is read-only; switch back to text to save".to_string());
+        return;
+    }
+
+    let encoding = v[tab_index].encoding;
+    if !encoding.can_encode(&v[tab_index].editor.buffer.text()) {
+        status.set(format!("Not saved: the text has characters {} can't store; pick another encoding", encoding.label()));
+        return;
+    }
+    let bytes = tab_text_bytes(&v[tab_index]);
+    match write_atomically(&path, &bytes) {
+        Ok(in_place) => {
+            // A first save, or Save As somewhere else, can mean other indentation rules.
+            if v[tab_index].path.as_ref() != Some(&path) {
+                let language = crate::syntax::detect_language_from_path(&path);
+                v[tab_index].indent = resolve_indent(Some(&path), &language, v[tab_index].editor.buffer.lines());
+            }
             v[tab_index].path = Some(path.clone());
             v[tab_index].language = crate::syntax::detect_language_from_path(&path);
+            crate::syntax::warm(&v[tab_index].language);
+            v[tab_index].editor.buffer.mark_saved();
+            v[tab_index].format_changed = false;
             v[tab_index].dirty = false;
+            v[tab_index].disk_stamp = DiskStamp::read(&path);
+            let id = v[tab_index].id;
+            sync_views(&mut v, tab_index);
             tabs.set(v);
-            status.set(format!("Saved {}", path.display()));
+            emit(EditorEvent::FileSaved { tab: id, path: path.clone() });
+            if in_place {
+                status.set(format!("Saved {} (written in place, a temp file couldn't be put next to it)", path.display()));
+            } else {
+                status.set(format!("Saved {}", path.display()));
+            }
         }
         Err(err) => status.set(format!("Save failed: {err}")),
     }
 }
 
+/// Write to a temp file next to `path`, then rename it over the target, so a crash or a full
+/// disk mid-write leaves the old file as it was. The old file's permissions carry over, and
+/// symlinks are written through rather than replaced. When the temp file can't be created
+/// (a writable file in a read-only folder) or moved over the target, the file is written in
+/// place instead, and this returns Ok(true).
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<bool> {
+    use std::io::Write;
+
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = target.with_file_name(format!(".{name}.side-tmp"));
+
+    let Ok(mut file) = std::fs::File::create(&tmp) else {
+        std::fs::write(&target, bytes)?;
+        return Ok(true);
+    };
+    // A failed write (a full disk) is an error rather than a reason to write in place, which
+    // would fail the same way after truncating the old file.
+    let written = (|| {
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Ok(meta) = std::fs::metadata(&target) {
+            std::fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        Ok(())
+    })();
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(err);
+    }
+
+    if std::fs::rename(&tmp, &target).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+        std::fs::write(&target, bytes)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
 async fn save_active_or_save_as(
     tabs: Signal<Vec<Tab>>,
     active_tab: Signal<usize>,
     status: Signal<String>,
+    overlay: Signal<Overlay>,
 ) {
     let idx = active_tab();
     let v = tabs();
@@ -906,61 +3307,317 @@ async fn save_active_or_save_as(
     }
 
     if let Some(p) = v[idx].path.clone() {
-        save_tab_to_path(tabs, idx, status, p).await;
+        save_tab_to_path(tabs, idx, status, p, overlay).await;
         return;
     }
 
-    if let Some(handle) = AsyncFileDialog::new().save_file().await {
-        let path = handle.path().to_path_buf();
-        save_tab_to_path(tabs, idx, status, path).await;
+    if let Some(path) = pick_save_path(&v[idx]).await {
+        save_tab_to_path(tabs, idx, status, path, overlay).await;
     }
 }
 
-async fn save_as_active(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, status: Signal<String>) {
+async fn save_as_active(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    status: Signal<String>,
+    overlay: Signal<Overlay>,
+) {
     let idx = active_tab();
     let v = tabs();
     if idx >= v.len() {
         return;
     }
 
-    if let Some(handle) = AsyncFileDialog::new().save_file().await {
-        let path = handle.path().to_path_buf();
-        save_tab_to_path(tabs, idx, status, path).await;
+    if let Some(path) = pick_save_path(&v[idx]).await {
+        save_tab_to_path(tabs, idx, status, path, overlay).await;
     }
 }
 
-fn close_tab_immediately(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, idx: usize) {
-    let mut v = tabs();
-    if v.is_empty() {
-        v.push(Tab::new_untitled(1));
-        tabs.set(v);
-        active_tab.set(0);
+fn toggle_markdown_preview(mut md_preview_open: Signal<bool>, is_markdown: bool, mut status: Signal<String>) {
+    if !md_preview_open() && !is_markdown {
+        status.set("Markdown preview is only available for Markdown files".to_string());
         return;
     }
+    md_preview_open.set(!md_preview_open());
+}
 
-    if idx >= v.len() {
-        return;
+/// Escape from outside an open overlay's dialog: cancel it the way its own Escape would. The
+/// recovery offer has no cancel, so it stays.
+fn dismiss_overlay(
+    tabs: Signal<Vec<Tab>>,
+    status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    pending_action: Signal<PendingAction>,
+) {
+    match overlay() {
+        Overlay::None | Overlay::Recover => {}
+        Overlay::Confirm => confirm_cancel(overlay, pending_action),
+        Overlay::Reload(idx) => answer_reload_prompt(tabs, status, overlay, idx, false),
+        _ => overlay.set(Overlay::None),
     }
+}
 
-    v.remove(idx);
-
-    if v.is_empty() {
-        v.push(Tab::new_untitled(1));
-        tabs.set(v);
-        active_tab.set(0);
-        return;
-    }
+fn confirm_cancel(mut overlay: Signal<Overlay>, mut pending_action: Signal<PendingAction>) {
+    overlay.set(Overlay::None);
+    pending_action.set(PendingAction::None);
+}
 
-    // keep active in range
-    let mut a = active_tab();
-    if a >= v.len() {
-        a = v.len() - 1;
+fn confirm_discard(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut overlay: Signal<Overlay>,
+    mut pending_action: Signal<PendingAction>,
+) {
+    let action = pending_action();
+    overlay.set(Overlay::None);
+    pending_action.set(PendingAction::None);
+
+    match action {
+        PendingAction::CloseTab(i) => {
+            // discard changes and close
+            close_tab_immediately(tabs, active_tab, i);
+        }
+        PendingAction::CloseTabs(ids) => remove_tabs(tabs, active_tab, &ids),
+        PendingAction::ExitApp => {
+            // Discarded on purpose, so there's nothing to offer back next time.
+            let _ = recovery::clear();
+            dioxus_desktop::window().close();
+        }
+        PendingAction::None => {}
     }
+}
 
-    tabs.set(v);
+fn confirm_save(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    mut pending_action: Signal<PendingAction>,
+) {
+    let action = pending_action();
+    overlay.set(Overlay::None);
+
+    spawn(async move {
+        // A file that changed on disk stops everything at the overwrite modal, which picks
+        // the action up again once it's answered.
+        let mut waiting = false;
+        match action.clone() {
+            PendingAction::CloseTab(i) => {
+                // Save that tab index (not necessarily active)
+                // If user cancels save dialog, nothing happens.
+                let v = tabs();
+                if i < v.len() {
+                    if let Some(p) = v[i].path.clone() {
+                        waiting = !save_tab_to_path(tabs, i, status, p, overlay).await;
+                    } else if let Some(path) = pick_save_path(&v[i]).await {
+                        waiting = !save_tab_to_path(tabs, i, status, path, overlay).await;
+                    }
+
+                    // If it saved (dirty cleared), close it.
+                    let v2 = tabs();
+                    if !waiting && i < v2.len() && !v2[i].dirty {
+                        close_tab_immediately(tabs, active_tab, i);
+                    }
+                }
+            }
+            PendingAction::CloseTabs(ids) => {
+                // Save each in turn; cancelling a save dialog stops there. Whatever
+                // saved closes, the rest stay open.
+                for &id in &ids {
+                    let v = tabs();
+                    let Some(i) = v.iter().position(|t| t.id == id) else {
+                        continue;
+                    };
+                    if !v[i].dirty {
+                        continue;
+                    }
+                    if let Some(p) = v[i].path.clone() {
+                        waiting = !save_tab_to_path(tabs, i, status, p, overlay).await;
+                    } else if let Some(path) = pick_save_path(&v[i]).await {
+                        waiting = !save_tab_to_path(tabs, i, status, path, overlay).await;
+                    } else {
+                        break;
+                    }
+                    // Closing tabs now would move the one the modal is asking about.
+                    if waiting {
+                        break;
+                    }
+                }
+                if !waiting {
+                    let saved: Vec<u64> =
+                        tabs().iter().filter(|t| ids.contains(&t.id) && !t.dirty).map(|t| t.id).collect();
+                    remove_tabs(tabs, active_tab, &saved);
+                }
+            }
+            PendingAction::ExitApp => {
+                // Save active tab, then exit if clean
+                let idx = active_tab();
+                let v = tabs();
+                if idx < v.len() {
+                    if let Some(p) = v[idx].path.clone() {
+                        waiting = !save_tab_to_path(tabs, idx, status, p, overlay).await;
+                    } else if let Some(path) = pick_save_path(&v[idx]).await {
+                        waiting = !save_tab_to_path(tabs, idx, status, path, overlay).await;
+                    }
+
+                    if !waiting && active_tab() < tabs().len() && !tabs()[active_tab()].dirty {
+                        dioxus_desktop::window().close();
+                    }
+                }
+            }
+            PendingAction::None => {}
+        }
+
+        if !waiting {
+            pending_action.set(PendingAction::None);
+        }
+    });
+}
+
+/// Reopen buffers from the recovery folder as unsaved tabs. A file snapshot takes over the
+/// file's tab if it's already open, and keeps the file's encoding and line ending otherwise.
+fn restore_recovered(
+    mut tabs: Signal<Vec<Tab>>,
+    mut active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    snapshots: Vec<recovery::Snapshot>,
+) {
+    let mut v = tabs();
+    // The blank tab every launch starts with would just be in the way.
+    if is_blank_start(&v) {
+        v.clear();
+    }
+
+    let count = snapshots.len();
+    let mut last = 0;
+    for snap in snapshots {
+        let existing = snap.path.as_ref().and_then(|p| find_open_tab_index(&v, p));
+        let idx = match existing {
+            Some(i) if v[i].view == TabView::Text => i,
+            _ => {
+                let id = next_tab_id(&v);
+                let tab = match &snap.path {
+                    Some(p) => match tab_from_path(id, p) {
+                        Ok(t) if t.view == TabView::Text => t,
+                        _ => Tab {
+                            path: Some(p.clone()),
+                            language: crate::syntax::detect_language_from_path(p),
+                            ..Tab::new_untitled(id)
+                        },
+                    },
+                    None => Tab::new_untitled(id),
+                };
+                v.push(tab);
+                v.len() - 1
+            }
+        };
+        // As an edit over the file's text, so the line ending stays and undoing back past it
+        // still counts as unsaved work.
+        v[idx].editor.buffer.replace_all(&snap.text);
+        v[idx].dirty = true;
+        last = idx;
+    }
+
+    tabs.set(v);
+    active_tab.set(last);
+    status.set(format!("Recovered {count} unsaved buffer(s)"));
+}
+
+fn close_tab_immediately(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, idx: usize) {
+    let mut v = tabs();
+    if v.is_empty() {
+        v.push(Tab::new_untitled(1));
+        tabs.set(v);
+        active_tab.set(0);
+        return;
+    }
+
+    if idx >= v.len() {
+        return;
+    }
+
+    let closed = v.remove(idx);
+    crate::syntax::forget_document(closed.id);
+    emit(EditorEvent::TabClosed { tab: closed.id, path: closed.path });
+
+    if v.is_empty() {
+        v.push(Tab::new_untitled(1));
+        tabs.set(v);
+        active_tab.set(0);
+        return;
+    }
+
+    // keep active in range
+    let mut a = active_tab();
+    if a >= v.len() {
+        a = v.len() - 1;
+    }
+
+    tabs.set(v);
+    active_tab.set(a);
+}
+
+/// Pass an event on to the embedding app's `EventSink`, if it provided one as context.
+fn emit(event: EditorEvent) {
+    if let Some(sink) = try_consume_context::<EventSink>() {
+        sink.emit(event);
+    }
+}
+
+/// Remove the tabs with these ids. The active tab stays active if it's kept; otherwise the
+/// next kept tab after it (or the last one) takes over.
+fn remove_tabs(mut tabs: Signal<Vec<Tab>>, mut active_tab: Signal<usize>, ids: &[u64]) {
+    if ids.is_empty() {
+        return;
+    }
+    let mut v = tabs();
+    // Kept tabs before the active one, which is where it (or the tab after it) ends up.
+    let before = v.iter().take(active_tab()).filter(|t| !ids.contains(&t.id)).count();
+    for t in v.iter().filter(|t| ids.contains(&t.id)) {
+        crate::syntax::forget_document(t.id);
+        emit(EditorEvent::TabClosed { tab: t.id, path: t.path.clone() });
+    }
+    v.retain(|t| !ids.contains(&t.id));
+    if v.is_empty() {
+        v.push(Tab::new_untitled(1));
+    }
+    let a = before.min(v.len() - 1);
+    tabs.set(v);
     active_tab.set(a);
 }
 
+/// Close a batch of tabs by id. Those that can go without losing work close right away, and
+/// one prompt covers the rest.
+fn close_tabs(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    mut status: Signal<String>,
+    mut overlay: Signal<Overlay>,
+    mut pending_action: Signal<PendingAction>,
+    ids: Vec<u64>,
+) {
+    if ids.is_empty() {
+        status.set("No tabs to close".to_string());
+        return;
+    }
+    let v = tabs();
+    // A dirty file only needs asking about when the batch closes every view of it.
+    let unsaved: Vec<u64> = (0..v.len())
+        .filter(|&i| ids.contains(&v[i].id) && v[i].dirty)
+        .filter(|&i| other_views(&v, i).iter().all(|&j| ids.contains(&v[j].id)))
+        .map(|i| v[i].id)
+        .collect();
+    let closing: Vec<u64> = ids.iter().copied().filter(|id| !unsaved.contains(id)).collect();
+    remove_tabs(tabs, active_tab, &closing);
+
+    if unsaved.is_empty() {
+        status.set(format!("Closed {} tab(s)", closing.len()));
+    } else {
+        pending_action.set(PendingAction::CloseTabs(unsaved));
+        overlay.set(Overlay::Confirm);
+    }
+}
+
 pub fn app() -> Element {
     let css = bundled_css();
 
@@ -974,26 +3631,100 @@ pub fn app() -> Element {
     let mut active_tab = use_signal(|| 0usize);
 
     // UI
+    let settings = use_signal(settings::load);
+    // The user syntax folder has to be in place before anything compiles its syntax.
+    use_hook(|| {
+        let _ = crate::syntax::set_user_dir(settings.peek().syntax_dir.clone());
+        crate::syntax::set_generic_highlighting(settings.peek().generic_highlighting);
+        WARM_LANGUAGES.iter().for_each(|l| crate::syntax::warm(l));
+    });
     let mut file_open = use_signal(|| false);
-    let status = use_signal(|| "".to_string());
+    let mut view_open = use_signal(|| false);
+    let mut edit_open = use_signal(|| false);
+    let mut encoding_menu_open = use_signal(|| false);
+    let mut unsaved_menu_open = use_signal(|| false);
+    let mut tab_list_open = use_signal(|| false);
+    // The tab bar's right-click menu, and where it was opened (client px).
+    let mut tab_menu_open = use_signal(|| false);
+    let mut tab_menu_at = use_signal(|| (0.0, 0.0));
+    // Close whichever dropdowns are open; false if none were.
+    let close_dropdowns = move || {
+        let mut any = false;
+        for mut open in [file_open, view_open, edit_open, encoding_menu_open, unsaved_menu_open, tab_list_open, tab_menu_open] {
+            if *open.peek() {
+                open.set(false);
+                any = true;
+            }
+        }
+        any
+    };
+    // Counterpart file Open Alternate File offers to create.
+    let alternate_offer = use_signal(|| Option::<PathBuf>::None);
+    // Rename in File: the name being replaced and what the user has typed so far.
+    let rename = use_signal(|| (String::new(), String::new()));
+    // Tabs (by id) whose mixed-indentation warning was dismissed.
+    let mut indent_warning_dismissed = use_signal(Vec::<u64>::new);
+    let mut status = use_signal(|| "".to_string());
 
     // Sidebar (directory)
-    let current_dir = use_signal(|| Option::<PathBuf>::None);
-    let dir_contents = use_signal(|| Vec::<(String, PathBuf)>::new());
-    let mut sidebar_collapsed = use_signal(|| false);
-    let mut sidebar_width = use_signal(|| 280.0f64);
+    let mut roots = use_signal(|| restore_workspace_roots(settings::load().sidebar_sort));
+    // Files Ctrl+clicked in the sidebar for Compare Selected, at most two.
+    let mut compare_picks = use_signal(Vec::<PathBuf>::new);
+    // The sidebar entry Reveal in Sidebar last marked.
+    let revealed = use_signal(|| None::<PathBuf>);
+    let saved_layout = use_hook(session::load);
+    let mut sidebar_collapsed = use_signal(|| saved_layout.sidebar_collapsed);
+    let mut sidebar_width = use_signal(|| {
+        let w = saved_layout.sidebar_width.unwrap_or(SIDEBAR_DEFAULT_W);
+        w.clamp(SIDEBAR_MIN_W, SIDEBAR_MAX_W)
+    });
     let mut sidebar_resizing = use_signal(|| false);
     let mut sidebar_resize_start_x = use_signal(|| 0.0f64);
-    let mut sidebar_resize_start_w = use_signal(|| 280.0f64);
+    let mut sidebar_resize_start_w = use_signal(|| SIDEBAR_DEFAULT_W);
+
+    // Markdown preview
+    let md_preview_open = use_signal(|| false);
+    let mut md_html = use_signal(String::new);
+    let mut md_generation = use_signal(|| 0u64);
+
+    // Status bar word count: the selection's when there is one (the bool), else the file's.
+    let mut text_stats = use_signal(|| None::<(TextStats, bool)>);
+    let mut stats_generation = use_signal(|| 0u64);
 
-    // Confirm modal
-    let mut confirm_open = use_signal(|| false);
+    // Whatever owns keyboard input instead of the editor (confirm modal, ...)
+    let mut overlay = use_signal(|| Overlay::None);
     let mut pending_action = use_signal(|| PendingAction::None);
+    // The confirm dialog is listing the tab's changes against its file.
+    let mut confirm_show_changes = use_signal(|| false);
+    use_effect(move || {
+        if overlay() != Overlay::Confirm {
+            confirm_show_changes.set(false);
+        }
+    });
+
+    // Unsaved buffers from a run that didn't exit cleanly, until restored or discarded.
+    let mut recovered = use_signal(recovery::load);
 
-    // for smooth scrolling (currently not used heavily, but kept)
+    // Live scroll of #scrollpane. Each tab keeps its own copy in editor.scroll_x/scroll_y,
+    // swapped in and out when the active tab changes.
     let mut scroll_top = use_signal(|| 0.0f64);
     let mut scroll_left = use_signal(|| 0.0f64);
     let mut viewport_h = use_signal(|| 600.0f64);
+    let mut shown_tab_id = use_signal(|| Option::<u64>::None);
+
+    // Bumped on every edit/move so the caret blink restarts solid.
+    let mut caret_epoch = use_signal(|| 0u64);
+    // Tracks focus on #scrollpane; the caret dims (and stops blinking) without it.
+    let mut editor_focused = use_signal(|| false);
+    // Keystroke macro: editor keys (with Shift state) captured between Ctrl+Shift+R presses.
+    let macro_recording = use_signal(|| false);
+    let mut macro_keys = use_signal(Vec::<(Key, bool)>::new);
+    let mut vim = use_signal(Vim::default);
+    // Insert key: typing replaces the character under the caret.
+    let mut overwrite = use_signal(|| false);
+
+    // True while an Alt+drag column selection is in progress.
+    let mut block_dragging = use_signal(|| false);
 
     // derived
     let active_idx = active_tab();
@@ -1012,6 +3743,58 @@ pub fn app() -> Element {
         .map(|t| t.dirty)
         .unwrap_or(false);
 
+    let active_view = tabs()
+        .get(active_idx)
+        .map(|t| t.view)
+        .unwrap_or(TabView::Text);
+
+    let (active_line_ending, active_encoding, active_final_newline, active_indent) = tabs()
+        .get(active_idx)
+        .map(|t| (t.line_ending(), t.encoding, t.final_newline, t.indent))
+        .unwrap_or_default();
+
+    // Tooltip for the language on the status bar: the tab's whole format at a glance.
+    let active_summary = tabs()
+        .get(active_idx)
+        .map(Tab::format_summary)
+        .unwrap_or_default();
+
+    let active_image_info = tabs()
+        .get(active_idx)
+        .filter(|t| t.view == TabView::Image)
+        .map(image_info);
+
+    // The tab the confirm dialog is about, when it has a file to compare its changes with.
+    let confirm_tab = match pending_action() {
+        PendingAction::CloseTab(i) => Some(i),
+        PendingAction::ExitApp => Some(active_idx),
+        _ => None,
+    }
+    .filter(|&i| tabs().get(i).is_some_and(|t| t.path.is_some()));
+
+    let active_diff_summary = tabs()
+        .get(active_idx)
+        .and_then(|t| t.diff.as_ref().map(|d| d.summary));
+
+    // Tabs with unsaved changes, as (index, title). Extra views of a file aren't counted again.
+    let unsaved_tabs: Vec<(usize, String)> = {
+        let v = tabs();
+        v.iter()
+            .enumerate()
+            .filter(|(i, t)| t.dirty && !(t.path.is_some() && v[..*i].iter().any(|o| o.path == t.path)))
+            .map(|(i, t)| (i, t.title()))
+            .collect()
+    };
+
+    let active_indent_mix = tabs()
+        .get(active_idx)
+        .filter(|t| t.view == TabView::Text && !indent_warning_dismissed().contains(&t.id))
+        .map(|t| (t.id, transform::indent_mix(t.editor.buffer.lines(), INDENT_SAMPLE_LINES)))
+        .filter(|(_, mix)| mix.is_mixed());
+
+    let active_is_markdown = active_language == "markdown";
+    let md_preview_visible = md_preview_open() && active_is_markdown;
+
     let _active_path = tabs()
         .get(active_idx)
         .and_then(|t| t.path.clone());
@@ -1046,8 +3829,361 @@ pub fn app() -> Element {
     });
 
 
+    // Tab switch: stash the outgoing tab's scroll and restore the incoming one's.
+    use_effect(move || {
+        let idx = active_tab();
+        let incoming = tabs().get(idx).map(|t| (t.id, t.editor.scroll_x, t.editor.scroll_y));
+        let prev = *shown_tab_id.peek();
+        if prev == incoming.map(|(id, _, _)| id) {
+            return;
+        }
+
+        if let Some(prev_id) = prev {
+            let (x, y) = (*scroll_left.peek(), *scroll_top.peek());
+            let mut tabs = tabs;
+            if let Some(t) = tabs.write().iter_mut().find(|t| t.id == prev_id) {
+                t.editor.scroll_x = x;
+                t.editor.scroll_y = y;
+            }
+        }
+
+        shown_tab_id.set(incoming.map(|(id, _, _)| id));
+        if let Some((_, x, y)) = incoming {
+            // Set the signals first so the virtualized window renders the right lines
+            // before the DOM scroll lands.
+            scroll_left.set(x);
+            scroll_top.set(y);
+            let script = restore_scroll_script(x, y);
+            spawn(async move {
+                if let Ok(h) = document::eval(&script).join::<f64>().await
+                    && h > 1.0
+                {
+                    viewport_h.set(h);
+                }
+            });
+        }
+    });
+
+    // Wheel speed / smooth scrolling, kept in step with the settings.
+    let scroll_eval = use_hook(|| document::eval(SCROLL_SETUP_SCRIPT));
+    use_effect(move || {
+        let s = settings();
+        let _ = scroll_eval.send((s.scroll_speed, s.smooth_scroll));
+    });
+
+    // Wheel over the tab strip.
+    use_hook(|| document::eval(TAB_WHEEL_SCRIPT));
+
+    // Keep the caret on screen after every edit or move (everything that bumps caret_epoch).
+    use_effect(move || {
+        caret_epoch();
+        let margin = REVEAL_MARGIN_COLS as f64 * *CHAR_PX.peek();
+        document::eval(&reveal_caret_script(margin));
+    });
+
+    // Report cursor moves in the active text tab to the embedder (see `emit`). The memo only
+    // changes when the position does, so edits that leave the cursor put stay quiet.
+    let cursor_at = use_memo(move || {
+        let v = tabs();
+        let t = v.get(active_tab()).filter(|t| t.view == TabView::Text)?;
+        let pos = t.editor.buffer.cursor();
+        Some((t.id, pos.line, t.editor.buffer.char_col(pos)))
+    });
+    use_effect(move || {
+        if let Some((tab, line, col)) = cursor_at() {
+            emit(EditorEvent::CursorMoved { tab, line, col });
+        }
+    });
+
+    // Marks for the overview strip beside a text tab: lines changed since the last save, and
+    // lines holding the selected text. Worked out again only when the text, the save state or
+    // the selection changes, not on every render.
+    let overview_key = use_memo(move || {
+        let v = tabs();
+        let t = v.get(active_tab()).filter(|t| t.view == TabView::Text)?;
+        let b = &t.editor.buffer;
+        let find = b.selected_text().filter(|s| !s.contains('\n') && !s.trim().is_empty());
+        Some((t.id, b.revision(), t.dirty, find))
+    });
+    let text_overview = use_memo(move || {
+        let (_, _, dirty, find) = overview_key()?;
+        let v = tabs.peek();
+        let b = &v.get(active_tab())?.editor.buffer;
+        let last = b.line_count() - 1;
+        let mut lines: Vec<(usize, &'static str)> = if dirty {
+            diff::line_changes(b.saved_lines(), b.lines())
+                .into_iter()
+                .map(|(line, kind)| (line.min(last), kind.class()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if let Some(find) = find {
+            lines.extend(b.lines().iter().enumerate().filter(|(_, l)| l.contains(&find)).map(|(i, _)| (i, "match")));
+        }
+        lines.sort_by_key(|&(line, _)| line);
+        Some((b.line_count(), overview_marks(lines.into_iter())))
+    });
+
+    // Bring the active tab into view in the tab strip whenever another tab becomes active,
+    // however that happened (click, keyboard, opening or closing tabs).
+    let active_tab_id = use_memo(move || tabs.read().get(active_tab()).map(|t| t.id));
+    use_effect(move || {
+        if let Some(id) = active_tab_id() {
+            document::eval(&tab_reveal_script(id));
+        }
+    });
+
+    // With sync on, the sidebar follows the active tab the same way.
+    let sync_sidebar = use_memo(move || settings().sync_sidebar);
+    use_effect(move || {
+        if active_tab_id().is_some() && sync_sidebar() {
+            reveal_in_sidebar(tabs, active_tab, roots, revealed, settings.peek().sidebar_sort, status, true);
+        }
+    });
+
+    // Move focus into the confirm modal whenever it opens, and back to the editor after.
+    use_effect(move || {
+        if overlay() != Overlay::None {
+            document::eval(MODAL_FOCUS_SCRIPT);
+        } else {
+            document::eval(EDITOR_FOCUS_SCRIPT);
+        }
+    });
+
+    // Snapshot unsaved buffers for crash recovery. Only rewrites when something changed, and
+    // leaves the folder alone while last run's snapshots are still waiting on the prompt.
+    use_hook(move || {
+        if !recovered.peek().is_empty() {
+            overlay.set(Overlay::Recover);
+        }
+        spawn(async move {
+            let mut last: Option<Vec<(u64, u64)>> = None;
+            loop {
+                tokio::time::sleep(Duration::from_secs(RECOVERY_INTERVAL_SECS)).await;
+                if !recovered.peek().is_empty() {
+                    continue;
+                }
+                let v = tabs.peek();
+                // Second views of a file hold the same text, so one snapshot each.
+                let dirty: Vec<&Tab> = v
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, t)| t.dirty && t.view == TabView::Text && !other_views(&v, i).iter().any(|&o| o < i))
+                    .map(|(_, t)| t)
+                    .collect();
+                let key: Vec<(u64, u64)> = dirty.iter().map(|t| (t.id, t.editor.buffer.revision())).collect();
+                if last.as_ref() == Some(&key) {
+                    continue;
+                }
+                let snapshots: Vec<recovery::Snapshot> = dirty
+                    .iter()
+                    .map(|t| recovery::Snapshot { path: t.path.clone(), text: t.editor.buffer.text() })
+                    .collect();
+                drop(v);
+                if recovery::save(&snapshots).is_ok() {
+                    last = Some(key);
+                }
+            }
+        });
+    });
+
+    // Reopen the tabs the sidebar folders had open last time, ahead of the files from the
+    // command line so those end up active. Folders added later bring theirs back as they come.
+    let mut known_roots = use_signal(|| roots.peek().iter().map(|r| r.path.clone()).collect::<Vec<PathBuf>>());
+    use_hook(move || {
+        for root in known_roots.peek().iter() {
+            restore_folder_tabs(tabs, active_tab, status, root);
+        }
+    });
+    let root_paths = use_memo(move || roots.read().iter().map(|r| r.path.clone()).collect::<Vec<PathBuf>>());
+    use_effect(move || {
+        let now = root_paths();
+        let added: Vec<PathBuf> = now.iter().filter(|p| !known_roots.peek().contains(p)).cloned().collect();
+        known_roots.set(now);
+        for root in &added {
+            restore_folder_tabs(tabs, active_tab, status, root);
+        }
+    });
+
+    // Remember every sidebar folder's tabs for next time. Checked every few seconds and only
+    // written when something changed, cursor moves included.
+    use_hook(move || {
+        spawn(async move {
+            let mut last: Option<Vec<session::FolderTabs>> = None;
+            loop {
+                tokio::time::sleep(Duration::from_secs(FOLDER_TABS_INTERVAL_SECS)).await;
+                let sets = folder_tabs(&tabs.peek(), *active_tab.peek(), &known_roots.peek());
+                if last.as_ref() == Some(&sets) {
+                    continue;
+                }
+                if save_folder_tabs(&sets).is_ok() {
+                    last = Some(sets);
+                }
+            }
+        });
+    });
+
+    // Open anything passed on the command line, jumping to file:line[:col] when given.
+    use_hook(move || {
+        let startup = try_consume_context::<cli::StartupFiles>().unwrap_or_default();
+        spawn(async move {
+            for req in startup.0 {
+                open_path_in_tab(tabs, active_tab, status, req.path).await;
+                if let Some(line) = req.line {
+                    let h = *viewport_h.peek();
+                    goto_line_col(tabs, active_tab, scroll_top, shown_tab_id, h, line, req.col.unwrap_or(1));
+                }
+            }
+        });
+    });
+
+    // Apply the font settings, then measure char_px() again and check the font is usable.
+    let font = use_memo(move || (settings().font_family.clone(), settings().font_size));
+    use_effect(move || {
+        let (family, size) = font();
+        let size = size.clamp(FONT_PX_RANGE.0, FONT_PX_RANGE.1);
+        if *FONT_PX.peek() != size {
+            *FONT_PX.write() = size;
+        }
+        document::eval(FONT_CHANGED_SCRIPT);
+        if let Some(family) = family {
+            check_font(status, family);
+        }
+    });
+
+    // The default indent from the settings, for tabs opened from now on and for open tabs
+    // that had nothing better to go by.
+    let default_indent = use_memo(move || (settings().indent_with_tabs, settings().indent_size.max(1)));
+    use_effect(move || {
+        let (with_tabs, size) = default_indent();
+        let default = Indent { tabs: with_tabs, width: size, ..Indent::default() };
+        if *DEFAULT_INDENT.peek() == default {
+            return;
+        }
+        *DEFAULT_INDENT.write() = default;
+        let mut tabs = tabs;
+        let mut v = tabs.write();
+        for t in v.iter_mut() {
+            if t.indent.tabs_from == IndentSource::Settings {
+                t.indent.tabs = with_tabs;
+            }
+            if t.indent.width_from == IndentSource::Settings {
+                t.indent.width = size;
+            }
+        }
+    });
+
+    // Keep char_px() in line with the font the webview actually renders.
+    use_hook(move || {
+        spawn(async move {
+            let mut eval = document::eval(CHAR_MEASURE_SCRIPT);
+            while let Ok(w) = eval.recv::<f64>().await {
+                if w > 0.0 && (w - *CHAR_PX.peek()).abs() > 0.001 {
+                    *CHAR_PX.write() = w;
+                }
+            }
+        });
+    });
+
+    // Check for files changed elsewhere whenever the window comes back into focus.
+    use_hook(move || {
+        spawn(async move {
+            let mut eval = document::eval(WINDOW_FOCUS_SCRIPT);
+            while eval.recv::<bool>().await.is_ok() {
+                if settings.peek().reload_on_focus {
+                    reload_changed_tabs(tabs, status, overlay);
+                }
+            }
+        });
+    });
+
+    // Files sent over by later launches when single_instance is on.
+    use_hook(move || {
+        let Some(mut rx) = try_consume_context::<instance::Incoming>().and_then(|i| i.take()) else {
+            return;
+        };
+        let window = dioxus::desktop::window();
+        spawn(async move {
+            while let Some(req) = rx.recv().await {
+                window.set_minimized(false);
+                window.set_focus();
+                if req.path.as_os_str().is_empty() {
+                    continue;
+                }
+                open_path_in_tab(tabs, active_tab, status, req.path).await;
+                if let Some(line) = req.line {
+                    let h = *viewport_h.peek();
+                    goto_line_col(tabs, active_tab, scroll_top, shown_tab_id, h, line, req.col.unwrap_or(1));
+                }
+            }
+        });
+    });
+
+    // Keep the sidebar layout for next time, once a resize drag is over.
+    use_effect(move || {
+        let (width, collapsed) = (sidebar_width(), sidebar_collapsed());
+        if !sidebar_resizing() {
+            save_sidebar_layout(width, collapsed, status);
+        }
+    });
+
+    // Re-render the Markdown preview once typing settles.
+    use_effect(move || {
+        if !md_preview_open() {
+            return;
+        }
+        let v = tabs();
+        let Some(t) = v.get(active_tab()) else { return };
+        if t.language != "markdown" {
+            return;
+        }
+
+        let text = t.editor.buffer.text();
+        let generation = *md_generation.peek() + 1;
+        md_generation.set(generation);
+
+        spawn(async move {
+            tokio::time::sleep(Duration::from_millis(MD_PREVIEW_DEBOUNCE_MS)).await;
+            if *md_generation.peek() == generation {
+                md_html.set(markdown::render_html(&text));
+            }
+        });
+    });
+
+    // Recount words for the status bar once typing or selecting settles.
+    use_effect(move || {
+        let on = settings().word_count;
+        tabs();
+        active_tab();
+        let generation = *stats_generation.peek() + 1;
+        stats_generation.set(generation);
+        if !on {
+            if text_stats.peek().is_some() {
+                text_stats.set(None);
+            }
+            return;
+        }
+
+        spawn(async move {
+            tokio::time::sleep(Duration::from_millis(WORD_COUNT_DEBOUNCE_MS)).await;
+            if *stats_generation.peek() != generation {
+                return;
+            }
+            let counted = tabs.peek().get(*active_tab.peek()).map(|t| {
+                let b = &t.editor.buffer;
+                match b.selected_text() {
+                    Some(sel) => (TextStats::of(&sel), true),
+                    None => (TextStats::of(&b.text()), false),
+                }
+            });
+            text_stats.set(counted);
+        });
+    });
+
     rsx! {
         style { "{css}" }
+        style { "{font_css(settings().font_family.as_deref())}" }
 
         // Focus the editor container so hotkeys work without clicking the text area first.
         script { "{FOCUS_SCRIPT}" }
@@ -1055,20 +4191,53 @@ pub fn app() -> Element {
         div {
             class: "app",
 
-            // click anywhere closes file dropdown
-            onclick: move |_| {
-                if file_open() {
-                    file_open.set(false);
+            // F6 cycles focus between regions from anywhere outside the editor (which handles it
+            // itself, like Escape). Escape here closes the overlay, else any open dropdown.
+            onkeydown: move |e| {
+                let key = e.data().key();
+                if key == Key::F6 && overlay() == Overlay::None {
+                    document::eval(&region_focus_script(e.data().modifiers().shift()));
+                    e.prevent_default();
+                } else if key == Key::Escape {
+                    if overlay() != Overlay::None {
+                        dismiss_overlay(tabs, status, overlay, pending_action);
+                    } else {
+                        close_dropdowns();
+                    }
                 }
             },
 
+            // click anywhere closes the dropdowns
+            onclick: move |_| {
+                close_dropdowns();
+            },
+
             // ===== Menu bar =====
             div { class: "menubar",
+                role: "menubar",
+                // Arrows move between menus and items; Escape closes the menu and goes back to the editor.
+                onkeydown: move |e| {
+                    let key = e.data().key();
+                    if key == Key::Escape {
+                        file_open.set(false);
+                        edit_open.set(false);
+                        view_open.set(false);
+                        document::eval(EDITOR_FOCUS_SCRIPT);
+                        e.prevent_default();
+                    } else if let Some(script) = menu_nav_script(&key, true) {
+                        document::eval(&script);
+                        e.prevent_default();
+                    }
+                },
                 div { class: "menu",
                     button {
                         class: "menu-button",
+                        aria_haspopup: "menu",
+                        aria_expanded: "{file_open()}",
                         onclick: move |e| {
                             e.stop_propagation();
+                            view_open.set(false);
+                            edit_open.set(false);
                             file_open.set(!file_open());
                         },
                         "File"
@@ -1077,11 +4246,13 @@ pub fn app() -> Element {
                     if file_open() {
                         div {
                             class: "dropdown",
+                            role: "menu",
                             onclick: move |e| e.stop_propagation(),
 
                             // New tab
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
                                     create_new_tab(tabs.clone(), active_tab.clone(), status.clone());
@@ -1089,28 +4260,114 @@ pub fn app() -> Element {
                                 "New Tab - Ctrl+N"
                             }
 
+                            for language in templates::languages() {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        file_open.set(false);
+                                        create_tab_from_template(tabs, active_tab, status, &language);
+                                    },
+                                    "New from Template: {language}"
+                                }
+                            }
+
                             // Open file...
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
                                     let tabs2 = tabs.clone();
                                     let act2 = active_tab.clone();
                                     let status2 = status.clone();
-                                    spawn(async move { open_dialog_add_tab(tabs2, act2, status2).await; });
+                                    spawn(async move { open_dialog_add_tab(tabs2, act2, status2, false).await; });
                                 },
                                 "Open - Ctrl+O"
                             }
 
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    spawn(async move { open_dialog_add_tab(tabs, active_tab, status, true).await; });
+                                },
+                                "Open as Read-Only..."
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    open_alternate_file(tabs, active_tab, status, overlay, alternate_offer, &settings().alternate_files);
+                                },
+                                "Open Alternate File - Alt+O"
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    overlay.set(Overlay::ReopenEncoding);
+                                },
+                                "Reopen with Encoding..."
+                            }
+
+                            // Second view of the same file
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    open_second_view(tabs, active_tab, status);
+                                },
+                                "New View of File - Ctrl+\\"
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    duplicate_tab(tabs, active_tab, status);
+                                },
+                                "Duplicate Tab"
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    toggle_read_only(tabs, active_tab, status);
+                                },
+                                if tabs().get(active_tab()).is_some_and(|t| t.read_only) { "Make Editable" } else { "Make Read-Only" }
+                            }
+
+                            // Diff the active tab against another file
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    spawn(async move { compare_active_with(tabs, active_tab, status).await; });
+                                },
+                                "Compare With File..."
+                            }
+
                             // Save
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
                                     let tabs2 = tabs.clone();
                                     let act2 = active_tab.clone();
                                     let status2 = status.clone();
-                                    spawn(async move { save_active_or_save_as(tabs2, act2, status2).await; });
+                                    spawn(async move { save_active_or_save_as(tabs2, act2, status2, overlay).await; });
                                 },
                                 "Save - Ctrl+S"
                             }
@@ -1118,39 +4375,68 @@ pub fn app() -> Element {
                             // Save As
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
                                     let tabs2 = tabs.clone();
                                     let act2 = active_tab.clone();
                                     let status2 = status.clone();
-                                    spawn(async move { save_as_active(tabs2, act2, status2).await; });
+                                    spawn(async move { save_as_active(tabs2, act2, status2, overlay).await; });
                                 },
                                 "Save As - Ctrl+Shift+S"
                             }
 
                             div { class: "menu-sep" }
 
+                            // Close every tab left of the active one
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    let ids = tabs().iter().take(active_tab()).map(|t| t.id).collect();
+                                    close_tabs(tabs, active_tab, status, overlay, pending_action, ids);
+                                },
+                                "Close All to the Left"
+                            }
+
+                            // Close every tab that was never saved to a file
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    file_open.set(false);
+                                    let ids = tabs().iter().filter(|t| t.path.is_none() && t.view == TabView::Text).map(|t| t.id).collect();
+                                    close_tabs(tabs, active_tab, status, overlay, pending_action, ids);
+                                },
+                                "Close All Untitled"
+                            }
+
+                            div { class: "menu-sep" }
+
                             // Open directory
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
-                                    let current_dir2 = current_dir.clone();
-                                    let dir_contents2 = dir_contents.clone();
+                                    let roots2 = roots;
                                     let status2 = status.clone();
-                                    spawn(async move { open_directory(current_dir2, dir_contents2, status2).await; });
+                                    let sort = settings().sidebar_sort;
+                                    spawn(async move { open_directory(roots2, status2, sort).await; });
                                 },
-                                "Open Directory - Ctrl+Shift+O"
+                                "Add Directory - Ctrl+Shift+O"
                             }
 
                             // Close directory
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
-                                    close_directory(current_dir.clone(), dir_contents.clone(), status.clone());
+                                    close_directory(roots, status);
                                 },
-                                "Close Directory - Ctrl+Shift+C"
+                                "Close All Directories - Ctrl+Shift+C"
                             }
 
                             div { class: "menu-sep" }
@@ -1158,13 +4444,14 @@ pub fn app() -> Element {
                             // Exit
                             button {
                                 class: "menu-item",
+                                role: "menuitem",
                                 onclick: move |_| {
                                     file_open.set(false);
 
                                     // If the active tab is dirty, confirm. (Yes, this is basic. No, it won't babysit every dirty tab.)
                                     if active_dirty {
                                         pending_action.set(PendingAction::ExitApp);
-                                        confirm_open.set(true);
+                                        overlay.set(Overlay::Confirm);
                                         return;
                                     }
 
@@ -1176,194 +4463,1386 @@ pub fn app() -> Element {
                     }
                 }
 
-                div { class: "file-indicator", "{active_title}" }
-                div { class: "file-indicator", "{status()}" }
-
-                if update_available() {
-                    div { class: "update-indicator",
-                        "Update avaliable: v{update_latest()} (you're on v{env!(\"CARGO_PKG_VERSION\")})"
-                    }
-                } else if !update_err().is_empty() {
-                    div { class: "update-indicator warn",
-                        "Update check failed"
+                div { class: "menu",
+                    button {
+                        class: "menu-button",
+                        aria_haspopup: "menu",
+                        aria_expanded: "{edit_open()}",
+                        onclick: move |e| {
+                            e.stop_propagation();
+                            file_open.set(false);
+                            view_open.set(false);
+                            edit_open.set(!edit_open());
+                        },
+                        "Edit"
                     }
-                }
-            }
 
-            // ===== Tabs =====
-            div { class: "tabbar",
-                // plus
-                div {
-                    class: "tab-plus",
-                    onclick: move |_| create_new_tab(tabs.clone(), active_tab.clone(), status.clone()),
-                    "+"
-                }
+                    if edit_open() {
+                        div {
+                            class: "dropdown",
+                            role: "menu",
+                            onclick: move |e| e.stop_propagation(),
 
-                for (idx, tab) in tabs().iter().enumerate() {
-                    div {
-                        class: if idx == active_tab() { "tab active" } else { "tab" },
-                        onclick: {
-                            let idx = idx;
-                            move |_| active_tab.set(idx)
-                        },
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        if t.editor.buffer.undo() {
+                                            t.refresh_dirty();
+                                        }
+                                    });
+                                },
+                                "Undo - Ctrl+Z"
+                            }
 
-                        span { class: "tab-title", "{tab.title()}" }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        if t.editor.buffer.redo() {
+                                            t.refresh_dirty();
+                                        }
+                                    });
+                                },
+                                "Redo - Ctrl+Y"
+                            }
 
-                        button {
-                            class: "tab-close",
-                            onclick: {
-                                let idx = idx;
-                                let tabs2 = tabs.clone();
-                                let act2 = active_tab.clone();
-                                let mut confirm2 = confirm_open.clone();
-                                let mut pending2 = pending_action.clone();
-                                move |e| {
-                                    e.stop_propagation();
-                                    let v = tabs2();
-                                    if idx >= v.len() {
-                                        return;
-                                    }
-                                    if v[idx].dirty {
-                                        pending2.set(PendingAction::CloseTab(idx));
-                                        confirm2.set(true);
-                                    } else {
-                                        close_tab_immediately(tabs2.clone(), act2.clone(), idx);
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    if tabs().get(active_tab()).is_some_and(|t| t.view == TabView::Text) {
+                                        overlay.set(Overlay::History);
                                     }
-                                }
-                            },
-                            "×"
-                        }
-                    }
-                }
-            }
-
-            // ===== Editor =====
-            div {
-                class: "editor-wrap",
-
-                // Sidebar resize drag handling
-                onmousemove: move |e| {
-                    if !sidebar_resizing() {
-                        return;
-                    }
+                                },
+                                "Undo History..."
+                            }
 
-                    let x = e.data().coordinates().client().x;
-                    let dx = x - sidebar_resize_start_x();
-                    let new_w = (sidebar_resize_start_w() + dx).clamp(180.0, 620.0);
-                    sidebar_width.set(new_w);
-                    e.prevent_default();
-                },
-                onmouseup: move |_| sidebar_resizing.set(false),
-                onmouseleave: move |_| sidebar_resizing.set(false),
-                div {
-                    class: "row",
+                            div { class: "menu-sep" }
 
-                    // Sidebar (left)
-                    if !sidebar_collapsed() {
-                        div {
-                            class: "sidebar-resize",
-                            style: "width: {sidebar_width()}px;",
-                            div { class: "sidebar",
-                                div {
-                                    class: "sidebar-header",
-                                    onclick: move |_| sidebar_collapsed.set(true),
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    start_rename(tabs, active_tab, status, overlay, rename);
+                                },
+                                "Rename in File... - F2"
+                            }
 
-                                    div {
-                                        class: "sidebar-title",
-                                        {
-                                            if let Some(p) = current_dir() {
-                                                rsx!("{p.display()}")
-                                            } else {
-                                                rsx!("No directory")
-                                            }
-                                        }
-                                    }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    insert_date_time(tabs, active_tab, status, &settings().date_format);
+                                    caret_epoch += 1;
+                                },
+                                "Insert Date/Time - F5"
+                            }
 
-                                    button { class: "sidebar-collapse-btn", "×" }
-                                }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    insert_file_path(tabs, active_tab, status);
+                                    caret_epoch += 1;
+                                },
+                                "Insert File Path"
+                            }
 
-                                div { class: "sidebar-contents",
-                                    if current_dir().is_some() {
-                                        for (name, path) in dir_contents().iter() {
-                                            button {
-                                                class: "sidebar-item",
-                                                onclick: {
-                                                    let tabs2 = tabs.clone();
-                                                    let act2 = active_tab.clone();
-                                                    let mut status2 = status.clone();
-                                                    let p = path.clone();
-                                                    let n = name.clone();
-                                                    move |_| {
-                                                        if p.is_dir() {
-                                                            status2.set(format!("Directory: {n}"));
-                                                        } else {
-                                                            let tabs3 = tabs2.clone();
-                                                            let act3 = act2.clone();
-                                                            let status3 = status2.clone();
-                                                            let p2 = p.clone();
-                                                            spawn(async move { open_path_in_tab(tabs3, act3, status3, p2).await; });
-                                                        }
-                                                    }
-                                                },
-                                                if path.is_dir() { "[DIR] " } else { "[FILE] " }
-                                                "{name}"
-                                            }
-                                        }
-                                    } else {
-                                        div { class: "sidebar-empty", "No directory open" }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    if let Some(text) = tabs().get(active_tab()).and_then(|t| t.editor.buffer.selected_text()) {
+                                        copy_to_clipboard(text);
                                     }
-                                }
+                                },
+                                "Copy - Ctrl+C"
                             }
-                        }
 
-                        // Drag handle to resize the sidebar
-                        div {
-                            class: "sidebar-handle",
-                            onmousedown: move |e| {
-                                e.stop_propagation();
-                                sidebar_resizing.set(true);
-                                sidebar_resize_start_x.set(e.data().coordinates().client().x);
-                                sidebar_resize_start_w.set(sidebar_width());
-                                e.prevent_default();
-                            },
-                        }
-                    } else if sidebar_collapsed() && current_dir().is_some() {
-                        div {
-                            class: "sidebar-collapsed",
-                            onclick: move |_| sidebar_collapsed.set(false),
-                            "▶"
-                        }
-                    }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| t.editor.buffer.select_all());
+                                },
+                                "Select All - Ctrl+A"
+                            }
 
-                    // Editor content (gutter + textpane)
-                    div {
-                        class: "scroll",
-                        tabindex: "0",
-                        autofocus: "true",
-                        id: "scrollpane",
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        t.editor.buffer.jump_to_matching_bracket();
+                                    });
+                                    caret_epoch += 1;
+                                },
+                                "Go to Matching Bracket - Ctrl+M"
+                            }
 
-                        onscroll: move |e| {
-                            let d = e.data();
-                            let new_top = d.scroll_top() as f64;
-                            if (new_top - scroll_top()).abs() > 0.5 {
-                                scroll_top.set(new_top);
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        t.editor.buffer.expand_selection();
+                                    });
+                                },
+                                "Expand Selection - Ctrl+Shift+Up"
                             }
-                            let new_left = d.scroll_left() as f64;
-                            if (new_left - scroll_left()).abs() > 0.5 {
-                                scroll_left.set(new_left);
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        t.editor.buffer.shrink_selection();
+                                    });
+                                },
+                                "Shrink Selection - Ctrl+Shift+Down"
                             }
-                            let new_h = d.client_height() as f64;
-                            if new_h >1.0 && (new_h - viewport_h()).abs() > 0.5 {
-                                viewport_h.set(new_h);
+
+                            div { class: "menu-sep" }
+
+                            for (label, f) in [
+                                ("Uppercase - Ctrl+Shift+U", transform::upper as fn(&str) -> String),
+                                ("Lowercase - Ctrl+Shift+L", transform::lower),
+                                ("Toggle Case", transform::toggle_case),
+                                ("Title Case", transform::title_case),
+                            ] {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        edit_open.set(false);
+                                        transform_active(tabs, active_tab, f);
+                                    },
+                                    "{label}"
+                                }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        if t.editor.buffer.join_lines() {
+                                            t.dirty = true;
+                                        }
+                                    });
+                                },
+                                "Join Lines - Ctrl+J"
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        if t.editor.buffer.duplicate_selection() {
+                                            t.dirty = true;
+                                        }
+                                    });
+                                },
+                                "Duplicate Selection - Ctrl+Shift+D"
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        t.editor.buffer.delete_lines();
+                                        t.dirty = true;
+                                    });
+                                },
+                                "Delete Line - Ctrl+Shift+K"
+                            }
+
+                            for (label, f) in [
+                                ("Sort Lines Ascending", transform::sort_lines as fn(Vec<String>) -> Vec<String>),
+                                ("Sort Lines Descending", transform::sort_lines_descending),
+                                ("Sort Lines (Ignore Case)", transform::sort_lines_case_insensitive),
+                                ("Sort Lines Numerically", transform::sort_lines_numeric),
+                                ("Remove Duplicate Lines", transform::dedup_lines),
+                                ("Remove Blank Lines", transform::remove_blank_lines),
+                                ("Collapse Blank Lines", transform::collapse_blank_lines),
+                            ] {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        edit_open.set(false);
+                                        transform_lines_active(tabs, active_tab, f);
+                                    },
+                                    "{label}"
+                                }
+                            }
+
+                            {
+                                // Wraps at the first ruler column.
+                                let width = settings().rulers.first().copied().filter(|&w| w > 0).unwrap_or(80);
+                                rsx! {
+                                    button {
+                                        class: "menu-item",
+                                        role: "menuitem",
+                                        onclick: move |_| {
+                                            edit_open.set(false);
+                                            transform_lines_active(tabs, active_tab, |lines| transform::wrap_paragraphs(lines, width));
+                                        },
+                                        "Wrap Paragraphs at Column {width}"
+                                    }
+                                }
+                            }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    transform_lines_active(tabs, active_tab, transform::join_paragraphs);
+                                },
+                                "Join Paragraphs"
+                            }
+
+                            for (label, to_tabs) in [("Convert Indentation to Spaces", false), ("Convert Indentation to Tabs", true)] {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        edit_open.set(false);
+                                        convert_indentation(tabs, active_tab, status, to_tabs);
+                                    },
+                                    "{label}"
+                                }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // Keystroke macro
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    toggle_macro_recording(macro_recording, macro_keys, status);
+                                },
+                                if macro_recording() { "Stop Recording Macro - Ctrl+Shift+R" } else { "Record Macro - Ctrl+Shift+R" }
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    edit_open.set(false);
+                                    play_macro(tabs, active_tab, macro_keys, macro_recording, overwrite(), status);
+                                },
+                                "Play Macro - Ctrl+Shift+P"
+                            }
+                        }
+                    }
+                }
+
+                div { class: "menu",
+                    button {
+                        class: "menu-button",
+                        aria_haspopup: "menu",
+                        aria_expanded: "{view_open()}",
+                        onclick: move |e| {
+                            e.stop_propagation();
+                            file_open.set(false);
+                            edit_open.set(false);
+                            view_open.set(!view_open());
+                        },
+                        "View"
+                    }
+
+                    if view_open() {
+                        div {
+                            class: "dropdown",
+                            role: "menu",
+                            onclick: move |e| e.stop_propagation(),
+
+                            // Text <-> hex
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    toggle_hex_view(tabs, active_tab, status);
+                                },
+                                if active_view == TabView::Hex { "Show as Text - Ctrl+Shift+H" } else { "Show as Hex - Ctrl+Shift+H" }
+                            }
+
+                            // Markdown preview
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    toggle_markdown_preview(md_preview_open, active_is_markdown, status);
+                                },
+                                if md_preview_open() { "Hide Markdown Preview - Ctrl+Shift+V" } else { "Show Markdown Preview - Ctrl+Shift+V" }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // User syntax folder
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    spawn(async move { choose_syntax_dir(tabs, settings, status).await; });
+                                },
+                                "Set Syntax Folder..."
+                            }
+
+                            if settings().syntax_dir.is_some() {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| s.syntax_dir = None);
+                                        reload_syntax(tabs, status, None);
+                                    },
+                                    "Stop Using Syntax Folder"
+                                }
+                            }
+
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    reload_syntax(tabs, status, settings().syntax_dir);
+                                },
+                                "Reload Syntax Definitions"
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // Sidebar details
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.show_file_details = !s.show_file_details);
+                                },
+                                if settings().show_file_details { "Hide File Details in Sidebar" } else { "Show File Details in Sidebar" }
+                            }
+
+                            // Reveal the active file
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    sidebar_collapsed.set(false);
+                                    reveal_in_sidebar(tabs, active_tab, roots, revealed, settings().sidebar_sort, status, false);
+                                },
+                                "Reveal Active File in Sidebar"
+                            }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.sync_sidebar = !s.sync_sidebar);
+                                },
+                                if settings().sync_sidebar { "● Sync Sidebar with Editor" } else { "Sync Sidebar with Editor" }
+                            }
+
+                            // Caret blink
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.caret_blink = !s.caret_blink);
+                                },
+                                if settings().caret_blink { "Disable Caret Blink" } else { "Enable Caret Blink" }
+                            }
+
+                            // Click past the end of a line
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.virtual_space = !s.virtual_space);
+                                },
+                                if settings().virtual_space { "Clamp Clicks to Line End" } else { "Allow Clicks Past Line End (Virtual Space)" }
+                            }
+
+                            // Indent guides
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.indent_guides = !s.indent_guides);
+                                },
+                                if settings().indent_guides { "Hide Indent Guides" } else { "Show Indent Guides" }
+                            }
+
+                            // Generic highlighting for files without a syntax
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.generic_highlighting = !s.generic_highlighting);
+                                    crate::syntax::set_generic_highlighting(settings.peek().generic_highlighting);
+                                },
+                                if settings().generic_highlighting { "Plain Text: No Highlighting" } else { "Plain Text: Highlight Numbers and Strings" }
+                            }
+
+                            // Reload on focus
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.reload_on_focus = !s.reload_on_focus);
+                                },
+                                if settings().reload_on_focus { "● Reload Changed Files on Focus" } else { "Reload Changed Files on Focus" }
+                            }
+
+                            // Reset layout
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    sidebar_width.set(SIDEBAR_DEFAULT_W);
+                                    sidebar_collapsed.set(false);
+                                },
+                                "Reset Layout"
+                            }
+
+                            // Rulers
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.show_rulers = !s.show_rulers);
+                                },
+                                if settings().show_rulers { "Hide Rulers" } else { "Show Rulers" }
+                            }
+
+                            // Long lines
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.highlight_long_lines = !s.highlight_long_lines);
+                                },
+                                if settings().highlight_long_lines {
+                                    "Stop Highlighting Lines Past {settings().long_line_limit}"
+                                } else {
+                                    "Highlight Lines Past {settings().long_line_limit}"
+                                }
+                            }
+
+                            // Relative line numbers
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.relative_line_numbers = !s.relative_line_numbers);
+                                },
+                                if settings().relative_line_numbers { "Show Absolute Line Numbers" } else { "Show Relative Line Numbers" }
+                            }
+
+                            // Word count
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.word_count = !s.word_count);
+                                },
+                                if settings().word_count { "Hide Word Count" } else { "Show Word Count" }
+                            }
+
+                            // Vim mode
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    vim.set(Vim::default());
+                                    update_settings(settings, status, |s| s.vim_mode = !s.vim_mode);
+                                },
+                                if settings().vim_mode { "Disable Vim Mode" } else { "Enable Vim Mode" }
+                            }
+
+                            // Single instance (read at launch)
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.single_instance = !s.single_instance);
+                                },
+                                if settings().single_instance { "Allow Multiple Windows" } else { "Open Files in One Window (after restart)" }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // Scrolling
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.smooth_scroll = !s.smooth_scroll);
+                                },
+                                if settings().smooth_scroll { "Disable Smooth Scrolling" } else { "Enable Smooth Scrolling" }
+                            }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.scroll_past_end = !s.scroll_past_end);
+                                },
+                                if settings().scroll_past_end { "Disable Scroll Past End" } else { "Enable Scroll Past End" }
+                            }
+                            for speed in SCROLL_SPEEDS {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| s.scroll_speed = speed);
+                                    },
+                                    if settings().scroll_speed == speed { "● Scroll Speed {speed}x" } else { "Scroll Speed {speed}x" }
+                                }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // Editor font
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.font_family = None);
+                                },
+                                if settings().font_family.is_none() { "● Font: JetBrains Mono (bundled)" } else { "Font: JetBrains Mono (bundled)" }
+                            }
+                            for family in FONT_FAMILIES {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| s.font_family = Some(family.to_string()));
+                                    },
+                                    if settings().font_family.as_deref() == Some(family) { "● Font: {family}" } else { "Font: {family}" }
+                                }
+                            }
+                            // A font named in settings.toml that isn't one of the above.
+                            if let Some(family) = settings().font_family.filter(|f| !FONT_FAMILIES.contains(&f.as_str())) {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| view_open.set(false),
+                                    "● Font: {family}"
+                                }
+                            }
+                            for size in FONT_SIZES {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| s.font_size = size);
+                                    },
+                                    if settings().font_size == size { "● Font Size {size}px" } else { "Font Size {size}px" }
+                                }
+                            }
+
+                            div { class: "menu-sep" }
+
+                            // Default indentation, the last word after .editorconfig, the file and the language
+                            for size in INDENT_SIZES {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| {
+                                            s.indent_with_tabs = false;
+                                            s.indent_size = size;
+                                        });
+                                    },
+                                    if !settings().indent_with_tabs && settings().indent_size == size { "● Default Indent: {size} Spaces" } else { "Default Indent: {size} Spaces" }
+                                }
+                            }
+                            button {
+                                class: "menu-item",
+                                role: "menuitem",
+                                onclick: move |_| {
+                                    view_open.set(false);
+                                    update_settings(settings, status, |s| s.indent_with_tabs = true);
+                                },
+                                if settings().indent_with_tabs { "● Default Indent: Tabs" } else { "Default Indent: Tabs" }
+                            }
+                            div { class: "menu-note",
+                                "Used when .editorconfig doesn't say, the file has no indented lines and manifest.toml has no default for the language."
+                            }
+
+                            div { class: "menu-sep" }
+
+                            for mode in SidebarSort::ALL {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        view_open.set(false);
+                                        update_settings(settings, status, |s| s.sidebar_sort = mode);
+                                        for root in roots.write().iter_mut() {
+                                            sort_dir_entries(&mut root.contents, mode);
+                                        }
+                                    },
+                                    if settings().sidebar_sort == mode { "● Sort Sidebar by {mode.label()}" } else { "Sort Sidebar by {mode.label()}" }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { class: "file-indicator", "{active_title}" }
+                div { class: "file-indicator", if tabs().len() == 1 { "1 tab" } else { "{tabs().len()} tabs" } }
+                if !unsaved_tabs.is_empty() {
+                    div { class: "menu",
+                        button {
+                            class: "status-item",
+                            title: "Show the tabs with unsaved changes",
+                            aria_haspopup: "menu",
+                            aria_expanded: "{unsaved_menu_open()}",
+                            onclick: move |e| {
+                                e.stop_propagation();
+                                file_open.set(false);
+                                view_open.set(false);
+                                edit_open.set(false);
+                                unsaved_menu_open.set(!unsaved_menu_open());
+                            },
+                            "{unsaved_tabs.len()} unsaved"
+                        }
+
+                        if unsaved_menu_open() {
+                            div {
+                                class: "dropdown",
+                                role: "menu",
+                                onclick: move |e| e.stop_propagation(),
+                                for (idx, title) in unsaved_tabs.clone() {
+                                    button {
+                                        class: "menu-item",
+                                        role: "menuitem",
+                                        onclick: move |_| {
+                                            unsaved_menu_open.set(false);
+                                            active_tab.set(idx);
+                                        },
+                                        "{title}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                if let Some(info) = active_image_info {
+                    div { class: "file-indicator", "{info}" }
+                }
+                if let Some(s) = active_diff_summary {
+                    div { class: "file-indicator", "+{s.added} -{s.removed} ~{s.changed}" }
+                }
+                div { class: "file-indicator", "{status()}" }
+
+                if update_available() {
+                    div { class: "update-indicator",
+                        "Update avaliable: v{update_latest()} (you're on v{env!(\"CARGO_PKG_VERSION\")})"
+                    }
+                } else if !update_err().is_empty() {
+                    div { class: "update-indicator warn",
+                        "Update check failed"
+                    }
+                }
+
+                // Per-file format, clickable to change
+                if active_view == TabView::Text {
+                    div { class: "status-items",
+                        button {
+                            class: "status-item",
+                            title: "Insert / overwrite (Insert key)",
+                            onclick: move |e| {
+                                e.stop_propagation();
+                                overwrite.toggle();
+                            },
+                            if overwrite() { "OVR" } else { "INS" }
+                        }
+                        if settings().vim_mode {
+                            span { class: "status-item vim-mode", "{vim().status()}" }
+                        }
+                        if tabs().get(active_idx).is_some_and(|t| t.read_only) {
+                            button {
+                                class: "status-item warn",
+                                title: "Edits are blocked in this tab (click to make it editable)",
+                                onclick: move |e| {
+                                    e.stop_propagation();
+                                    toggle_read_only(tabs, active_tab, status);
+                                },
+                                "Read-only"
+                            }
+                        }
+                        if let Some((id, mix)) = active_indent_mix {
+                            button {
+                                class: "status-item warn",
+                                title: {
+                                    let to = if mix.prefers_tabs() { "tabs" } else { "spaces" };
+                                    format!("{} lines indented with tabs, {} with spaces, {} mixing both. Click to convert all to {to}.", mix.tabs, mix.spaces, mix.mixed)
+                                },
+                                onclick: move |e| {
+                                    e.stop_propagation();
+                                    convert_indentation(tabs, active_tab, status, mix.prefers_tabs());
+                                },
+                                "Mixed indentation"
+                            }
+                            button {
+                                class: "status-item",
+                                title: "Dismiss for this tab",
+                                aria_label: "Dismiss mixed indentation warning",
+                                onclick: move |e| {
+                                    e.stop_propagation();
+                                    indent_warning_dismissed.write().push(id);
+                                },
+                                "×"
+                            }
+                        }
+                        if let Some((st, selected)) = text_stats() {
+                            span {
+                                class: "status-item",
+                                title: if selected { "Counts for the selection" } else { "Counts for the whole file" },
+                                if selected { "Selection: " }
+                                "{st.words} words, {st.chars} chars, {st.lines} lines"
+                            }
+                        }
+                        span { class: "status-item", title: "{active_summary}", "{active_language}" }
+                        span {
+                            class: "status-item",
+                            title: "Tabs or spaces from {active_indent.tabs_from.label()}, width from {active_indent.width_from.label()}",
+                            "{active_indent.label()}"
+                        }
+                        button {
+                            class: "status-item",
+                            title: "Toggle LF / CRLF",
+                            onclick: move |e| {
+                                e.stop_propagation();
+                                toggle_line_ending(tabs, active_tab, status);
+                            },
+                            "{active_line_ending.label()}"
+                        }
+                        button {
+                            class: "status-item",
+                            title: "What saving does with the final newline (click to change for this tab)",
+                            onclick: move |e| {
+                                e.stop_propagation();
+                                cycle_final_newline(tabs, active_tab, status);
+                            },
+                            "{active_final_newline.label()}"
+                        }
+
+                        div { class: "menu",
+                            button {
+                                class: "status-item",
+                                title: "Change encoding",
+                                aria_haspopup: "menu",
+                                aria_expanded: "{encoding_menu_open()}",
+                                onclick: move |e| {
+                                    e.stop_propagation();
+                                    file_open.set(false);
+                                    view_open.set(false);
+                                    edit_open.set(false);
+                                    encoding_menu_open.set(!encoding_menu_open());
+                                },
+                                "{active_encoding.label()}"
+                            }
+
+                            if encoding_menu_open() {
+                                div {
+                                    class: "dropdown dropdown-right",
+                                    role: "menu",
+                                    onclick: move |e| e.stop_propagation(),
+                                    onkeydown: move |e| {
+                                        let key = e.data().key();
+                                        if key == Key::Escape {
+                                            encoding_menu_open.set(false);
+                                            document::eval(EDITOR_FOCUS_SCRIPT);
+                                            e.prevent_default();
+                                        } else if let Some(script) = menu_nav_script(&key, false) {
+                                            document::eval(&script);
+                                            e.prevent_default();
+                                        }
+                                    },
+                                    for enc in TextEncoding::ALL {
+                                        button {
+                                            class: "menu-item",
+                                            role: "menuitem",
+                                            onclick: move |_| {
+                                                encoding_menu_open.set(false);
+                                                set_tab_encoding(tabs, active_tab, status, enc);
+                                            },
+                                            if enc == active_encoding { "● {enc.label()}" } else { "{enc.label()}" }
+                                        }
+                                    }
+                                    div { class: "menu-sep" }
+                                    button {
+                                        class: "menu-item",
+                                        role: "menuitem",
+                                        onclick: move |_| {
+                                            encoding_menu_open.set(false);
+                                            overlay.set(Overlay::ReopenEncoding);
+                                        },
+                                        "Reopen with Encoding..."
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Tabs =====
+            div { class: "tabbar",
+                div { class: "tab-strip",
+                    role: "tablist",
+                    aria_label: "Open files",
+                    // plus
+                    div {
+                        class: "tab-plus",
+                        role: "button",
+                        aria_label: "New tab",
+                        onclick: move |_| create_new_tab(tabs.clone(), active_tab.clone(), status.clone()),
+                        "+"
+                    }
+
+                    for (idx, tab) in tabs().iter().enumerate() {
+                        div {
+                            id: "tab-{tab.id}",
+                        class: if idx == active_tab() { "tab active" } else { "tab" },
+                            role: "tab",
+                            aria_selected: "{idx == active_tab()}",
+                            title: "{tab.format_summary()}",
+                            // Only the active tab is in the Tab order; arrows move between tabs.
+                            tabindex: if idx == active_tab() { "0" } else { "-1" },
+                            onclick: {
+                                let idx = idx;
+                                move |_| active_tab.set(idx)
+                            },
+                            // Right-click picks the tab and opens its menu there.
+                            oncontextmenu: move |e| {
+                                e.prevent_default();
+                                e.stop_propagation();
+                                active_tab.set(idx);
+                                close_dropdowns();
+                                let at = e.data().coordinates().client();
+                                tab_menu_at.set((at.x, at.y));
+                                tab_menu_open.set(true);
+                            },
+                            onkeydown: move |e| {
+                                match e.data().key() {
+                                    Key::Enter => active_tab.set(idx),
+                                    Key::Character(c) if c == " " => active_tab.set(idx),
+                                    Key::ArrowRight => {
+                                        document::eval(&focus_step_script(".tabbar", ".tab", 1));
+                                    }
+                                    Key::ArrowLeft => {
+                                        document::eval(&focus_step_script(".tabbar", ".tab", -1));
+                                    }
+                                    _ => return,
+                                }
+                                e.prevent_default();
+                            },
+
+                            if tab.read_only {
+                                span { class: "tab-lock", title: "Read-only", "🔒" }
+                            }
+                            span { class: "tab-title", "{tab.title()}" }
+
+                            button {
+                                class: "tab-close",
+                                tabindex: "-1",
+                                aria_label: "Close {tab.title()}",
+                                onclick: {
+                                    let idx = idx;
+                                    let tabs2 = tabs.clone();
+                                    let act2 = active_tab.clone();
+                                    let mut overlay2 = overlay.clone();
+                                    let mut pending2 = pending_action.clone();
+                                    move |e| {
+                                        e.stop_propagation();
+                                        let v = tabs2();
+                                        if idx >= v.len() {
+                                            return;
+                                        }
+                                        if close_needs_confirm(&v, idx) {
+                                            pending2.set(PendingAction::CloseTab(idx));
+                                            overlay2.set(Overlay::Confirm);
+                                        } else {
+                                            close_tab_immediately(tabs2.clone(), act2.clone(), idx);
+                                        }
+                                    }
+                                },
+                                "×"
+                            }
+                        }
+                    }
+                }
+
+                // Every tab in a list, for the ones scrolled out of sight.
+                div { class: "menu",
+                    button {
+                        class: "tab-overflow",
+                        title: "All tabs",
+                        aria_haspopup: "menu",
+                        aria_expanded: "{tab_list_open()}",
+                        onclick: move |e| {
+                            e.stop_propagation();
+                            file_open.set(false);
+                            view_open.set(false);
+                            edit_open.set(false);
+                            tab_list_open.set(!tab_list_open());
+                        },
+                        "⌄"
+                    }
+
+                    if tab_list_open() {
+                        div {
+                            class: "dropdown dropdown-right tab-list",
+                            role: "menu",
+                            onclick: move |e| e.stop_propagation(),
+                            onkeydown: move |e| {
+                                let key = e.data().key();
+                                if key == Key::Escape {
+                                    tab_list_open.set(false);
+                                    document::eval(EDITOR_FOCUS_SCRIPT);
+                                    e.prevent_default();
+                                } else if let Some(script) = menu_nav_script(&key, false) {
+                                    document::eval(&script);
+                                    e.prevent_default();
+                                }
+                            },
+                            for (idx, tab) in tabs().iter().enumerate() {
+                                button {
+                                    class: "menu-item",
+                                    role: "menuitem",
+                                    onclick: move |_| {
+                                        tab_list_open.set(false);
+                                        active_tab.set(idx);
+                                    },
+                                    if idx == active_tab() { "● {tab.title()}" } else { "{tab.title()}" }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if tab_menu_open() {
+                    div {
+                        class: "dropdown context-menu",
+                        role: "menu",
+                        style: "left: {tab_menu_at().0}px; top: {tab_menu_at().1}px;",
+                        onclick: move |e| e.stop_propagation(),
+                        onkeydown: move |e| {
+                            let key = e.data().key();
+                            if key == Key::Escape {
+                                tab_menu_open.set(false);
+                                document::eval(EDITOR_FOCUS_SCRIPT);
+                                e.prevent_default();
+                            } else if let Some(script) = menu_nav_script(&key, false) {
+                                document::eval(&script);
+                                e.prevent_default();
+                            }
+                        },
+                        button {
+                            class: "menu-item",
+                            role: "menuitem",
+                            onclick: move |_| {
+                                tab_menu_open.set(false);
+                                duplicate_tab(tabs, active_tab, status);
+                            },
+                            "Duplicate Tab"
+                        }
+                        button {
+                            class: "menu-item",
+                            role: "menuitem",
+                            onclick: move |_| {
+                                tab_menu_open.set(false);
+                                open_second_view(tabs, active_tab, status);
+                            },
+                            "New View of File"
+                        }
+                        button {
+                            class: "menu-item",
+                            role: "menuitem",
+                            onclick: move |_| {
+                                tab_menu_open.set(false);
+                                sidebar_collapsed.set(false);
+                                reveal_in_sidebar(tabs, active_tab, roots, revealed, settings().sidebar_sort, status, false);
+                            },
+                            "Reveal in Sidebar"
+                        }
+                    }
+                }
+            }
+
+            // ===== Breadcrumbs =====
+            if let Some(path) = tabs().get(active_idx).and_then(|t| t.path.clone()) {
+                div { class: "breadcrumbs",
+                    for (i, (label, dir)) in breadcrumb_segments(&path, &roots()).into_iter().enumerate() {
+                        if i > 0 {
+                            span { class: "crumb-sep", "›" }
+                        }
+                        if let Some(dir) = dir {
+                            button {
+                                class: "crumb",
+                                title: "{dir.display()}",
+                                onclick: move |_| {
+                                    sidebar_collapsed.set(false);
+                                    show_directory(roots, status, dir.clone(), settings().sidebar_sort);
+                                },
+                                "{label}"
+                            }
+                        } else {
+                            span { class: "crumb crumb-file", "{label}" }
+                        }
+                    }
+                }
+            }
+
+            // ===== Editor =====
+            div {
+                class: "editor-wrap",
+
+                // Sidebar resize drag handling
+                onmousemove: move |e| {
+                    if !sidebar_resizing() {
+                        return;
+                    }
+
+                    let x = e.data().coordinates().client().x;
+                    let dx = x - sidebar_resize_start_x();
+                    let new_w = (sidebar_resize_start_w() + dx).clamp(SIDEBAR_MIN_W, SIDEBAR_MAX_W);
+                    sidebar_width.set(new_w);
+                    e.prevent_default();
+                },
+                onmouseup: move |_| {
+                    sidebar_resizing.set(false);
+                    block_dragging.set(false);
+                },
+                onmouseleave: move |_| {
+                    sidebar_resizing.set(false);
+                    block_dragging.set(false);
+                },
+                div {
+                    class: "row",
+
+                    // Sidebar (left)
+                    if !sidebar_collapsed() {
+                        div {
+                            class: "sidebar-resize",
+                            style: "width: {sidebar_width()}px;",
+                            div { class: "sidebar",
+                                div {
+                                    class: "sidebar-header",
+                                    onclick: move |_| sidebar_collapsed.set(true),
+
+                                    div {
+                                        class: "sidebar-title",
+                                        {
+                                            match roots().as_slice() {
+                                                [] => rsx!("No directory"),
+                                                [only] => rsx!("{only.path.display()}"),
+                                                many => rsx!("Workspace ({many.len()} folders)"),
+                                            }
+                                        }
+                                    }
+
+                                    button { class: "sidebar-collapse-btn", "×" }
+                                }
+
+                                div { class: "sidebar-contents",
+                                    if roots().is_empty() {
+                                        div { class: "sidebar-empty", "No directory open" }
+                                    }
+                                    if compare_picks().len() == 2 {
+                                        button {
+                                            class: "sidebar-item sidebar-compare",
+                                            onclick: move |_| {
+                                                let picks = compare_picks.take();
+                                                compare_files(tabs, active_tab, status, picks[0].clone(), picks[1].clone());
+                                            },
+                                            "Compare Selected"
+                                        }
+                                    }
+                                    for (ri, root) in roots().iter().enumerate() {
+                                        div {
+                                            class: "sidebar-root",
+                                            title: "{root.path.display()}",
+                                            onclick: move |_| {
+                                                if let Some(r) = roots.write().get_mut(ri) {
+                                                    r.collapsed = !r.collapsed;
+                                                }
+                                            },
+                                            span { class: "sidebar-root-arrow", if root.collapsed { "▶" } else { "▼" } }
+                                            span {
+                                                class: "sidebar-root-name",
+                                                {
+                                                    root.path
+                                                        .file_name()
+                                                        .map(|n| n.to_string_lossy().to_string())
+                                                        .unwrap_or_else(|| root.path.display().to_string())
+                                                }
+                                            }
+                                            button {
+                                                class: "sidebar-collapse-btn",
+                                                title: "Remove folder from workspace",
+                                                onclick: move |e| {
+                                                    e.stop_propagation();
+                                                    remove_root(roots, status, ri);
+                                                },
+                                                "×"
+                                            }
+                                        }
+                                        if !root.collapsed {
+                                            for entry in root.contents.iter() {
+                                                button {
+                                                    class: if compare_picks().contains(&entry.path) {
+                                                        "sidebar-item picked"
+                                                    } else if revealed().as_ref() == Some(&entry.path) {
+                                                        "sidebar-item revealed"
+                                                    } else {
+                                                        "sidebar-item"
+                                                    },
+                                                    onclick: {
+                                                        let tabs2 = tabs;
+                                                        let act2 = active_tab;
+                                                        let mut status2 = status;
+                                                        let p = entry.path.clone();
+                                                        let n = entry.name.clone();
+                                                        let is_dir = entry.is_dir;
+                                                        move |e: MouseEvent| {
+                                                            // Ctrl+click picks files to compare instead of opening them.
+                                                            if !is_dir && e.data().modifiers().ctrl() {
+                                                                let mut picks = compare_picks.write();
+                                                                if let Some(i) = picks.iter().position(|x| *x == p) {
+                                                                    picks.remove(i);
+                                                                } else {
+                                                                    picks.push(p.clone());
+                                                                    if picks.len() > 2 {
+                                                                        picks.remove(0);
+                                                                    }
+                                                                }
+                                                                if picks.len() == 1 {
+                                                                    status2.set("Ctrl+click another file to compare with".to_string());
+                                                                }
+                                                                return;
+                                                            }
+                                                            if is_dir {
+                                                                status2.set(format!("Directory: {n}"));
+                                                            } else {
+                                                                let tabs3 = tabs2;
+                                                                let act3 = act2;
+                                                                let status3 = status2;
+                                                                let p2 = p.clone();
+                                                                spawn(async move { open_path_in_tab(tabs3, act3, status3, p2).await; });
+                                                            }
+                                                        }
+                                                    },
+                                                    if entry.is_dir { "[DIR] " } else { "[FILE] " }
+                                                    "{entry.name}"
+                                                    if settings().show_file_details {
+                                                        span { class: "sidebar-meta", "{dir_entry_details(entry)}" }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        // Drag handle to resize the sidebar
+                        div {
+                            class: "sidebar-handle",
+                            onmousedown: move |e| {
+                                e.stop_propagation();
+                                sidebar_resizing.set(true);
+                                sidebar_resize_start_x.set(e.data().coordinates().client().x);
+                                sidebar_resize_start_w.set(sidebar_width());
+                                e.prevent_default();
+                            },
+                        }
+                    } else if sidebar_collapsed() && !roots().is_empty() {
+                        div {
+                            class: "sidebar-collapsed",
+                            onclick: move |_| sidebar_collapsed.set(false),
+                            "▶"
+                        }
+                    }
+
+                    // Editor content (gutter + textpane)
+                    div {
+                        class: "scroll",
+                        tabindex: "0",
+                        autofocus: "true",
+                        id: "scrollpane",
+                        role: "textbox",
+                        aria_multiline: "true",
+                        aria_label: "Editor",
+                        // Tabs draw as wide as the tab's indent width, like the columns counted for them.
+                        style: "--tab-size: {active_indent.width};",
+
+                        // Coming back restarts the blink solid, so the caret shows right away.
+                        onfocus: move |_| {
+                            editor_focused.set(true);
+                            caret_epoch += 1;
+                        },
+                        onblur: move |_| editor_focused.set(false),
+
+                        onscroll: move |e| {
+                            let d = e.data();
+                            let new_top = d.scroll_top() as f64;
+                            if (new_top - scroll_top()).abs() > 0.5 {
+                                scroll_top.set(new_top);
+                            }
+                            let new_left = d.scroll_left() as f64;
+                            if (new_left - scroll_left()).abs() > 0.5 {
+                                scroll_left.set(new_left);
+                            }
+                            let new_h = d.client_height() as f64;
+                            if new_h >1.0 && (new_h - viewport_h()).abs() > 0.5 {
+                                viewport_h.set(new_h);
+                            }
+                            if md_preview_visible {
+                                document::eval(MD_SYNC_SCRIPT);
+                            }
+                        },
+
+                        onkeydown: move |e| {
+                            // An open overlay owns input; don't let keys edit the buffer behind it.
+                            // Escape still cancels it, in case focus ended up back here.
+                            if overlay() != Overlay::None {
+                                if e.data().key() == Key::Escape {
+                                    dismiss_overlay(tabs, status, overlay, pending_action);
+                                }
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // Any key but a modifier leaves virtual space; typing first fills it in.
+                            let mut virtual_pad = 0;
+                            if !matches!(e.data().key(), Key::Shift | Key::Control | Key::Alt | Key::Meta) {
+                                virtual_pad = tabs.peek().get(*active_tab.peek()).map_or(0, |t| t.editor.virtual_cols());
+                                if virtual_pad > 0 {
+                                    set_active_tab_editor(tabs, active_tab, |t| t.editor.virtual_space = None);
+                                }
+                            }
+
+                            // F6 / Shift+F6 : Leave the editor for the next region (Tab indents here)
+                            if e.data().key() == Key::F6 {
+                                document::eval(&region_focus_script(e.data().modifiers().shift()));
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // Escape : Clear the selection (below), or with none, close an open dropdown
+                            if e.data().key() == Key::Escape {
+                                let selected = tabs.peek().get(*active_tab.peek()).is_some_and(|t| {
+                                    t.editor.buffer.selection().is_some() || t.editor.buffer.block().is_some()
+                                });
+                                if !selected && close_dropdowns() {
+                                    e.prevent_default();
+                                    e.stop_propagation();
+                                    return;
+                                }
                             }
-                        },
 
-                        onkeydown: move |e| {
                             let kd = e.data();
                             let m = kd.modifiers();
                             let ctrl = m.ctrl() || m.meta();
                             let shift = m.shift();
                             let key = kd.key();
+                            // The live view, not the one from the last render: keys can arrive
+                            // faster than renders, e.g. right after a tab switch.
+                            let active_view = tabs.peek().get(*active_tab.peek()).map_or(TabView::Text, |t| t.view);
+
+                            // Alt + O : Header/source (or module/test) counterpart
+                            if m.alt() && !ctrl && kd.code() == Code::KeyO {
+                                open_alternate_file(tabs, active_tab, status, overlay, alternate_offer, &settings().alternate_files);
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // F5 : Insert the date and time
+                            if key == Key::F5 && !ctrl && !m.alt() {
+                                insert_date_time(tabs, active_tab, status, &settings().date_format);
+                                caret_epoch += 1;
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // F2 : Rename the name under the cursor across the file
+                            if key == Key::F2 && !ctrl && !m.alt() {
+                                start_rename(tabs, active_tab, status, overlay, rename);
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // Ctrl/Cmd + Shift + Up/Down : Expand/shrink the selection
+                            if ctrl && shift && matches!(key, Key::ArrowUp | Key::ArrowDown) && active_view == TabView::Text {
+                                let grow = key == Key::ArrowUp;
+                                set_active_tab_editor(tabs, active_tab, |t| {
+                                    let b = &mut t.editor.buffer;
+                                    if grow { b.expand_selection() } else { b.shrink_selection() };
+                                });
+                                caret_epoch += 1;
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
 
                             if ctrl {
                                 if let Key::Character(c) = key {
@@ -1380,24 +5859,132 @@ pub fn app() -> Element {
                                             let tabs2 = tabs.clone();
                                             let act2 = active_tab.clone();
                                             let status2 = status.clone();
-                                            spawn(async move { open_dialog_add_tab(tabs2, act2, status2).await; });
+                                            spawn(async move { open_dialog_add_tab(tabs2, act2, status2, false).await; });
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + \ : Second view of the active file
+                                        (false, "\\") => {
+                                            open_second_view(tabs, active_tab, status);
                                             e.prevent_default();
                                             e.stop_propagation();
                                             return;
                                         }
-                                        // Ctrl/Cmd + Shift + O : Open directory
+                                        // Ctrl/Cmd + Shift + O : Add directory
                                         (true, "o") => {
-                                            let current_dir2 = current_dir.clone();
-                                            let dir_contents2 = dir_contents.clone();
+                                            let roots2 = roots;
                                             let status2 = status.clone();
-                                            spawn(async move { open_directory(current_dir2, dir_contents2, status2).await; });
+                                            let sort = settings().sidebar_sort;
+                                            spawn(async move { open_directory(roots2, status2, sort).await; });
                                             e.prevent_default();
                                             e.stop_propagation();
                                             return;
                                         }
-                                        // Ctrl/Cmd + Shift + C : Close directory
+                                        // Ctrl/Cmd + Shift + C : Close all directories
                                         (true, "c") => {
-                                            close_directory(current_dir.clone(), dir_contents.clone(), status.clone());
+                                            close_directory(roots, status);
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + C : Copy the selection (or column selection)
+                                        (false, "c") => {
+                                            let text = tabs().get(active_tab()).and_then(|t| t.editor.buffer.selected_text());
+                                            if let Some(text) = text {
+                                                copy_to_clipboard(text);
+                                            }
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + A : Select all
+                                        (false, "a") if active_view == TabView::Text => {
+                                            set_active_tab_editor(tabs, active_tab, |t| t.editor.buffer.select_all());
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Z : Undo, Ctrl/Cmd + Shift + Z or Ctrl/Cmd + Y : Redo
+                                        (false, "z") | (true, "z") | (false, "y") if active_view == TabView::Text => {
+                                            let forward = shift || c.eq_ignore_ascii_case("y");
+                                            set_active_tab_editor(tabs, active_tab, |t| {
+                                                let b = &mut t.editor.buffer;
+                                                let changed = if forward { b.redo() } else { b.undo() };
+                                                if changed {
+                                                    t.refresh_dirty();
+                                                }
+                                            });
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + U / L : Upper/lowercase the selection
+                                        (true, "u") | (true, "l") if active_view == TabView::Text => {
+                                            let f = if c.eq_ignore_ascii_case("u") { transform::upper } else { transform::lower };
+                                            transform_active(tabs, active_tab, f);
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + J : Join lines
+                                        (false, "j") if active_view == TabView::Text => {
+                                            set_active_tab_editor(tabs, active_tab, |t| {
+                                                if t.editor.buffer.join_lines() {
+                                                    t.dirty = true;
+                                                }
+                                            });
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + K : Delete the line(s)
+                                        (true, "k") if active_view == TabView::Text => {
+                                            set_active_tab_editor(tabs, active_tab, |t| {
+                                                t.editor.buffer.delete_lines();
+                                                t.dirty = true;
+                                            });
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + D : Duplicate the selection (or line)
+                                        (true, "d") if active_view == TabView::Text => {
+                                            set_active_tab_editor(tabs, active_tab, |t| {
+                                                if t.editor.buffer.duplicate_selection() {
+                                                    t.dirty = true;
+                                                }
+                                            });
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + M : Jump to the matching bracket
+                                        (false, "m") if active_view == TabView::Text => {
+                                            set_active_tab_editor(tabs, active_tab, |t| {
+                                                t.editor.buffer.jump_to_matching_bracket();
+                                            });
+                                            caret_epoch += 1;
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + R : Start/stop recording a macro
+                                        (true, "r") => {
+                                            toggle_macro_recording(macro_recording, macro_keys, status);
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + P : Play the macro
+                                        (true, "p") => {
+                                            play_macro(tabs, active_tab, macro_keys, macro_recording, overwrite(), status);
+                                            caret_epoch += 1;
                                             e.prevent_default();
                                             e.stop_propagation();
                                             return;
@@ -1407,7 +5994,7 @@ pub fn app() -> Element {
                                             let tabs2 = tabs.clone();
                                             let act2 = active_tab.clone();
                                             let status2 = status.clone();
-                                            spawn(async move { save_active_or_save_as(tabs2, act2, status2).await; });
+                                            spawn(async move { save_active_or_save_as(tabs2, act2, status2, overlay).await; });
                                             e.prevent_default();
                                             e.stop_propagation();
                                             return;
@@ -1417,7 +6004,21 @@ pub fn app() -> Element {
                                             let tabs2 = tabs.clone();
                                             let act2 = active_tab.clone();
                                             let status2 = status.clone();
-                                            spawn(async move { save_as_active(tabs2, act2, status2).await; });
+                                            spawn(async move { save_as_active(tabs2, act2, status2, overlay).await; });
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + H : Toggle hex view
+                                        (true, "h") => {
+                                            toggle_hex_view(tabs, active_tab, status);
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Shift + V : Toggle Markdown preview
+                                        (true, "v") => {
+                                            toggle_markdown_preview(md_preview_open, active_is_markdown, status);
                                             e.prevent_default();
                                             e.stop_propagation();
                                             return;
@@ -1429,164 +6030,1087 @@ pub fn app() -> Element {
                                             e.stop_propagation();
                                             return;
                                         }
-                                        // Ctrl/Cmd + W : Close tab
-                                        (false, "w") => {
-                                            let idx = active_tab();
+                                        // Ctrl/Cmd + W : Close tab
+                                        (false, "w") => {
+                                            let idx = active_tab();
+                                            let v = tabs();
+                                            if idx < v.len() {
+                                                if close_needs_confirm(&v, idx) {
+                                                    pending_action.set(PendingAction::CloseTab(idx));
+                                                    overlay.set(Overlay::Confirm);
+                                                } else {
+                                                    close_tab_immediately(tabs.clone(), active_tab.clone(), idx);
+                                                }
+                                            }
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        // Ctrl/Cmd + Q : Quit
+                                        (false, "q") => {
+                                            if active_dirty {
+                                                pending_action.set(PendingAction::ExitApp);
+                                                overlay.set(Overlay::Confirm);
+                                            } else {
+                                                dioxus_desktop::window().close();
+                                            }
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            return;
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            }
+
+                            // ===== Editor typing =====
+                            // The hex view is read-only, so only the shortcuts above apply.
+                            if active_view != TabView::Text {
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            let key = e.data().key();
+                            let idx = active_tab();
+
+                            // PageUp/PageDown scroll a page, less the lines kept for context, and
+                            // take the caret along.
+                            if matches!(key, Key::PageUp | Key::PageDown) {
+                                let lp = line_px();
+                                let page = ((viewport_h() / lp).floor() as usize)
+                                    .saturating_sub(settings().page_overlap)
+                                    .max(1);
+                                let down = key == Key::PageDown;
+                                set_active_tab_editor(tabs, active_tab, |t| {
+                                    let b = &mut t.editor.buffer;
+                                    for _ in 0..page {
+                                        if down { b.move_down(shift) } else { b.move_up(shift) }
+                                    }
+                                });
+                                let dy = page as f64 * lp;
+                                document::eval(&scroll_by_script(if down { dy } else { -dy }));
+                                caret_epoch += 1;
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            // Vim mode gets first look; Insert mode typing falls through below.
+                            if settings().vim_mode {
+                                let vk = match &key {
+                                    Key::Character(c) if c.chars().count() == 1 => VimKey::Char(c.chars().next().unwrap_or(' ')),
+                                    Key::Escape => VimKey::Escape,
+                                    Key::Enter => VimKey::Enter,
+                                    Key::Backspace => VimKey::Backspace,
+                                    _ => VimKey::Other,
+                                };
+                                let mut action = VimAction::PassThrough;
+                                set_active_tab_editor(tabs, active_tab, |t| {
+                                    action = vim.write().handle(&mut t.editor.buffer, vk);
+                                    if action == VimAction::Edited {
+                                        t.dirty = true;
+                                    }
+                                });
+                                caret_epoch += 1;
+                                if action != VimAction::PassThrough {
+                                    match action {
+                                        VimAction::Yank(text) => copy_to_clipboard(text),
+                                        VimAction::Write => {
+                                            spawn(async move { save_active_or_save_as(tabs, active_tab, status, overlay).await; });
+                                        }
+                                        VimAction::WriteQuit => {
+                                            spawn(async move {
+                                                save_active_or_save_as(tabs, active_tab, status, overlay).await;
+                                                let idx = active_tab();
+                                                if tabs().get(idx).is_some_and(|t| !t.dirty) {
+                                                    close_tab_immediately(tabs, active_tab, idx);
+                                                }
+                                            });
+                                        }
+                                        VimAction::Quit { force } if idx < tabs().len() => {
+                                            if close_needs_confirm(&tabs(), idx) && !force {
+                                                pending_action.set(PendingAction::CloseTab(idx));
+                                                overlay.set(Overlay::Confirm);
+                                            } else {
+                                                close_tab_immediately(tabs, active_tab, idx);
+                                            }
+                                        }
+                                        VimAction::GotoLine(line) => {
+                                            goto_line_col(tabs, active_tab, scroll_top, shown_tab_id, viewport_h(), line, 1);
+                                        }
+                                        VimAction::Unknown(cmd) => status.set(format!("Not an editor command: {cmd}")),
+                                        _ => {}
+                                    }
+                                    e.prevent_default();
+                                    e.stop_propagation();
+                                    return;
+                                }
+                            }
+
+                            if key == Key::Insert {
+                                overwrite.toggle();
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+
+                            if macro_recording() {
+                                macro_keys.write().push((key.clone(), shift));
+                            }
+
+                            // In virtual space, Backspace and Left step back a column toward the text.
+                            if virtual_pad > 0 && matches!(key, Key::Backspace | Key::ArrowLeft) && !shift {
+                                if virtual_pad > 1 {
+                                    set_active_tab_editor(tabs, active_tab, |t| {
+                                        let (at, revision) = (t.editor.buffer.cursor(), t.editor.buffer.revision());
+                                        t.editor.virtual_space = Some(VirtualSpace { at, revision, cols: virtual_pad - 1 });
+                                    });
+                                }
+                                caret_epoch += 1;
+                                e.prevent_default();
+                                e.stop_propagation();
+                                return;
+                            }
+                            let fills = matches!(&key, Key::Character(_)) || (key == Key::Tab && !shift);
+
+                            set_active_tab_editor(tabs.clone(), active_tab.clone(), |t| {
+                                let pairs = crate::syntax::auto_close_pairs(&t.language);
+                                if virtual_pad > 0 && fills {
+                                    t.editor.buffer.insert(&" ".repeat(virtual_pad));
+                                }
+                                let expanded = key == Key::Tab && !shift && virtual_pad == 0
+                                    && expand_snippet(&mut t.editor.buffer, &t.language, t.indent);
+                                let changed = expanded || handle_key(&mut t.editor.buffer, key, shift, &pairs, overwrite(), t.indent);
+                                if changed {
+                                    t.dirty = true;
+                                }
+                            });
+                            caret_epoch += 1;
+
+                            // status line hint
+                            if idx < tabs().len() {
+                                // nothing
+                            }
+
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        if active_view == TabView::Image {
+                            div { class: "image-view",
+                                {
+                                    let uri = tabs()
+                                        .get(active_tab())
+                                        .and_then(|t| t.image_uri.clone())
+                                        .unwrap_or_default();
+                                    rsx!(img { src: "{uri}", draggable: "false" })
+                                }
+                            }
+                        } else if active_view == TabView::Diff {
+                            div { class: "editor-content",
+                                {
+                                    let d = tabs().get(active_tab()).and_then(|t| t.diff.clone());
+                                    let rows = d.as_ref().map_or(0, |d| d.rows.len());
+                                    let (start, end, top_h, bottom_h) =
+                                        visible_range(scroll_top(), viewport_h(), rows.max(1));
+
+                                    rsx!(
+                                        div { class: "textpane diffpane",
+                                            div { style: "height: {top_h}px;" }
+                                            if let Some(d) = d {
+                                                for row in &d.rows[start.min(rows)..end.min(rows)] {
+                                                    div { class: "diffrow {row.kind.class()}",
+                                                        for side in [&row.left, &row.right] {
+                                                            span { class: "diff-num", {side.as_ref().map(|(n, _)| n.to_string())} }
+                                                            span { class: "diff-text", {side.as_ref().map(|(_, text)| text.replace('\t', "    "))} }
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                            div { style: "height: {bottom_h}px;" }
+                                        }
+                                    )
+                                }
+                            }
+                        } else if active_view == TabView::Hex {
+                            div { class: "editor-content",
+                                {
+                                    let v = tabs();
+                                    let bytes = v
+                                        .get(active_tab())
+                                        .and_then(|t| t.bytes.clone())
+                                        .unwrap_or_default();
+
+                                    let total = hexview::row_count(bytes.len());
+                                    let (start, end, top_h, bottom_h) =
+                                        visible_range(scroll_top(), viewport_h(), total);
+
+                                    rsx!(
+                                        div { class: "textpane hexpane",
+                                            div { style: "height: {top_h}px;" }
+                                            for i in start..end {
+                                                {
+                                                    let row = hexview::format_row(&bytes, i);
+                                                    rsx!(
+                                                        div { class: "hexrow",
+                                                            span { class: "hex-offset", "{row.offset}" }
+                                                            span { class: "hex-bytes", "{row.hex}" }
+                                                            span { class: "hex-ascii", "{row.ascii}" }
+                                                        }
+                                                    )
+                                                }
+                                            }
+                                            div { style: "height: {bottom_h}px;" }
+                                        }
+                                    )
+                                }
+                            }
+                        } else {
+                            div { class: "editor-content",
+                                // gutter
+                                {
+                                    let v = tabs();
+                                    let idx = active_tab();
+
+                                    let (total, cursor_line) = v
+                                        .get(idx)
+                                        .map(|t| (t.editor.buffer.line_count(), t.editor.buffer.cursor().line))
+                                        .unwrap_or((1, 0));
+
+                                    let (start, end, top_h, bottom_h) =
+                                        visible_range(scroll_top(), viewport_h(), total);
+                                    let bottom_h = bottom_h + overscroll_px(settings().scroll_past_end, viewport_h());
+
+                                    rsx!(
+                                        div { class: "gutter",
+                                            div {
+                                                class: "gutter-active",
+                                                style: "top: calc(var(--pad-y) + {row_top(cursor_line)}px);"
+                                            }
+                                            div { style: "height: {top_h}px;" }
+                                            for i in start..end {
+                                                div {
+                                                    class: if i == cursor_line { "ln active" } else { "ln" },
+                                                    if settings().relative_line_numbers && i != cursor_line {
+                                                        "{i.abs_diff(cursor_line)}"
+                                                    } else {
+                                                        "{i + 1}"
+                                                    }
+                                                }
+                                            }
+                                            div { style: "height: {bottom_h}px;" }
+                                        }
+                                    )
+                                }
+
+    // text pane
+                                div {
+                                    class: "textpane",
+
+
+                                    // active line band
+                                    {
+                                        let v = tabs();
+                                        let idx = active_tab();
+                                        let s = v.get(idx).map(|t| t.editor.buffer.clone()).unwrap_or_default();
+
+                                        let top = row_top(s.cursor().line);
+                                        let (start, end, _, _) = visible_range(scroll_top(), viewport_h(), s.lines().len());
+                                        let longest = s.lines()[start..end]
+                                            .iter()
+                                            .map(|l| transform::display_columns(l, active_indent.width))
+                                            .max()
+                                            .unwrap_or(0);
+                                        let content_w = (longest as f64) * char_px();
+
+                                        rsx!(
+                                            div {
+                                                class: "active-line",
+                                                style: "top: calc(var(--pad-y) + {top}px); width: max(100%, calc({content_w}px + 2 * var(--pad-x)));"
+                                            }
+                                        )
+                                    }
+
+                                    // selection
+                                    {
+                                        let v = tabs();
+                                        let idx = active_tab();
+                                        let s = v.get(idx).map(|t| t.editor.buffer.clone()).unwrap_or_default();
+
+                                        let (start, end, _, _) = visible_range(scroll_top(), viewport_h(), s.lines().len());
+                                        // (line, first column, columns) as drawn, tabs at their width; line
+                                        // breaks inside the selection show as one extra column.
+                                        let tab_width = active_indent.width;
+                                        let rows: Vec<(usize, usize, usize)> = match s.selection() {
+                                            Some((a, b)) => (a.line.max(start)..=b.line.min(end.saturating_sub(1)))
+                                                .map(|l| {
+                                                    let from = if l == a.line { a } else { Cursor { line: l, col: 0 } };
+                                                    let to = if l == b.line { b } else { Cursor { line: l, col: s.lines()[l].len() } };
+                                                    let left = s.visual_col(from, tab_width);
+                                                    let len = s.visual_col(to, tab_width) - left + usize::from(l != b.line);
+                                                    (l, left, len)
+                                                })
+                                                .collect(),
+                                            None => Vec::new(),
+                                        };
+
+                                        rsx!(
+                                            for (l, left, len) in rows {
+                                                div {
+                                                    class: "selection",
+                                                    style: "top: calc(var(--pad-y) + {l as f64 * line_px()}px); left: calc(var(--pad-x) + {left as f64 * char_px()}px); width: {len as f64 * char_px()}px;"
+                                                }
+                                            }
+                                        )
+                                    }
+
+                                    // column selection
+                                    {
+                                        let v = tabs();
+                                        let idx = active_tab();
+                                        let s = v.get(idx).map(|t| t.editor.buffer.clone()).unwrap_or_default();
+
+                                        let (start, end, _, _) = visible_range(scroll_top(), viewport_h(), s.lines().len());
+                                        let rows: Vec<usize> = match s.block() {
+                                            Some(b) => b.lines().filter(|l| (start..end).contains(l)).collect(),
+                                            None => Vec::new(),
+                                        };
+                                        let cols = s.block().map(|b| b.cols()).unwrap_or(0..0);
+                                        let left = cols.start as f64 * char_px();
+                                        let width = cols.len() as f64 * char_px();
+
+                                        rsx!(
+                                            for l in rows {
+                                                div {
+                                                    class: "block-sel",
+                                                    style: "top: calc(var(--pad-y) + {l as f64 * line_px()}px); left: calc(var(--pad-x) + {left}px); width: {width}px;"
+                                                }
+                                            }
+                                        )
+                                    }
+
+                                    // indent guides
+                                    if settings().indent_guides {
+                                        {
                                             let v = tabs();
-                                            if idx < v.len() {
-                                                if v[idx].dirty {
-                                                    pending_action.set(PendingAction::CloseTab(idx));
-                                                    confirm_open.set(true);
-                                                } else {
-                                                    close_tab_immediately(tabs.clone(), active_tab.clone(), idx);
+                                            let idx = active_tab();
+                                            let s = v.get(idx).map(|t| t.editor.buffer.clone()).unwrap_or_default();
+                                            let width = active_indent.width.max(1);
+
+                                            let (start, end, _, _) = visible_range(scroll_top(), viewport_h(), s.lines().len());
+                                            let step = width as f64 * char_px();
+
+                                            rsx!(
+                                                for (i, levels) in indent_guide_levels(s.lines(), start, end, width).into_iter().enumerate() {
+                                                    if levels > 0 {
+                                                        div {
+                                                            class: "indent-guides",
+                                                            style: "top: calc(var(--pad-y) + {(start + i) as f64 * line_px()}px); width: {levels as f64 * step}px; background-size: {step}px 100%;"
+                                                        }
+                                                    }
+                                                }
+                                            )
+                                        }
+                                    }
+
+                                    // rulers
+                                    if settings().show_rulers {
+                                        for col in settings().rulers {
+                                            div {
+                                                class: "ruler",
+                                                style: "left: calc(var(--pad-x) + {col as f64 * char_px()}px);"
+                                            }
+                                        }
+                                    }
+
+                                    // caret
+                                    {
+                                        // Read in place rather than from a copy of the buffer, so
+                                        // it's always the cursor the last key left.
+                                        let (line, col) = tabs
+                                            .read()
+                                            .get(active_tab())
+                                            .map(|t| {
+                                                let b = &t.editor.buffer;
+                                                (b.cursor().line, b.visual_col(b.cursor(), t.indent.width) + t.editor.virtual_cols())
+                                            })
+                                            .unwrap_or_default();
+
+                                        let top = row_top(line);
+                                        let left = col as f64 * char_px();
+
+                                        // Alternating between two identical animations restarts the blink.
+                                        let class = if !editor_focused() {
+                                            "caret unfocused"
+                                        } else if !settings().caret_blink {
+                                            "caret"
+                                        } else if caret_epoch() % 2 == 0 {
+                                            "caret blink-a"
+                                        } else {
+                                            "caret blink-b"
+                                        };
+                                        // Overwrite mode shows a block over the character it will replace.
+                                        let width = if overwrite() { format!("width: {}px;", char_px()) } else { String::new() };
+
+                                        rsx!(
+                                            div {
+                                                class: if overwrite() { "{class} overwrite" } else { "{class}" },
+                                                style: "top: calc(var(--pad-y) + {top}px); left: calc(var(--pad-x) + {left}px); {width}"
+                                            }
+                                        )
+                                    }
+
+                                    // lines (with syntax highlighting)
+                                    {
+                                        let v = tabs();
+                                        let idx = active_tab();
+                                        let s = v.get(idx).map(|t| t.editor.buffer.clone()).unwrap_or_default();
+                                        let id = v.get(idx).map_or(0, |t| t.id);
+
+                                        let total = s.lines().len();
+                                        let (start, end, top_h, bottom_h) =
+                                            visible_range(scroll_top(), viewport_h(), total);
+                                        let bottom_h = bottom_h + overscroll_px(settings().scroll_past_end, viewport_h());
+                                        let highlighted = crate::syntax::highlighter(&active_language).highlight(id, &s, start..end);
+
+                                        rsx!(
+                                            div { style: "height: {top_h}px;" }
+                                            for i in start..end {
+                                                {
+                                                    let line_index = i;
+                                                    let mut spans = highlighted[i - start].clone();
+                                                    if settings().highlight_long_lines {
+                                                        spans = mark_long_line(spans, settings().long_line_limit, active_indent.width);
+                                                    }
+                                                    let spans = mark_control_chars(spans);
+                                                    rsx!(
+                                                        div {
+                                                            class: if i == s.cursor().line { "line active" } else { "line" },
+                                                            // Alt+drag: column selection
+                                                            onmousedown: move |e| {
+                                                                if !e.data().modifiers().alt() {
+                                                                    return;
+                                                                }
+                                                                e.prevent_default();
+                                                                let col = column_at(e.data().coordinates().element().x, scroll_left());
+                                                                block_dragging.set(true);
+                                                                caret_epoch += 1;
+                                                                set_active_tab_editor(tabs, active_tab, |t| {
+                                                                    t.editor.buffer.set_block((line_index, col), (line_index, col));
+                                                                });
+                                                            },
+                                                            onmousemove: move |e| {
+                                                                if !block_dragging() {
+                                                                    return;
+                                                                }
+                                                                let col = column_at(e.data().coordinates().element().x, scroll_left());
+                                                                set_active_tab_editor(tabs, active_tab, |t| {
+                                                                    if let Some(b) = t.editor.buffer.block()
+                                                                        && b.head != (line_index, col)
+                                                                    {
+                                                                        t.editor.buffer.set_block(b.anchor, (line_index, col));
+                                                                    }
+                                                                });
+                                                            },
+                                                            onclick: {
+                                                                let tabs2 = tabs.clone();
+                                                                let act2 = active_tab.clone();
+                                                                move |e| {
+                                                                    let m = e.data().modifiers();
+                                                                    if m.alt() {
+                                                                        return;
+                                                                    }
+                                                                    let clicked_col = column_at(e.data().coordinates().element().x, scroll_left());
+                                                                    let virtual_space = settings().virtual_space;
+                                                                    caret_epoch += 1;
+                                                                    set_active_tab_editor(tabs2.clone(), act2.clone(), |t| {
+                                                                        let tab_width = t.indent.width;
+                                                                        let b = &mut t.editor.buffer;
+                                                                        let pos = b.pos_at_visual_col(line_index, clicked_col, tab_width);
+                                                                        // Shift+click extends the selection to the click.
+                                                                        if m.shift() {
+                                                                            b.extend_selection(pos);
+                                                                        } else {
+                                                                            b.set_cursor(pos);
+                                                                        }
+                                                                        let past_end = clicked_col.saturating_sub(b.visual_col(pos, tab_width));
+                                                                        t.editor.virtual_space = (virtual_space && !m.shift() && past_end > 0).then(|| VirtualSpace {
+                                                                            at: b.cursor(),
+                                                                            revision: b.revision(),
+                                                                            cols: past_end,
+                                                                        });
+                                                                    });
+                                                                }
+                                                            },
+                                                            for (sp, real) in spans {
+                                                                if let Some(c) = real {
+                                                                    span { class: "ctrl-char", style: "color: {sp.color};", title: "U+{c as u32:04X}", "{sp.text}" }
+                                                                } else {
+                                                                    span { style: "color: {sp.color};", "{sp.text}" }
+                                                                }
+                                                            }
+                                                        }
+                                                    )
                                                 }
                                             }
-                                            e.prevent_default();
+                                            div { style: "height: {bottom_h}px;" }
+                                        )
+                                    }
+
+
+                                }
+                            }
+                        }
+                    }
+
+                    // Overview strip: where the changes are in the whole file, click to jump there.
+                    // Diff tabs mark the rows that differ; text tabs mark the lines changed since
+                    // the last save and the lines holding the selected text.
+                    if active_view == TabView::Diff || active_view == TabView::Text {
+                        {
+                            let (total, marks) = if active_view == TabView::Diff {
+                                let d = tabs().get(active_tab()).and_then(|t| t.diff.clone());
+                                let total = d.as_ref().map_or(0, |d| d.rows.len());
+                                let marks = d.map_or_else(Vec::new, |d| {
+                                    overview_marks(
+                                        d.rows
+                                            .iter()
+                                            .enumerate()
+                                            .filter(|(_, r)| r.kind != diff::RowKind::Same)
+                                            .map(|(i, r)| (i, r.kind.class())),
+                                    )
+                                });
+                                (total, marks)
+                            } else {
+                                text_overview().unwrap_or_default()
+                            };
+                            let total = total.max(1);
+                            rsx!(
+                                div {
+                                    class: "overview",
+                                    title: if active_view == TabView::Diff {
+                                        "Changes in the whole file (click to jump)"
+                                    } else {
+                                        "Unsaved changes and matches of the selection (click to jump)"
+                                    },
+                                    onclick: move |e| {
+                                        let frac = (e.data().coordinates().element().y / viewport_h().max(1.0)).clamp(0.0, 1.0);
+                                        let row = (frac * total as f64) as usize;
+                                        let top = (PAD_Y_PX + row as f64 * line_px() - viewport_h() / 2.0).max(0.0);
+                                        document::eval(&scroll_to_script(scroll_left(), top));
+                                    },
+                                    for m in marks {
+                                        div {
+                                            class: "overview-mark {m.class}",
+                                            style: "top: {m.start as f64 * 100.0 / total as f64}%; height: {m.len as f64 * 100.0 / total as f64}%;",
+                                        }
+                                    }
+                                }
+                            )
+                        }
+                    }
+
+                    // Markdown preview (right)
+                    if md_preview_visible {
+                        div {
+                            class: "md-preview",
+                            id: "md-preview",
+                            // Links would navigate the whole webview away from the editor.
+                            onclick: move |e| e.prevent_default(),
+                            dangerous_inner_html: "{md_html}",
+                        }
+                    }
+                }
+            }
+
+            // ===== Recovery modal =====
+            if overlay() == Overlay::Recover {
+                div {
+                    class: "modal-backdrop",
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
+
+                        // Enter restores; there's no cancel, since either choice clears the prompt.
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Enter => {
+                                    overlay.set(Overlay::None);
+                                    restore_recovered(tabs, active_tab, status, recovered.take());
+                                }
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        div { class: "modal-title", "SIDE didn't close properly last time." }
+                        div { class: "modal-sub", "Restore the unsaved work it was holding?" }
+                        ul { class: "modal-list",
+                            for snap in recovered() {
+                                li { "{snap.title()}" }
+                            }
+                        }
+
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn btn-danger",
+                                onclick: move |_| {
+                                    overlay.set(Overlay::None);
+                                    recovered.set(Vec::new());
+                                    if let Err(err) = recovery::clear() {
+                                        status.set(format!("Couldn't clear recovery files: {err}"));
+                                    }
+                                },
+                                "Discard"
+                            }
+
+                            button {
+                                class: "btn btn-primary",
+                                onclick: move |_| {
+                                    overlay.set(Overlay::None);
+                                    restore_recovered(tabs, active_tab, status, recovered.take());
+                                },
+                                "Restore"
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Undo history modal =====
+            if overlay() == Overlay::History {
+                div {
+                    class: "modal-backdrop",
+                    onclick: move |_| overlay.set(Overlay::None),
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
+
+                        // Escape closes; Enter and Space press the focused step.
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => overlay.set(Overlay::None),
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Enter => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        div { class: "modal-title", "Undo History" }
+                        div { class: "modal-sub", "Newest first. Pick a step to go back (or forward) to just after it." }
+
+                        {
+                            let (steps, position) = tabs()
+                                .get(active_tab())
+                                .map(|t| (t.editor.buffer.history(), t.editor.buffer.history_position()))
+                                .unwrap_or_default();
+                            let mut jump = move |to: usize| {
+                                set_active_tab_editor(tabs, active_tab, |t| {
+                                    if t.editor.buffer.jump_to_history(to) {
+                                        t.dirty = true;
+                                    }
+                                });
+                                caret_epoch += 1;
+                            };
+                            rsx! {
+                                div { class: "history-list",
+                                    for (i, step) in steps.into_iter().enumerate().rev() {
+                                        button {
+                                            key: "{i}",
+                                            class: if i + 1 == position { "history-step current" } else if step.done { "history-step" } else { "history-step undone" },
+                                            onclick: move |_| jump(i + 1),
+                                            "{step.label}"
+                                        }
+                                    }
+                                    button {
+                                        class: if position == 0 { "history-step current" } else { "history-step" },
+                                        onclick: move |_| jump(0),
+                                        "Oldest state kept"
+                                    }
+                                }
+                            }
+                        }
+
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn btn-primary",
+                                onclick: move |_| overlay.set(Overlay::None),
+                                "Close"
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Reopen with encoding modal =====
+            if overlay() == Overlay::ReopenEncoding {
+                div {
+                    class: "modal-backdrop",
+                    onclick: move |_| overlay.set(Overlay::None),
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
+
+                        // Escape cancels; Enter and Space press the focused encoding.
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => overlay.set(Overlay::None),
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Enter => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        div { class: "modal-title", "Reopen with Encoding" }
+                        div { class: "modal-sub",
+                            "Read the file again as:"
+                            if tabs().get(active_tab()).is_some_and(|t| t.dirty) {
+                                " (this replaces the unsaved changes in this tab)"
+                            }
+                        }
+
+                        div { class: "encoding-list",
+                            for enc in TextEncoding::ALL {
+                                button {
+                                    // The current one starts focused.
+                                    class: if enc == active_encoding { "encoding-choice current btn-primary" } else { "encoding-choice" },
+                                    onclick: move |_| {
+                                        overlay.set(Overlay::None);
+                                        reopen_with_encoding(tabs, active_tab, status, enc);
+                                        caret_epoch += 1;
+                                    },
+                                    "{enc.label()}"
+                                }
+                            }
+                        }
+
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn",
+                                onclick: move |_| overlay.set(Overlay::None),
+                                "Cancel"
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Reload modal =====
+            if let Overlay::Reload(idx) = overlay() {
+                div {
+                    class: "modal-backdrop",
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
+
+                        // Escape keeps the edits; Enter and Space press the focused button (Keep to start with).
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => answer_reload_prompt(tabs, status, overlay, idx, false),
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Enter => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        div {
+                            class: "modal-title",
+                            {
+                                let name = tabs().get(idx).map(|t| t.title().trim_end_matches('*').to_string()).unwrap_or_default();
+                                rsx!("{name} changed on disk.")
+                            }
+                        }
+                        div { class: "modal-sub", "Reload it and lose your unsaved changes?" }
+
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn btn-danger",
+                                onclick: move |_| answer_reload_prompt(tabs, status, overlay, idx, true),
+                                "Reload"
+                            }
+
+                            button {
+                                class: "btn btn-primary",
+                                onclick: move |_| answer_reload_prompt(tabs, status, overlay, idx, false),
+                                "Keep My Changes"
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Create alternate file modal =====
+            if overlay() == Overlay::CreateAlternate
+                && let Some(path) = alternate_offer()
+            {
+                div {
+                    class: "modal-backdrop",
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
+
+                        // Escape cancels; Enter and Space press the focused button (Create to start with).
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => overlay.set(Overlay::None),
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Enter => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
+                        div { class: "modal-title", "No counterpart file found." }
+                        div { class: "modal-sub", "Create {path.display()}?" }
+
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn",
+                                onclick: move |_| overlay.set(Overlay::None),
+                                "Cancel"
+                            }
+
+                            button {
+                                class: "btn btn-primary",
+                                onclick: move |_| {
+                                    overlay.set(Overlay::None);
+                                    create_alternate_file(tabs, active_tab, status, path.clone());
+                                },
+                                "Create"
+                            }
+                        }
+                    }
+                }
+            }
+
+            // ===== Rename in file modal =====
+            if overlay() == Overlay::Rename {
+                {
+                    let (from, to) = rename();
+                    let buffer = tabs().get(active_tab()).map(|t| t.editor.buffer.clone());
+                    let found = buffer.as_ref().map_or(0, |b| b.word_occurrences(&from).len());
+                    // Renaming onto a name that's already used merges the two.
+                    let clashes = if to != from { buffer.as_ref().map_or(0, |b| b.word_occurrences(&to).len()) } else { 0 };
+                    rsx! {
+                        div {
+                            class: "modal-backdrop",
+                            onclick: move |_| overlay.set(Overlay::None),
+
+                            div {
+                                class: "modal",
+                                onclick: move |e| e.stop_propagation(),
+
+                                // Escape cancels; Enter in the field renames, on a button presses it.
+                                onkeydown: move |e| {
+                                    match e.data().key() {
+                                        Key::Escape => overlay.set(Overlay::None),
+                                        Key::Tab => {
+                                            let back = e.data().modifiers().shift();
+                                            document::eval(&modal_tab_script(back));
+                                        }
+                                        Key::Enter => {
                                             e.stop_propagation();
                                             return;
                                         }
-                                        // Ctrl/Cmd + Q : Quit
-                                        (false, "q") => {
-                                            if active_dirty {
-                                                pending_action.set(PendingAction::ExitApp);
-                                                confirm_open.set(true);
-                                            } else {
-                                                dioxus_desktop::window().close();
-                                            }
-                                            e.prevent_default();
+                                        Key::Character(c) if c == " " => {
                                             e.stop_propagation();
                                             return;
                                         }
                                         _ => {}
                                     }
-                                }
-                            }
+                                    e.prevent_default();
+                                    e.stop_propagation();
+                                },
 
-                            // ===== Editor typing =====
-                            let key = e.data().key();
-                            let idx = active_tab();
+                                div { class: "modal-title", "Rename {from}" }
+                                div { class: "modal-sub", "{found} occurrence(s) in this file" }
+
+                                input {
+                                    class: "modal-input",
+                                    r#type: "text",
+                                    spellcheck: "false",
+                                    value: "{to}",
+                                    oninput: move |e| {
+                                        let mut rename = rename;
+                                        rename.with_mut(|r| r.1 = e.value());
+                                    },
+                                    // Typing stays in the field; Escape and Tab go on to the modal.
+                                    onkeydown: move |e| {
+                                        match e.data().key() {
+                                            Key::Escape | Key::Tab => {}
+                                            Key::Enter => {
+                                                apply_rename(tabs, active_tab, status, overlay, rename);
+                                                e.prevent_default();
+                                                e.stop_propagation();
+                                            }
+                                            _ => e.stop_propagation(),
+                                        }
+                                    },
+                                }
 
-                            set_active_tab_editor(tabs.clone(), active_tab.clone(), |t| {
-                                let changed = handle_key(&mut t.editor, key);
-                                if changed {
-                                    t.dirty = true;
+                                if clashes > 0 {
+                                    div { class: "modal-sub warn", "{to} is already used {clashes} time(s) here" }
                                 }
-                            });
 
-                            // status line hint
-                            if idx < tabs().len() {
-                                // nothing
+                                div { class: "modal-actions",
+                                    button {
+                                        class: "btn",
+                                        onclick: move |_| overlay.set(Overlay::None),
+                                        "Cancel"
+                                    }
+
+                                    button {
+                                        class: "btn btn-primary",
+                                        disabled: to.is_empty(),
+                                        onclick: move |_| apply_rename(tabs, active_tab, status, overlay, rename),
+                                        "Rename"
+                                    }
+                                }
                             }
+                        }
+                    }
+                }
+            }
+
+            // ===== Overwrite modal =====
+            if let Overlay::Overwrite(idx) = overlay() {
+                div {
+                    class: "modal-backdrop",
+                    onclick: move |_| {
+                        overlay.set(Overlay::None);
+                        pending_action.set(PendingAction::None);
+                    },
+
+                    div {
+                        class: "modal",
+                        onclick: move |e| e.stop_propagation(),
 
+                        // Escape cancels; Enter and Space press the focused button (Cancel to start with).
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => {
+                                    overlay.set(Overlay::None);
+                                    pending_action.set(PendingAction::None);
+                                }
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                Key::Enter => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
                             e.prevent_default();
                             e.stop_propagation();
                         },
 
-                        div { class: "editor-content",
-                            // gutter
+                        div { class: "modal-title", "This file changed on disk since you opened it." }
+                        div {
+                            class: "modal-sub",
                             {
-                                let v = tabs();
-                                let idx = active_tab();
-
-                                let (lines, cursor_line) = if idx < v.len() {
-                                    (v[idx].editor.lines.clone(), v[idx].editor.cursor.line)
-                                } else {
-                                    (Arc::new(vec![String::new()]), 0usize)
-                                };
-
-                                let total = lines.len();
-                                let (start, end, top_h, bottom_h) =
-                                    visible_range(scroll_top(), viewport_h(), total);
+                                let name = tabs().get(idx).map(|t| t.title().trim_end_matches('*').to_string()).unwrap_or_default();
+                                rsx!("Overwrite {name} with your version?")
+                            }
+                        }
 
-                                rsx!(
-                                    div { class: "gutter",
-                                        div { style: "height: {top_h}px;" }
-                                        for i in start..end {
-                                            div {
-                                                class: if i == cursor_line { "ln active" } else { "ln" },
-                                                "{i + 1}"
+                        div { class: "modal-actions",
+                            button {
+                                class: "btn btn-danger",
+                                onclick: move |_| {
+                                    overlay.set(Overlay::None);
+                                    if let Some(path) = tabs.peek().get(idx).and_then(|t| t.path.clone()) {
+                                        spawn(async move {
+                                            write_tab_to_path(tabs, idx, status, path).await;
+                                            // Carry on with the close or exit this save was for,
+                                            // unless the write failed.
+                                            let saved = tabs.peek().get(idx).is_some_and(|t| !t.dirty);
+                                            if saved && *pending_action.peek() != PendingAction::None {
+                                                confirm_save(tabs, active_tab, status, overlay, pending_action);
+                                            } else {
+                                                pending_action.set(PendingAction::None);
                                             }
-                                        }
-                                        div { style: "height: {bottom_h}px;" }
+                                        });
                                     }
-                                )
+                                },
+                                "Overwrite"
                             }
 
-// text pane
-                            div {
-                                class: "textpane",
-
-
-                                // caret
-                                {
-                                    let v = tabs();
-                                    let idx = active_tab();
-                                    let s = if idx < v.len() { v[idx].editor.clone() } else { EditorState::default() };
-
-                                    let top = (s.cursor.line as f64) * line_px();
-                                    let left = (s.cursor.col as f64) * char_px();
-
-                                    rsx!(
-                                        div {
-                                            class: "caret",
-                                            style: "top: calc(var(--pad-y) + {top}px); left: calc(var(--pad-x) + {left}px);"
-                                        }
-                                    )
-                                }
-
-                                // lines (with syntax highlighting)
-                                {
-                                    let v = tabs();
-                                    let idx = active_tab();
-                                    let s = if idx < v.len() { v[idx].editor.clone() } else { EditorState::default() };
-
-                                    let total = s.lines.len();
-                                    let (start, end, top_h, bottom_h) =
-                                        visible_range(scroll_top(), viewport_h(), total);
-
-                                    rsx!(
-                                        div { style: "height: {top_h}px;" }
-                                        for i in start..end {
-                                            {
-                                                let line_index = i;
-                                                let line = &s.lines[i];
-                                                let spans = crate::syntax::highlight_line(&active_language, line);
-                                                rsx!(
-                                                    div {
-                                                        class: if i == s.cursor.line { "line active" } else { "line" },
-                                                        onclick: {
-                                                            let tabs2 = tabs.clone();
-                                                            let act2 = active_tab.clone();
-                                                            move |e| {
-                                                                let p = e.data().coordinates().element();
-                                                                let view_start_col = ((scroll_left() / char_px()).floor() as isize).max(0) as usize;
-                                                                let local_x = (p.x + CLICK_COL_BIAS_PX).max(0.0);
-                                                                let clicked_col = view_start_col + (local_x / char_px()).floor() as usize;
-                                                                set_active_tab_editor(tabs2.clone(), act2.clone(), |t| {
-                                                                    let s = &mut t.editor;
-                                                                    if s.lines.is_empty() {
-                                                                        lines_mut(s).push(String::new());
-                                                                    }
-                                                                    let line = line_index.min(s.lines.len() - 1);
-                                                                    let mut col = clicked_col;
-                                                                    let max_col = s.lines[line].len();
-                                                                    if col > max_col { col = max_col; }
-                                                                    s.cursor = Cursor { line, col };
-                                                                });
-                                                            }
-                                                        },
-                                                        for sp in spans {
-                                                            span { style: "color: {sp.color};", "{sp.text}" }
-                                                        }
-                                                    }
-                                                )
-                                            }
-                                        }
-                                        div { style: "height: {bottom_h}px;" }
-                                    )
-                                }
-
-
+                            button {
+                                class: "btn btn-primary",
+                                onclick: move |_| {
+                                    overlay.set(Overlay::None);
+                                    pending_action.set(PendingAction::None);
+                                    status.set("Save cancelled".to_string());
+                                },
+                                "Cancel"
                             }
                         }
                     }
@@ -1594,120 +7118,125 @@ pub fn app() -> Element {
             }
 
             // ===== Confirm modal =====
-            if confirm_open() {
+            if overlay() == Overlay::Confirm {
                 div {
                     class: "modal-backdrop",
-                    onclick: move |_| {
-                        confirm_open.set(false);
-                        pending_action.set(PendingAction::None);
-                    },
+                    onclick: move |_| confirm_cancel(overlay, pending_action),
 
                     div {
                         class: "modal",
                         onclick: move |e| e.stop_propagation(),
 
+                        // Escape cancels, Enter saves, Tab cycles the buttons without leaving the dialog.
+                        // Nothing typed here should ever reach the editor behind it.
+                        onkeydown: move |e| {
+                            match e.data().key() {
+                                Key::Escape => confirm_cancel(overlay, pending_action),
+                                Key::Enter => confirm_save(tabs, active_tab, status, overlay, pending_action),
+                                Key::Tab => {
+                                    let back = e.data().modifiers().shift();
+                                    document::eval(&modal_tab_script(back));
+                                }
+                                // Space still activates the focused button natively.
+                                Key::Character(c) if c == " " => {
+                                    e.stop_propagation();
+                                    return;
+                                }
+                                _ => {}
+                            }
+                            e.prevent_default();
+                            e.stop_propagation();
+                        },
+
                         div { class: "modal-title", "You have unsaved changes." }
                         div {
                             class: "modal-sub",
                             {
                                 let what = match pending_action() {
-                                    PendingAction::CloseTab(_) => "Close the tab?",
-                                    PendingAction::ExitApp => "Exit the app?",
-                                    PendingAction::None => "Continue?",
+                                    PendingAction::CloseTab(_) => "Close the tab?".to_string(),
+                                    PendingAction::CloseTabs(ids) => format!("Close {} unsaved tab(s)?", ids.len()),
+                                    PendingAction::ExitApp => "Exit the app?".to_string(),
+                                    PendingAction::None => "Continue?".to_string(),
                                 };
                                 rsx!("Save before continuing? ({what})")
                             }
                         }
 
+                        // What the tab changed since it was saved.
+                        if confirm_show_changes() {
+                            {
+                                let source = confirm_tab
+                                    .and_then(|i| tabs().get(i).and_then(|t| Some((t.path.clone()?, t.editor.buffer.text()))));
+                                let lines = match source.map(|(path, text)| read_text_file(&path).map(|old| diff::unified(&old, &text, 2))) {
+                                    Some(Ok(lines)) if lines.iter().any(|l| matches!(l, Some(('-' | '+', _)))) => lines,
+                                    Some(Ok(_)) => vec![Some((' ', "No differences from the file on disk".to_string()))],
+                                    Some(Err(err)) => vec![Some((' ', err))],
+                                    None => Vec::new(),
+                                };
+                                let more = lines.len().saturating_sub(CONFIRM_DIFF_MAX_LINES);
+                                rsx!(
+                                    div { class: "modal-diff",
+                                        for line in lines.into_iter().take(CONFIRM_DIFF_MAX_LINES) {
+                                            match line {
+                                                Some((mark, text)) => rsx!(
+                                                    div {
+                                                        class: match mark {
+                                                            '-' => "modal-diff-line removed",
+                                                            '+' => "modal-diff-line added",
+                                                            _ => "modal-diff-line",
+                                                        },
+                                                        "{mark} {text}"
+                                                    }
+                                                ),
+                                                None => rsx!(div { class: "modal-diff-line skipped", "⋯" }),
+                                            }
+                                        }
+                                        if more > 0 {
+                                            div { class: "modal-diff-line skipped", "{more} more lines" }
+                                        }
+                                    }
+                                )
+                            }
+                        }
+
                         div { class: "modal-actions",
+                            if confirm_tab.is_some() {
+                                button {
+                                    class: "btn modal-changes",
+                                    onclick: move |_| confirm_show_changes.toggle(),
+                                    // Enter here toggles the list instead of saving.
+                                    onkeydown: move |e| {
+                                        if e.data().key() == Key::Enter {
+                                            confirm_show_changes.toggle();
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                        }
+                                    },
+                                    if confirm_show_changes() { "Hide Changes" } else { "Show Changes" }
+                                }
+                            }
+
                             // Cancel
                             button {
                                 class: "btn",
-                                onclick: move |_| {
-                                    confirm_open.set(false);
-                                    pending_action.set(PendingAction::None);
-                                },
+                                id: "confirm-cancel",
+                                onclick: move |_| confirm_cancel(overlay, pending_action),
                                 "Cancel"
                             }
 
                             // Discard
                             button {
                                 class: "btn btn-danger",
-                                onclick: move |_| {
-                                    let action = pending_action();
-                                    confirm_open.set(false);
-                                    pending_action.set(PendingAction::None);
-
-                                    match action {
-                                        PendingAction::CloseTab(i) => {
-                                            // discard changes and close
-                                            close_tab_immediately(tabs.clone(), active_tab.clone(), i);
-                                        }
-                                        PendingAction::ExitApp => {
-                                            dioxus_desktop::window().close();
-                                        }
-                                        PendingAction::None => {}
-                                    }
-                                },
+                                id: "confirm-discard",
+                                onclick: move |_| confirm_discard(tabs, active_tab, overlay, pending_action),
                                 "Discard"
                             }
 
                             // Save
                             button {
                                 class: "btn btn-primary",
-                                onclick: move |_| {
-                                    let action = pending_action();
-                                    confirm_open.set(false);
-
-                                    let tabs2 = tabs.clone();
-                                    let act2 = active_tab.clone();
-                                    let status2 = status.clone();
-                                    let mut pending2 = pending_action.clone();
-
-                                    spawn(async move {
-                                        match action.clone() {
-                                            PendingAction::CloseTab(i) => {
-                                                // Save that tab index (not necessarily active)
-                                                // If user cancels save dialog, nothing happens.
-                                                let v = tabs2();
-                                                if i < v.len() {
-                                                    if let Some(p) = v[i].path.clone() {
-                                                        save_tab_to_path(tabs2.clone(), i, status2.clone(), p).await;
-                                                    } else if let Some(handle) = AsyncFileDialog::new().save_file().await {
-                                                        let path = handle.path().to_path_buf();
-                                                        save_tab_to_path(tabs2.clone(), i, status2.clone(), path).await;
-                                                    }
-
-                                                    // If it saved (dirty cleared), close it.
-                                                    let v2 = tabs2();
-                                                    if i < v2.len() && !v2[i].dirty {
-                                                        close_tab_immediately(tabs2.clone(), act2.clone(), i);
-                                                    }
-                                                }
-                                            }
-                                            PendingAction::ExitApp => {
-                                                // Save active tab, then exit if clean
-                                                let idx = act2();
-                                                let v = tabs2();
-                                                if idx < v.len() {
-                                                    if let Some(p) = v[idx].path.clone() {
-                                                        save_tab_to_path(tabs2.clone(), idx, status2.clone(), p).await;
-                                                    } else if let Some(handle) = AsyncFileDialog::new().save_file().await {
-                                                        let path = handle.path().to_path_buf();
-                                                        save_tab_to_path(tabs2.clone(), idx, status2.clone(), path).await;
-                                                    }
-
-                                                    if act2() < tabs2().len() && !tabs2()[act2()].dirty {
-                                                        dioxus_desktop::window().close();
-                                                    }
-                                                }
-                                            }
-                                            PendingAction::None => {}
-                                        }
-
-                                        pending2.set(PendingAction::None);
-                                    });
-                                },
+                                id: "confirm-save",
+                                onclick: move |_| confirm_save(tabs, active_tab, status, overlay, pending_action),
                                 "Save"
                             }
                         }
@@ -1720,135 +7249,162 @@ pub fn app() -> Element {
 
 /* ===== EDITING ===== */
 
-fn lines_mut(s: &mut EditorState) -> &mut Vec<String> {
-    Arc::make_mut(&mut s.lines)
+// Tab right after a snippet trigger: swap the trigger for the snippet, laid out at the line's
+// indentation. False (nothing changed) when the word before the cursor isn't a trigger.
+fn expand_snippet(b: &mut Buffer, language: &str, indent: Indent) -> bool {
+    let Some(word) = b.word_before_cursor() else {
+        return false;
+    };
+    let Some(snippet) = snippets::find(language, &word) else {
+        return false;
+    };
+    let line = &b.lines()[b.cursor().line];
+    let lead = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+    let expansion = snippet.expand(lead, &indent.unit());
+    b.replace_before_cursor(word.len(), &expansion.text, expansion.cursor);
+    true
 }
 
-fn handle_key(s: &mut EditorState, key: Key) -> bool {
+// Keys that edit or move; the buffer does the work. Returns true if the text changed.
+// `pairs` are the tab language's auto-closing characters, `overwrite` is the Insert-key mode
+// and `indent` is what Tab inserts.
+fn handle_key(b: &mut Buffer, key: Key, shift: bool, pairs: &[(char, char)], overwrite: bool, indent: Indent) -> bool {
     match key {
-        Key::ArrowLeft => {
-            move_left(s);
-            false
-        }
-        Key::ArrowRight => {
-            move_right(s);
-            false
-        }
-        Key::ArrowUp => {
-            move_up(s);
-            false
-        }
-        Key::ArrowDown => {
-            move_down(s);
-            false
-        }
+        Key::ArrowLeft => b.move_left(shift),
+        Key::ArrowRight => b.move_right(shift),
+        Key::ArrowUp => b.move_up(shift),
+        Key::ArrowDown => b.move_down(shift),
+        Key::Escape => b.clear_selection(),
         Key::Backspace => {
-            backspace(s);
-            true
+            if !b.delete_pair(pairs) {
+                b.delete_backward();
+            }
+            return true;
         }
         Key::Enter => {
-            newline(s);
-            true
+            b.newline();
+            return true;
         }
+        Key::Tab if shift => return b.outdent(indent.width),
         Key::Tab => {
-            insert_str(s, "    ");
-            true
+            b.tab(&indent.unit(), indent.width);
+            return true;
         }
         Key::Character(c) if c.chars().count() == 1 => {
-            insert_char(s, c.chars().next().unwrap());
-            true
+            if let Some(ch) = c.chars().next() {
+                if overwrite {
+                    b.overtype_char(ch);
+                } else {
+                    b.type_char(ch, pairs);
+                }
+            }
+            return true;
         }
-        _ => false,
+        _ => {}
     }
+    false
 }
 
-fn insert_char(s: &mut EditorState, ch: char) {
-    let Cursor { line, col } = s.cursor;
-    let lines = lines_mut(s);
-    if line >= lines.len() {
-        lines.push(String::new());
-    }
-    lines[line].insert(col, ch);
-    s.cursor.col += ch.len_utf8();
-}
+/* ===== MACROS ===== */
 
-fn insert_str(s: &mut EditorState, t: &str) {
-    for c in t.chars() {
-        insert_char(s, c);
+fn toggle_macro_recording(
+    mut recording: Signal<bool>,
+    mut keys: Signal<Vec<(Key, bool)>>,
+    mut status: Signal<String>,
+) {
+    if recording() {
+        recording.set(false);
+        status.set(format!("Macro recorded ({} keys) - Ctrl+Shift+P to play", keys.peek().len()));
+    } else {
+        keys.write().clear();
+        recording.set(true);
+        status.set("Recording macro... Ctrl+Shift+R to stop".to_string());
     }
 }
 
-fn backspace(s: &mut EditorState) {
-    let Cursor { line, col } = s.cursor;
-    let lines = lines_mut(s);
-
-    if lines.is_empty() {
-        lines.push(String::new());
-        s.cursor = Cursor { line: 0, col: 0 };
-        return;
-    }
-
-    let line = line.min(lines.len().saturating_sub(1));
-
-    if col > 0 {
-        if col <= lines[line].len() {
-            lines[line].remove(col - 1);
-            s.cursor.col = col - 1;
+/// Run a text transform over the active tab's selection (or the word at the cursor).
+fn transform_active(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, f: fn(&str) -> String) {
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view == TabView::Text && t.editor.buffer.transform_selection(f) {
+            t.dirty = true;
         }
-    } else if line > 0 {
-        let tail = lines.remove(line);
-        let prev = line - 1;
-        let len = lines[prev].len();
-        lines[prev].push_str(&tail);
-        s.cursor = Cursor { line: prev, col: len };
-    }
+    });
 }
 
-fn newline(s: &mut EditorState) {
-    let Cursor { line, col } = s.cursor;
-    let lines = lines_mut(s);
-
-    if lines.is_empty() {
-        lines.push(String::new());
-    }
-
-    let line = line.min(lines.len().saturating_sub(1));
-    let safe_col = col.min(lines[line].len());
-    let rest = lines[line].split_off(safe_col);
-    lines.insert(line + 1, rest);
-    s.cursor = Cursor { line: line + 1, col: 0 };
+/// Type `text` at the active tab's cursor, over the selection, like a paste: one undo step.
+fn insert_into_active(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, text: &str) {
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view == TabView::Text {
+            t.editor.buffer.insert(text);
+            t.dirty = true;
+        }
+    });
 }
 
-fn move_left(s: &mut EditorState) {
-    if s.cursor.col > 0 {
-        s.cursor.col -= 1;
-    } else if s.cursor.line > 0 {
-        s.cursor.line -= 1;
-        s.cursor.col = s.lines[s.cursor.line].len();
+/// Insert Date/Time: the local time (UTC where the offset can't be read) in `format`.
+fn insert_date_time(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>, format: &str) {
+    let now = time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    let text = time::format_description::parse(format)
+        .map_err(|e| e.to_string())
+        .and_then(|f| now.format(&f).map_err(|e| e.to_string()));
+    match text {
+        Ok(text) => insert_into_active(tabs, active_tab, &text),
+        Err(err) => status.set(format!("date_format in settings.toml: {err}")),
     }
 }
 
-fn move_right(s: &mut EditorState) {
-    if s.cursor.col < s.lines[s.cursor.line].len() {
-        s.cursor.col += 1;
-    } else if s.cursor.line + 1 < s.lines.len() {
-        s.cursor.line += 1;
-        s.cursor.col = 0;
+/// Insert File Path: the active tab's full path.
+fn insert_file_path(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, mut status: Signal<String>) {
+    match tabs.peek().get(*active_tab.peek()).and_then(|t| t.path.clone()) {
+        Some(path) => insert_into_active(tabs, active_tab, &path.display().to_string()),
+        None => status.set("This tab hasn't been saved to a file yet".to_string()),
     }
 }
 
-fn move_up(s: &mut EditorState) {
-    if s.cursor.line > 0 {
-        s.cursor.line -= 1;
-        s.cursor.col = s.cursor.col.min(s.lines[s.cursor.line].len());
-    }
+/// Run a line transform over the lines the active tab's selection covers (or all of them).
+fn transform_lines_active(tabs: Signal<Vec<Tab>>, active_tab: Signal<usize>, f: impl FnOnce(Vec<String>) -> Vec<String>) {
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view == TabView::Text && t.editor.buffer.transform_lines(f) {
+            t.dirty = true;
+        }
+    });
 }
 
-fn move_down(s: &mut EditorState) {
-    if s.cursor.line + 1 < s.lines.len() {
-        s.cursor.line += 1;
-        s.cursor.col = s.cursor.col.min(s.lines[s.cursor.line].len());
+/// Replay the recorded keys at the cursor as a single undo step.
+fn play_macro(
+    tabs: Signal<Vec<Tab>>,
+    active_tab: Signal<usize>,
+    keys: Signal<Vec<(Key, bool)>>,
+    recording: Signal<bool>,
+    overwrite: bool,
+    mut status: Signal<String>,
+) {
+    if recording() {
+        status.set("Stop recording before playing the macro".to_string());
+        return;
+    }
+    let keys = keys.peek().clone();
+    if keys.is_empty() {
+        status.set("No macro recorded".to_string());
+        return;
     }
+    set_active_tab_editor(tabs, active_tab, |t| {
+        if t.view != TabView::Text {
+            return;
+        }
+        let pairs = crate::syntax::auto_close_pairs(&t.language);
+        let indent = t.indent;
+        let changed = t.editor.buffer.group(|b| {
+            let mut changed = false;
+            for (key, shift) in keys {
+                changed |= handle_key(b, key, shift, &pairs, overwrite, indent);
+            }
+            changed
+        });
+        if changed {
+            t.dirty = true;
+        }
+    });
 }
 
 fn main() {
@@ -1867,5 +7423,187 @@ fn main() {
                 .with_position(LogicalPosition::new(500, 200)),
         );
 
-    LaunchBuilder::desktop().with_cfg(cfg).launch(app);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let startup = cli::parse_args(args.iter().cloned());
+
+    // Single instance: pass the files to a window that's already open and quit.
+    // `--new-window` always starts a fresh one.
+    let incoming = if settings::load().single_instance && !args.iter().any(|a| a == "--new-window") {
+        if instance::forward(&startup) {
+            return;
+        }
+        instance::listen().unwrap_or_default()
+    } else {
+        instance::Incoming::default()
+    };
+
+    LaunchBuilder::desktop()
+        .with_cfg(cfg)
+        .with_context(startup)
+        .with_context(incoming)
+        .launch(app);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn entry(name: &str, is_dir: bool) -> DirEntry {
+        DirEntry {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            is_dir,
+            size: None,
+            modified: None,
+        }
+    }
+
+    fn span(text: &str) -> crate::syntax::HighlightSpan {
+        crate::syntax::HighlightSpan { text: text.to_string(), color: "#fff".to_string() }
+    }
+
+    fn marked(text: &str) -> Vec<(String, Option<char>)> {
+        mark_control_chars(vec![span(text)]).into_iter().map(|(s, real)| (s.text, real)).collect()
+    }
+
+    #[test]
+    fn control_placeholders() {
+        assert_eq!(control_placeholder('\0'), Some('␀'));
+        assert_eq!(control_placeholder('\u{7}'), Some('␇'));
+        assert_eq!(control_placeholder('\u{200B}'), Some('·'));
+        assert_eq!(control_placeholder('\t'), None);
+        assert_eq!(control_placeholder('a'), None);
+        assert_eq!(control_placeholder('é'), None);
+    }
+
+    #[test]
+    fn mark_control_chars_splits_out_each_placeholder() {
+        assert_eq!(marked("\0"), [("␀".to_string(), Some('\0'))]);
+        assert_eq!(marked("\u{200B}"), [("·".to_string(), Some('\u{200B}'))]);
+        assert_eq!(
+            marked("a\0b\u{200B}"),
+            [
+                ("a".to_string(), None),
+                ("␀".to_string(), Some('\0')),
+                ("b".to_string(), None),
+                ("·".to_string(), Some('\u{200B}')),
+            ]
+        );
+        // Ordinary text (tabs included) passes through as the span it was.
+        assert_eq!(marked("let x = 1;\t// ok"), [("let x = 1;\t// ok".to_string(), None)]);
+    }
+
+    // 20 px lines in a 200 px viewport: 10 lines show, plus the 20 rendered beyond them.
+    fn in_view(scroll_top: f64, total: usize) -> (usize, usize, f64, f64) {
+        lines_in_view(scroll_top, 200.0, total, 20.0)
+    }
+
+    #[test]
+    fn visible_range_of_short_documents() {
+        assert_eq!(in_view(0.0, 0), (0, 0, 0.0, 0.0));
+        assert_eq!(in_view(0.0, 1), (0, 1, 0.0, 0.0));
+        // Shorter than the viewport: everything, with no spacers.
+        assert_eq!(in_view(0.0, 5), (0, 5, 0.0, 0.0));
+        // Exactly the lines that fit plus the extra rendered ones.
+        assert_eq!(in_view(0.0, 30), (0, 30, 0.0, 0.0));
+        assert_eq!(in_view(0.0, 31), (0, 30, 0.0, 20.0));
+    }
+
+    #[test]
+    fn visible_range_scrolled() {
+        assert_eq!(in_view(400.0, 100), (20, 50, 400.0, 1000.0));
+        assert_eq!(in_view(-50.0, 100), (0, 30, 0.0, 1400.0));
+    }
+
+    #[test]
+    fn visible_range_scrolled_past_the_end_keeps_the_last_line() {
+        for total in [1, 5, 30, 100] {
+            let (start, end, top_h, bottom_h) = in_view(1_000_000.0, total);
+            assert_eq!(start, total - 1);
+            assert_eq!(end, total);
+            assert!(start <= end);
+            assert_eq!(top_h, (total - 1) as f64 * 20.0);
+            assert_eq!(bottom_h, 0.0);
+        }
+    }
+
+    #[test]
+    fn indent_guides_follow_the_tab_width() {
+        let lines: Vec<String> = ["fn f() {", "\tlet a;", "", "\t\tb();", "    c();"].map(String::from).to_vec();
+        assert_eq!(indent_guide_levels(&lines, 0, 5, 4), [0, 1, 1, 2, 1]);
+        assert_eq!(indent_guide_levels(&lines, 0, 5, 2), [0, 1, 1, 2, 2]);
+        assert_eq!(indent_guide_levels(&lines, 0, 5, 8), [0, 1, 1, 2, 0]);
+    }
+
+    #[test]
+    fn long_line_limit_counts_tabs_at_the_tab_width() {
+        let colored = |tab_width| {
+            mark_long_line(vec![span("\tabcdef")], 6, tab_width).into_iter().map(|s| s.text).collect::<Vec<_>>()
+        };
+        assert_eq!(colored(4), ["\tab", "cdef"]);
+        assert_eq!(colored(2), ["\tabcd", "ef"]);
+        assert_eq!(colored(8), ["\t", "abcdef"]);
+    }
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(natural_cmp("a9b", "a10a"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case_and_padding_until_a_tie() {
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("file007", "file10"), Ordering::Less);
+        // Names that only differ in case or zero padding still get a fixed, antisymmetric order.
+        for (a, b) in [("file01", "file1"), ("Readme", "readme"), ("File1", "file01")] {
+            let ord = natural_cmp(a, b);
+            assert_ne!(ord, Ordering::Equal, "{a} vs {b}");
+            assert_eq!(natural_cmp(b, a), ord.reverse(), "{a} vs {b}");
+        }
+        assert_eq!(natural_cmp("file1", "file1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn sort_dir_entries_puts_folders_first_then_natural_order() {
+        let names = [
+            ("file10.txt", false),
+            ("src", true),
+            ("File2.txt", false),
+            ("file01.txt", false),
+            ("assets10", true),
+            ("assets9", true),
+        ];
+        let want = ["assets9", "assets10", "src", "file01.txt", "File2.txt", "file10.txt"];
+        let mut entries: Vec<DirEntry> = names.iter().map(|&(n, d)| entry(n, d)).collect();
+        sort_dir_entries(&mut entries, SidebarSort::Name);
+        let got: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(got, want);
+
+        // The result doesn't depend on the order the directory listing came back in.
+        entries.reverse();
+        sort_dir_entries(&mut entries, SidebarSort::Name);
+        let got: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn save_falls_back_to_writing_in_place() {
+        let dir = std::env::temp_dir().join(format!("side-save-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        assert!(!write_atomically(&path, b"new").unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+        // Something in the way of the temp file.
+        std::fs::create_dir(dir.join(".a.txt.side-tmp")).unwrap();
+        assert!(write_atomically(&path, b"newer").unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
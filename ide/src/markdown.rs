@@ -0,0 +1,42 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+
+// Render Markdown to HTML for the preview pane.
+// The preview is injected with inner HTML, so anything that could run script is neutered here:
+// raw HTML blocks are shown as text and javascript:/vbscript:/data: links are dropped.
+pub fn render_html(src: &str) -> String {
+    let mut opts = Options::empty();
+    opts.insert(Options::ENABLE_TABLES);
+    opts.insert(Options::ENABLE_STRIKETHROUGH);
+    opts.insert(Options::ENABLE_TASKLISTS);
+
+    let events = Parser::new_ext(src, opts).map(|ev| match ev {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        other => other,
+    });
+
+    let mut out = String::with_capacity(src.len() * 3 / 2);
+    html::push_html(&mut out, events);
+    out
+}
+
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    let lower = url.trim_start().to_ascii_lowercase();
+    let blocked = ["javascript:", "vbscript:", "data:"];
+    if blocked.iter().any(|scheme| lower.starts_with(scheme)) {
+        CowStr::Borrowed("#")
+    } else {
+        url
+    }
+}
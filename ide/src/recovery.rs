@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Crash recovery: unsaved buffers are copied to a recovery folder in the config dir every few
+// seconds, never to the real files. Whatever is still there at the next launch was lost by a
+// crash or a forced quit and gets offered back. Discarding at the prompt empties the folder.
+
+/// One unsaved buffer. `path` is the file it belongs to, None for untitled tabs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+impl Snapshot {
+    pub fn title(&self) -> String {
+        match &self.path {
+            Some(p) => p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            None => "Untitled".to_string(),
+        }
+    }
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    crate::settings::config_dir().map(|d| d.join("recovery"))
+}
+
+/// Replace the stored snapshots with these. An empty list clears the folder.
+pub fn save(snapshots: &[Snapshot]) -> Result<(), String> {
+    let dir = recovery_dir().ok_or("no config directory")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("create {}: {e}", dir.display()))?;
+
+    for (i, snap) in snapshots.iter().enumerate() {
+        let text = toml::to_string(snap).map_err(|e| format!("serialize snapshot: {e}"))?;
+        // Write then rename, so a crash mid-write never leaves a half snapshot behind.
+        let tmp = dir.join(format!("{i}.toml.tmp"));
+        let path = dir.join(format!("{i}.toml"));
+        fs::write(&tmp, text).map_err(|e| format!("write {}: {e}", tmp.display()))?;
+        fs::rename(&tmp, &path).map_err(|e| format!("write {}: {e}", path.display()))?;
+    }
+
+    // Drop snapshots left from when more buffers were unsaved.
+    for path in snapshot_files(&dir) {
+        let stale = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<usize>().ok())
+            .is_none_or(|i| i >= snapshots.len());
+        if stale {
+            let _ = fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+/// Snapshots left by the last run, in tab order. Unreadable ones are skipped.
+pub fn load() -> Vec<Snapshot> {
+    let Some(dir) = recovery_dir() else {
+        return Vec::new();
+    };
+    let mut files = snapshot_files(&dir);
+    files.sort_by_key(|p| p.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<usize>().ok()));
+    files
+        .into_iter()
+        .filter_map(|p| fs::read_to_string(p).ok())
+        .filter_map(|s| toml::from_str(&s).ok())
+        .collect()
+}
+
+pub fn clear() -> Result<(), String> {
+    save(&[])
+}
+
+fn snapshot_files(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| {
+            rd.flatten()
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
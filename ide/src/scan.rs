@@ -0,0 +1,73 @@
+use regex::Regex;
+use std::ops::Range;
+
+// Finding where something matches within a line, shared by the highlighter's rules and the
+// buffer's whole-word search so they agree on char boundaries and empty matches. Ranges are
+// byte offsets into the line, always on char boundaries, in order and never overlapping.
+
+/// Something that can be looked for in a line.
+pub trait Matcher {
+    /// The first match starting at or after byte `from` (a char boundary).
+    fn find_from(&self, line: &str, from: usize) -> Option<Range<usize>>;
+}
+
+impl Matcher for str {
+    fn find_from(&self, line: &str, from: usize) -> Option<Range<usize>> {
+        if self.is_empty() {
+            return None;
+        }
+        line[from..].find(self).map(|i| from + i..from + i + self.len())
+    }
+}
+
+impl Matcher for Regex {
+    // find_at, so anchors and word boundaries still see the text before `from`.
+    fn find_from(&self, line: &str, from: usize) -> Option<Range<usize>> {
+        self.find_at(line, from).map(|m| m.range())
+    }
+}
+
+/// Matches of the inner matcher that aren't part of a longer identifier.
+pub struct WholeWord<'a, M: Matcher + ?Sized>(pub &'a M);
+
+impl<M: Matcher + ?Sized> Matcher for WholeWord<'_, M> {
+    fn find_from(&self, line: &str, mut from: usize) -> Option<Range<usize>> {
+        while let Some(m) = self.0.find_from(line, from) {
+            let before = line[..m.start].chars().next_back();
+            let after = line[m.end..].chars().next();
+            if !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char) {
+                return Some(m);
+            }
+            // A rejected match can still overlap a good one, so look again one char on.
+            from = m.start + line[m.start..].chars().next()?.len_utf8();
+        }
+        None
+    }
+}
+
+/// Every non-empty match in `line`, left to right. After an empty match the scan moves on
+/// by one char, so a pattern like `a*` can't loop.
+pub fn ranges<M: Matcher + ?Sized>(line: &str, matcher: &M) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut from = 0;
+    while from <= line.len() {
+        let Some(m) = matcher.find_from(line, from) else {
+            break;
+        };
+        if m.is_empty() {
+            match line[m.end..].chars().next() {
+                Some(c) => from = m.end + c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+        from = m.end;
+        out.push(m);
+    }
+    out
+}
+
+/// Identifier characters, for whole-word matching and word motions.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+// Workspace state that carries over between launches (unlike settings, this is "where you were",
+// not "how you like it"). Stored as session.toml next to settings.toml.
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Folders shown in the sidebar, in order.
+    pub roots: Vec<PathBuf>,
+    /// Folder the last open/save dialog ended up in; the next one starts there.
+    pub last_dir: Option<PathBuf>,
+    /// Sidebar width in px as last dragged; None for the default.
+    pub sidebar_width: Option<f64>,
+    pub sidebar_collapsed: bool,
+    /// The files each sidebar folder had open, reopened along with the folder.
+    pub open_tabs: Vec<FolderTabs>,
+}
+
+/// The tabs open on files inside one sidebar folder.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderTabs {
+    pub root: PathBuf,
+    /// In tab bar order.
+    pub files: Vec<OpenFile>,
+    /// Index into `files` of the active tab, if it was one of them.
+    pub active: Option<usize>,
+}
+
+/// An open file and its cursor: 0-based line, column in characters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+fn session_path() -> Option<PathBuf> {
+    crate::settings::config_dir().map(|d| d.join("session.toml"))
+}
+
+/// Missing or broken session files just mean an empty workspace.
+pub fn load() -> Session {
+    session_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(session: &Session) -> Result<(), String> {
+    let path = session_path().ok_or("no config directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("create {}: {e}", dir.display()))?;
+    }
+    let text = toml::to_string_pretty(session).map_err(|e| format!("serialize session: {e}"))?;
+    fs::write(&path, text).map_err(|e| format!("write {}: {e}", path.display()))
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+// User preferences, stored as TOML in the platform config dir.
+// Every field has a default, so older or hand-edited files keep loading as new settings appear.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub show_file_details: bool,
+    pub sidebar_sort: SidebarSort,
+    /// Reveal the active tab's file in the sidebar whenever another tab becomes active.
+    pub sync_sidebar: bool,
+    pub caret_blink: bool,
+    /// Clicking past the end of a line leaves the caret there (typing pads the gap with
+    /// spaces) instead of putting it at the line's end.
+    pub virtual_space: bool,
+    pub indent_guides: bool,
+    pub show_rulers: bool,
+    /// Columns that get a vertical ruler line when rulers are shown.
+    pub rulers: Vec<usize>,
+    /// Tint the part of each line past `long_line_limit` columns.
+    pub highlight_long_lines: bool,
+    pub long_line_limit: usize,
+    pub single_instance: bool,
+    /// When the window regains focus, reload tabs whose files changed on disk (asking first
+    /// for tabs with unsaved edits).
+    pub reload_on_focus: bool,
+    pub vim_mode: bool,
+    /// Gutter shows distance from the cursor line instead of line numbers.
+    pub relative_line_numbers: bool,
+    /// Status bar shows word, character and line counts.
+    pub word_count: bool,
+    /// Multiplier on mouse wheel distance.
+    pub scroll_speed: f64,
+    /// Animate wheel, page and go-to-line scrolling instead of jumping.
+    pub smooth_scroll: bool,
+    /// Let the editor scroll until the last line is at the top, instead of stopping with it
+    /// at the bottom.
+    pub scroll_past_end: bool,
+    /// Lines of the previous page still showing after PageUp/PageDown.
+    pub page_overlap: usize,
+    /// Folder of the user's own `.sidel` files and `manifest.toml`, used over the built-in ones.
+    pub syntax_dir: Option<PathBuf>,
+    /// Color numbers, quoted strings and URLs in files whose language has no rules of its own.
+    pub generic_highlighting: bool,
+    /// Installed font for the editor; None uses the bundled JetBrains Mono.
+    pub font_family: Option<String>,
+    /// Editor font size in pixels.
+    pub font_size: f64,
+    /// Indentation for files nothing else decides for: no .editorconfig setting, no indented
+    /// lines to go by, and no default for the language in the manifest.
+    pub indent_with_tabs: bool,
+    pub indent_size: usize,
+    /// Extra alternate-file rules (Alt+O), tried before the built-in ones. Each is a list of
+    /// patterns like `["*.h", "*.c"]`; see alternate.rs.
+    pub alternate_files: Vec<Vec<String>>,
+    /// What Insert Date/Time (F5) types, in the `time` crate's format description syntax:
+    /// `[year]-[month]-[day] [hour]:[minute]`, `[weekday], [day] [month repr:long]`, ...
+    pub date_format: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            show_file_details: false,
+            sidebar_sort: SidebarSort::Name,
+            sync_sidebar: false,
+            caret_blink: true,
+            virtual_space: false,
+            indent_guides: true,
+            show_rulers: false,
+            rulers: vec![80],
+            highlight_long_lines: false,
+            long_line_limit: 100,
+            single_instance: false,
+            reload_on_focus: false,
+            vim_mode: false,
+            relative_line_numbers: false,
+            word_count: false,
+            scroll_speed: 1.0,
+            smooth_scroll: false,
+            scroll_past_end: true,
+            page_overlap: 2,
+            syntax_dir: None,
+            generic_highlighting: true,
+            font_family: None,
+            font_size: 14.0,
+            indent_with_tabs: false,
+            indent_size: 4,
+            alternate_files: Vec::new(),
+            date_format: "[year]-[month]-[day] [hour]:[minute]".to_string(),
+        }
+    }
+}
+
+/// Default indent sizes offered in the View menu.
+pub const INDENT_SIZES: [usize; 3] = [2, 4, 8];
+
+/// Wheel speeds offered in the View menu.
+pub const SCROLL_SPEEDS: [f64; 4] = [0.5, 1.0, 2.0, 3.0];
+
+/// Fonts offered in the View menu besides the bundled one. Any other installed font can be
+/// named in `font_family`.
+pub const FONT_FAMILIES: [&str; 4] = ["Consolas", "Cascadia Mono", "Courier New", "Lucida Console"];
+
+/// Font sizes offered in the View menu.
+pub const FONT_SIZES: [f64; 5] = [12.0, 13.0, 14.0, 16.0, 18.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SidebarSort {
+    Name,
+    Modified,
+    Size,
+}
+
+impl SidebarSort {
+    pub const ALL: [SidebarSort; 3] = [SidebarSort::Name, SidebarSort::Modified, SidebarSort::Size];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SidebarSort::Name => "Name",
+            SidebarSort::Modified => "Modified",
+            SidebarSort::Size => "Size",
+        }
+    }
+}
+
+/// %APPDATA%\SIDE on Windows, $XDG_CONFIG_HOME/side or ~/.config/side elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return std::env::var_os("APPDATA").map(|d| PathBuf::from(d).join("SIDE"));
+    }
+    if let Some(d) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(d).join("side"));
+    }
+    std::env::var_os("HOME").map(|d| PathBuf::from(d).join(".config").join("side"))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("settings.toml"))
+}
+
+/// Missing or broken settings files just mean defaults.
+pub fn load() -> Settings {
+    settings_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(settings: &Settings) -> Result<(), String> {
+    let path = settings_path().ok_or("no config directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("create {}: {e}", dir.display()))?;
+    }
+    let text = toml::to_string_pretty(settings).map_err(|e| format!("serialize settings: {e}"))?;
+    fs::write(&path, text).map_err(|e| format!("write {}: {e}", path.display()))
+}
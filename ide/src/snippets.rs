@@ -0,0 +1,82 @@
+use include_dir::{include_dir, Dir};
+use serde::Deserialize;
+use std::fs;
+
+// Tab-triggered snippets: type a trigger word, press Tab, and the word becomes the snippet's
+// body. Each language has a snippets/<language>.toml of `[[snippet]]` tables, embedded like
+// the templates; a file of the same name in the user syntax folder adds to them, replacing
+// built-ins with the same trigger. In a body, `$0` marks where the cursor ends up and each
+// leading tab of a line stands for one indent level in the file's own indentation.
+static SNIPPET_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/snippets");
+
+const CURSOR_MARKER: &str = "$0";
+
+#[derive(Deserialize, Default)]
+struct SnippetFile {
+    #[serde(default)]
+    snippet: Vec<Snippet>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Snippet {
+    pub trigger: String,
+    pub body: String,
+}
+
+/// A snippet laid out for the line it's expanded on.
+pub struct Expansion {
+    pub text: String,
+    /// Where the marker was, as (line, byte column) within `text`; the end without one.
+    pub cursor: (usize, usize),
+}
+
+fn parse(text: &str) -> Vec<Snippet> {
+    toml::from_str::<SnippetFile>(text).map(|f| f.snippet).unwrap_or_default()
+}
+
+/// This language's snippets, built-in first. A user file that doesn't parse is ignored.
+pub fn load(language: &str) -> Vec<Snippet> {
+    let file = format!("{language}.toml");
+    let mut snippets = SNIPPET_DIR
+        .get_file(&file)
+        .and_then(|f| f.contents_utf8())
+        .map(parse)
+        .unwrap_or_default();
+    if let Some(text) = crate::syntax::user_dir().and_then(|dir| fs::read_to_string(dir.join(&file)).ok()) {
+        let user = parse(&text);
+        snippets.retain(|s| !user.iter().any(|u| u.trigger == s.trigger));
+        snippets.extend(user);
+    }
+    snippets
+}
+
+/// The snippet `trigger` expands to in this language, if any.
+pub fn find(language: &str, trigger: &str) -> Option<Snippet> {
+    load(language).into_iter().find(|s| s.trigger == trigger)
+}
+
+impl Snippet {
+    /// The body with its leading tabs turned into `unit`, and every line after the first
+    /// starting at `indent` (the indentation of the line the trigger was on).
+    pub fn expand(&self, indent: &str, unit: &str) -> Expansion {
+        let body = self.body.strip_suffix('\n').unwrap_or(&self.body);
+        let lines: Vec<String> = body
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let rest = line.trim_start_matches('\t');
+                let levels = unit.repeat(line.len() - rest.len());
+                let lead = if i == 0 || line.is_empty() { "" } else { indent };
+                format!("{lead}{levels}{rest}")
+            })
+            .collect();
+        let raw = lines.join("\n");
+
+        let at = raw.find(CURSOR_MARKER).unwrap_or(raw.len());
+        let before = &raw[..at];
+        let line = before.matches('\n').count();
+        let col = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+        let after = raw.get(at + CURSOR_MARKER.len()..).unwrap_or_default();
+        Expansion { text: format!("{before}{after}"), cursor: (line, col) }
+    }
+}
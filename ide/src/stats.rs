@@ -0,0 +1,20 @@
+// Word, character and line counts for the status bar.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextStats {
+    pub words: usize,
+    pub chars: usize,
+    pub lines: usize,
+}
+
+impl TextStats {
+    /// Words are runs of anything but Unicode whitespace, so no-break and ideographic spaces
+    /// separate words too. Empty text still counts as one line, like the gutter.
+    pub fn of(text: &str) -> Self {
+        Self {
+            words: text.split_whitespace().count(),
+            chars: text.chars().count(),
+            lines: text.split('\n').count(),
+        }
+    }
+}
@@ -1,31 +1,68 @@
+use crate::encoding::FinalNewline;
 use include_dir::{include_dir, Dir};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use serde::Deserialize;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::{Regex, RegexBuilder, RegexSet};
+use serde::{de::DeserializeOwned, Deserialize};
+use side::{buffer::Buffer, scan};
 use std::{
     collections::{HashMap, HashSet},
     fs,
+    ops::Range,
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 // Embed the syntax folder (portable exe).
 static SIDEL_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/syntax");
 
-// Cache compiled syntax rules
-static SYNTAX_CACHE: Lazy<Mutex<HashMap<String, Syntax>>> =
+// Cache compiled syntax rules. One cell per language, so a thread that asks while another is
+// compiling it waits for that instead of compiling it again.
+type SyntaxCell = Arc<OnceCell<CompiledSidel>>;
+static SYNTAX_CACHE: Lazy<Mutex<HashMap<String, SyntaxCell>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
-// Load the manifest once
-static MANIFEST: Lazy<ManifestData> = Lazy::new(|| load_manifest().unwrap_or_else(|_| ManifestData {
-    ext_to_lang: HashMap::new(),
-    languages: HashSet::new(),
-}));
+// The manifest, rebuilt by `reload` so user syntax changes apply without a restart.
+static MANIFEST: Lazy<RwLock<Arc<ManifestData>>> =
+    Lazy::new(|| RwLock::new(Arc::new(load_manifest().0)));
+
+// The .sidel highlighter of each language, kept so each tab's line contexts carry over
+// between renders. Cleared by `reload`, since the contexts refer to the old syntax's regions.
+static SIDEL_HIGHLIGHTERS: Lazy<Mutex<HashMap<String, Arc<SidelHighlighter>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// The user's syntax folder: its .sidel files and manifest entries win over the built-in ones.
+static USER_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+// Languages with no rules of their own (plain text, unknown extensions) get generic.sidel's
+// numbers, strings and URLs while this is on; see set_generic_highlighting.
+static GENERIC_HIGHLIGHTING: AtomicBool = AtomicBool::new(true);
+const GENERIC_SIDEL: &str = "generic";
+
+// Auto-close pairs for languages the manifest doesn't give their own.
+const DEFAULT_PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
 
 #[derive(Debug, Clone)]
 pub struct Syntax {
     pub default_color: String,
     pub rules: Vec<Rule>,
+    // Every rule's pattern in one set (same order as `rules`), to find which rules match a
+    // line at all before running them one by one. None if the set wouldn't compile.
+    pub rule_set: Option<RegexSet>,
+    pub regions: Vec<Region>,
+}
+
+/// A run of lines highlighted as another language, like a fenced code block in Markdown.
+/// Regions are whole lines: the lines matching `start` and `end` keep the outer language
+/// and everything between them uses `language`, which may name `start`'s capture groups
+/// (`$1`, `$lang`) and may be a file extension as well as a language name.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub start: Regex,
+    pub end: Regex,
+    pub language: String,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +72,16 @@ pub struct Rule {
     pub regex: Regex,
     pub color: String,
     pub priority: i32,
+    pub ignore_case: bool,
+    pub group: usize,
+}
+
+// A language's .sidel layers compiled: None when no layer parses. `errors` are the rules,
+// regions and files that were skipped, one line each for the status bar.
+#[derive(Debug, Clone, Default)]
+struct CompiledSidel {
+    syntax: Option<Arc<Syntax>>,
+    errors: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,13 +90,164 @@ pub struct HighlightSpan {
     pub color: String,
 }
 
+/// A way of coloring a document. The .sidel regex rules cover every language; built with the
+/// `tree-sitter` feature, languages with a bundled grammar are parsed instead unless the
+/// manifest says `highlighter = "sidel"`.
+pub trait Highlighter: Send + Sync {
+    /// Spans for each of the buffer's lines in `range`. It takes the whole buffer, since how
+    /// a line reads can depend on everything above it. `doc` (the tab's id) says which
+    /// document it is, so what's remembered about one tab between renders isn't mistaken for
+    /// another's.
+    fn highlight(&self, doc: u64, buffer: &Buffer, range: Range<usize>) -> Vec<Vec<HighlightSpan>>;
+
+    /// Drop whatever is remembered about `doc`, once its tab is closed.
+    fn forget(&self, doc: u64);
+}
+
+/// The .sidel backend: regex rules line by line, switching language inside embedded regions.
+struct SidelHighlighter {
+    language: String,
+    // Each document's line contexts, by tab id.
+    contexts: Mutex<HashMap<u64, ContextCache>>,
+}
+
+impl Highlighter for SidelHighlighter {
+    fn highlight(&self, doc: u64, buffer: &Buffer, range: Range<usize>) -> Vec<Vec<HighlightSpan>> {
+        let syn = load_syntax(&self.language);
+        let lines = buffer.lines();
+        if syn.regions.is_empty() {
+            return lines[range].iter().map(|line| highlight_line(&self.language, line)).collect();
+        }
+        let mut caches = self.contexts.lock().unwrap();
+        let contexts = caches.entry(doc).or_default().update(&syn, buffer, range.end);
+        lines[range.clone()]
+            .iter()
+            .zip(&contexts[range])
+            .map(|(line, context)| highlight_line(&context.language_of(&syn, &self.language, line), line))
+            .collect()
+    }
+
+    fn forget(&self, doc: u64) {
+        self.contexts.lock().unwrap().remove(&doc);
+    }
+}
+
+/// The backend for `language`: Tree-sitter when it's built in, has the grammar and the
+/// manifest doesn't ask for .sidel, else .sidel.
+pub fn highlighter(language: &str) -> Arc<dyn Highlighter> {
+    #[cfg(feature = "tree-sitter")]
+    if !manifest().sidel_only.contains(language)
+        && let Some(h) = crate::treesitter::highlighter(language)
+    {
+        return h;
+    }
+    let mut cache = SIDEL_HIGHLIGHTERS.lock().unwrap();
+    let h = cache.entry(language.to_string()).or_insert_with(|| {
+        Arc::new(SidelHighlighter {
+            language: language.to_string(),
+            contexts: Mutex::new(HashMap::new()),
+        })
+    });
+    h.clone()
+}
+
+/// Tell every highlighter in use that a tab is gone, whatever language it was last shown in.
+pub fn forget_document(doc: u64) {
+    for h in SIDEL_HIGHLIGHTERS.lock().unwrap().values() {
+        h.forget(doc);
+    }
+    #[cfg(feature = "tree-sitter")]
+    crate::treesitter::forget_document(doc);
+}
+
+/// What a line starts inside of, carried down from the lines above it: the embedded region
+/// (by index into `Syntax::regions`) and the language inside it, or None at the outer level.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LineContext {
+    region: Option<(usize, String)>,
+}
+
+impl LineContext {
+    /// The language to highlight `line` with when it starts in this context. The lines that
+    /// open and close a region keep the outer `language`.
+    fn language_of(&self, syn: &Syntax, language: &str, line: &str) -> String {
+        match &self.region {
+            Some((r, inner)) if !syn.regions[*r].end.is_match(line) => inner.clone(),
+            _ => language.to_string(),
+        }
+    }
+
+    /// The context the line after `line` starts in.
+    fn next(&self, syn: &Syntax, line: &str) -> LineContext {
+        let region = match &self.region {
+            Some((r, _)) if syn.regions[*r].end.is_match(line) => None,
+            Some(inside) => Some(inside.clone()),
+            None => syn.regions.iter().enumerate().find_map(|(r, region)| {
+                let caps = region.start.captures(line)?;
+                let mut name = String::new();
+                caps.expand(&region.language, &mut name);
+                // Unknown languages still open the region so its end is found, they just
+                // highlight as plain text.
+                Some((r, resolve_language(&name).unwrap_or_else(|| "plain".to_string())))
+            }),
+        };
+        LineContext { region }
+    }
+}
+
+// One document's line contexts as of the last highlight, so a render only scans the lines
+// below what it already knows, and an edit only drops what follows the first line it changed.
+// `contexts[i]` is the context line i starts in, worked out from the buffer at `revision`.
+#[derive(Debug, Default)]
+struct ContextCache {
+    revision: Option<u64>,
+    contexts: Vec<LineContext>,
+}
+
+impl ContextCache {
+    /// Contexts for at least the first `upto` lines of `buffer`, as it is now.
+    fn update(&mut self, syn: &Syntax, buffer: &Buffer, upto: usize) -> &[LineContext] {
+        // A line's context depends only on the lines above it, so the first changed line keeps
+        // its context and everything below it is worked out again.
+        let keep = match self.revision {
+            Some(revision) => buffer.first_changed_line(revision).map_or(usize::MAX, |line| line + 1),
+            None => 0,
+        };
+        self.contexts.truncate(keep);
+        self.revision = Some(buffer.revision());
+        if self.contexts.is_empty() {
+            self.contexts.push(LineContext::default());
+        }
+        let lines = buffer.lines();
+        self.contexts.truncate(lines.len());
+        while self.contexts.len() < upto.min(lines.len()) {
+            let i = self.contexts.len() - 1;
+            let next = self.contexts[i].next(syn, &lines[i]);
+            self.contexts.push(next);
+        }
+        &self.contexts
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct SidelFile {
-    #[serde(default = "default_color")]
-    default_color: String,
+    #[serde(default)]
+    default_color: Option<String>,
     // IMPORTANT: your .sidel files use [[rule]] (singular)
     #[serde(default)]
     rule: Vec<SidelRule>,
+    #[serde(default)]
+    region: Vec<SidelRegion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SidelRegion {
+    start: String,
+    end: String,
+    language: String,
+    // The file it came from, for errors.
+    #[serde(skip)]
+    file: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -60,6 +258,16 @@ struct SidelRule {
     color: String,
     #[serde(default = "default_priority")]
     priority: i32,
+    /// Match regardless of case, like a `(?i)` at the start of `pattern`.
+    #[serde(default)]
+    ignore_case: bool,
+    /// The capture group to color, for rules that match context they don't color; 0 is the
+    /// whole match.
+    #[serde(default)]
+    group: usize,
+    // The file it came from, for errors.
+    #[serde(skip)]
+    file: String,
 }
 
 fn default_color() -> String {
@@ -81,11 +289,34 @@ struct ManifestLang {
     name: String,
     #[serde(default)]
     extensions: Vec<String>,
+    // Two-character strings like "()"; absent means DEFAULT_PAIRS, empty means none.
+    #[serde(default)]
+    auto_close: Option<Vec<String>>,
+    // "keep", "ensure" or "strip"; see FinalNewline.
+    #[serde(default)]
+    final_newline: Option<String>,
+    // "tab" or "space", and columns per level; defaults for files that don't show their own.
+    #[serde(default)]
+    indent_style: Option<String>,
+    #[serde(default)]
+    indent_size: Option<usize>,
+    // "tree-sitter" (the default, where there's a grammar) or "sidel".
+    #[serde(default)]
+    highlighter: Option<String>,
 }
 
 struct ManifestData {
     ext_to_lang: HashMap<String, String>,
+    // Each language's first extension.
+    lang_to_ext: HashMap<String, String>,
     languages: HashSet<String>,
+    auto_close: HashMap<String, Vec<(char, char)>>,
+    final_newline: HashMap<String, FinalNewline>,
+    // (indent with tabs, columns per level), either of which may be unset.
+    indent: HashMap<String, (Option<bool>, Option<usize>)>,
+    // Languages highlighted with their .sidel rules even when there's a Tree-sitter grammar.
+    #[cfg_attr(not(feature = "tree-sitter"), allow(dead_code))]
+    sidel_only: HashSet<String>,
 }
 
 /// Read embedded file text by name.
@@ -116,6 +347,34 @@ fn disk_candidates(rel_path: &str) -> Vec<PathBuf> {
     v
 }
 
+fn manifest() -> Arc<ManifestData> {
+    MANIFEST.read().unwrap().clone()
+}
+
+/// The user syntax folder, if one is set.
+pub fn user_dir() -> Option<PathBuf> {
+    USER_DIR.lock().unwrap().clone()
+}
+
+/// Use `dir` (None for none) as the user syntax folder and reload.
+pub fn set_user_dir(dir: Option<PathBuf>) -> Result<(), String> {
+    *USER_DIR.lock().unwrap() = dir;
+    reload()
+}
+
+/// Re-read the manifest and drop every compiled syntax, so edited `.sidel` files and manifest
+/// entries take effect. Errs if the user manifest couldn't be read; the built-in languages
+/// still load then.
+pub fn reload() -> Result<(), String> {
+    let (manifest, user_err) = load_manifest();
+    *MANIFEST.write().unwrap() = Arc::new(manifest);
+    SYNTAX_CACHE.lock().unwrap().clear();
+    SIDEL_HIGHLIGHTERS.lock().unwrap().clear();
+    #[cfg(feature = "tree-sitter")]
+    crate::treesitter::clear();
+    user_err.map_or(Ok(()), Err)
+}
+
 fn load_manifest_text() -> Option<String> {
     // Optional override
     if let Ok(dir) = std::env::var("SIDE_SYNTAX_DIR") {
@@ -139,22 +398,156 @@ fn load_manifest_text() -> Option<String> {
     embedded_text("manifest.toml").map(|s| s.to_string())
 }
 
-fn load_manifest() -> Result<ManifestData, String> {
-    let text = load_manifest_text().ok_or("manifest.toml not found")?;
-    let parsed: ManifestFile =
-        toml::from_str(&text).map_err(|e| format!("manifest.toml parse error: {e}"))?;
+// The manifest's languages, leaving out (and listing in `errors`) any entry that's malformed.
+// Errs only when the file isn't TOML at all.
+fn parse_manifest(text: &str, errors: &mut Vec<String>) -> Result<Vec<ManifestLang>, String> {
+    let mut table: toml::Table =
+        toml::from_str(text).map_err(|e| format!("manifest.toml parse error: {}", toml_error(text, &e)))?;
+    let language = lenient_entries(&mut table, "language", "manifest.toml", errors);
+    let parsed: ManifestFile = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| format!("manifest.toml: {}", e.message()))?;
+    Ok(parsed.language.into_iter().chain(language).collect())
+}
+
+// One `.sidel` layer, skipping (and listing in `errors`) each rule or region that's malformed
+// instead of the whole file. None when the file isn't TOML at all. `file` names it in errors.
+fn parse_sidel(text: &str, file: &str, errors: &mut Vec<String>) -> Option<SidelFile> {
+    let mut table: toml::Table = match toml::from_str(text) {
+        Ok(table) => table,
+        Err(e) => {
+            errors.push(format!("{file}: {}", toml_error(text, &e)));
+            return None;
+        }
+    };
+    let mut rule: Vec<SidelRule> = lenient_entries(&mut table, "rule", file, errors);
+    let mut region: Vec<SidelRegion> = lenient_entries(&mut table, "region", file, errors);
+    rule.iter_mut().for_each(|r| r.file = file.to_string());
+    region.iter_mut().for_each(|r| r.file = file.to_string());
+    match toml::Value::Table(table).try_into::<SidelFile>() {
+        Ok(parsed) => Some(SidelFile { rule, region, ..parsed }),
+        Err(e) => {
+            errors.push(format!("{file}: {}", e.message()));
+            None
+        }
+    }
+}
+
+// Take the `[[key]]` array out of `table` and deserialize its entries one by one, so a bad
+// one is reported as `file key N "name": why` and the rest still load.
+fn lenient_entries<T: DeserializeOwned>(
+    table: &mut toml::Table,
+    key: &str,
+    file: &str,
+    errors: &mut Vec<String>,
+) -> Vec<T> {
+    let entries = match table.remove(key) {
+        None => return Vec::new(),
+        Some(toml::Value::Array(entries)) => entries,
+        Some(_) => {
+            errors.push(format!("{file}: `{key}` should be a list of [[{key}]] tables"));
+            return Vec::new();
+        }
+    };
+    let mut out = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        let name = entry.get("name").and_then(|n| n.as_str()).map(|n| format!(" \"{n}\"")).unwrap_or_default();
+        match entry.try_into::<T>() {
+            Ok(v) => out.push(v),
+            Err(e) => errors.push(format!("{file} {key} {}{name}: {}", i + 1, e.message())),
+        }
+    }
+    out
+}
+
+// A TOML syntax error on one line: where it is and what's wrong, without the source excerpt
+// its Display draws.
+fn toml_error(text: &str, e: &toml::de::Error) -> String {
+    match e.span() {
+        Some(span) => format!("line {}: {}", text[..span.start].lines().count().max(1), e.message()),
+        None => e.message().to_string(),
+    }
+}
+
+/// The built-in manifest with the user folder's entries over it: a language the user manifest
+/// lists replaces the built-in entry of that name. Also returns why the user manifest (if
+/// there is one) couldn't be used.
+fn load_manifest() -> (ManifestData, Option<String>) {
+    let mut languages = load_manifest_text()
+        .and_then(|text| parse_manifest(&text, &mut Vec::new()).ok())
+        .unwrap_or_default();
+
+    let mut user_err = None;
+    if let Some(dir) = user_dir() {
+        let path = dir.join("manifest.toml");
+        let mut errors = Vec::new();
+        match fs::read_to_string(&path) {
+            Ok(text) => match parse_manifest(&text, &mut errors) {
+                Ok(user) => {
+                    languages.retain(|l| !user.iter().any(|u| u.name == l.name));
+                    languages.extend(user);
+                    // Entries that didn't parse were left out; say which.
+                    user_err = summarize(&errors).map(|e| format!("{}: {e}", dir.display()));
+                }
+                Err(e) => user_err = Some(format!("{}: {e}", path.display())),
+            },
+            // A folder of .sidel files only overriding built-in languages needs no manifest.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => user_err = Some(format!("{}: {e}", path.display())),
+        }
+    }
+
+    (build_manifest(languages), user_err)
+}
 
+fn build_manifest(parsed: Vec<ManifestLang>) -> ManifestData {
     let mut ext_to_lang = HashMap::new();
+    let mut lang_to_ext = HashMap::new();
     let mut languages = HashSet::new();
+    let mut auto_close = HashMap::new();
+    let mut final_newline = HashMap::new();
+    let mut indent = HashMap::new();
+    let mut sidel_only = HashSet::new();
 
-    for lang in parsed.language {
+    for lang in parsed {
         languages.insert(lang.name.clone());
+        if lang.highlighter.as_deref() == Some("sidel") {
+            sidel_only.insert(lang.name.clone());
+        }
+        let tabs = match lang.indent_style.as_deref() {
+            Some("tab") => Some(true),
+            Some("space") => Some(false),
+            _ => None,
+        };
+        let size = lang.indent_size.filter(|&n| n > 0);
+        if tabs.is_some() || size.is_some() {
+            indent.insert(lang.name.clone(), (tabs, size));
+        }
+        if let Some(policy) = lang.final_newline.as_deref().and_then(FinalNewline::parse) {
+            final_newline.insert(lang.name.clone(), policy);
+        }
+        if let Some(pairs) = lang.auto_close {
+            let pairs = pairs
+                .iter()
+                .filter_map(|p| {
+                    let mut chars = p.chars();
+                    match (chars.next(), chars.next(), chars.next()) {
+                        (Some(open), Some(close), None) => Some((open, close)),
+                        _ => None,
+                    }
+                })
+                .collect();
+            auto_close.insert(lang.name.clone(), pairs);
+        }
+        if let Some(ext) = lang.extensions.first() {
+            lang_to_ext.insert(lang.name.clone(), ext.to_ascii_lowercase());
+        }
         for ext in lang.extensions {
             ext_to_lang.insert(ext.to_ascii_lowercase(), lang.name.clone());
         }
     }
 
-    Ok(ManifestData { ext_to_lang, languages })
+    ManifestData { ext_to_lang, lang_to_ext, languages, auto_close, final_newline, indent, sidel_only }
 }
 
 fn read_embedded_sidel(language: &str) -> Option<&'static str> {
@@ -162,15 +555,7 @@ fn read_embedded_sidel(language: &str) -> Option<&'static str> {
     SIDEL_DIR.get_file(filename)?.contents_utf8()
 }
 
-fn load_sidel_text(language: &str) -> Option<String> {
-    // Optional override
-    if let Ok(dir) = std::env::var("SIDE_SYNTAX_DIR") {
-        let p = PathBuf::from(dir).join(format!("{language}.sidel"));
-        if let Ok(s) = fs::read_to_string(p) {
-            return Some(s);
-        }
-    }
-
+fn builtin_sidel_text(language: &str) -> Option<String> {
     // Debug: prefer disk so edits don't require a rebuild
     #[cfg(debug_assertions)]
     {
@@ -186,6 +571,22 @@ fn load_sidel_text(language: &str) -> Option<String> {
     read_embedded_sidel(language).map(|s| s.to_string())
 }
 
+/// Every .sidel file for this language, built-in first, then SIDE_SYNTAX_DIR's, then the user
+/// syntax folder's, as (name for errors, text). Later ones are layered over earlier ones (see
+/// `merge_sidel`).
+fn load_sidel_texts(language: &str) -> Vec<(String, String)> {
+    let file = format!("{language}.sidel");
+    let overrides = [std::env::var("SIDE_SYNTAX_DIR").ok().map(PathBuf::from), user_dir()];
+    builtin_sidel_text(language)
+        .map(|text| (format!("built-in {file}"), text))
+        .into_iter()
+        .chain(overrides.into_iter().flatten().filter_map(|dir| {
+            let path = dir.join(&file);
+            fs::read_to_string(&path).ok().map(|text| (path.display().to_string(), text))
+        }))
+        .collect()
+}
+
 pub fn detect_language_from_path(path: &Path) -> String {
     let ext = path
         .extension()
@@ -197,98 +598,268 @@ pub fn detect_language_from_path(path: &Path) -> String {
         return "plain".to_string();
     }
 
-    MANIFEST
+    manifest()
         .ext_to_lang
         .get(&ext)
         .cloned()
         .unwrap_or_else(|| "plain".to_string())
 }
 
-pub fn load_syntax(language: &str) -> Syntax {
-    if let Some(hit) = SYNTAX_CACHE.lock().unwrap().get(language).cloned() {
-        return hit;
-    }
+/// The extension new files in this language get, like "rs" for rust.
+pub fn default_extension(language: &str) -> Option<String> {
+    manifest().lang_to_ext.get(language).cloned()
+}
+
+/// Characters that auto-close as they're typed in this language.
+pub fn auto_close_pairs(language: &str) -> Vec<(char, char)> {
+    manifest()
+        .auto_close
+        .get(language)
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_PAIRS.to_vec())
+}
+
+/// The manifest's final-newline policy for this language, if it sets one.
+pub fn final_newline_policy(language: &str) -> Option<FinalNewline> {
+    manifest().final_newline.get(language).copied()
+}
+
+/// The manifest's indentation for this language: (tabs rather than spaces, columns per level).
+/// Either is None when the manifest leaves it out.
+pub fn indent_policy(language: &str) -> (Option<bool>, Option<usize>) {
+    manifest().indent.get(language).copied().unwrap_or((None, None))
+}
 
+pub fn load_syntax(language: &str) -> Arc<Syntax> {
     // If the manifest doesn't know this language, don't even bother trying.
-    if !MANIFEST.languages.contains(language) {
+    if !manifest().languages.contains(language) {
         return fallback_syntax();
     }
+    compiled_sidel(language).syntax.unwrap_or_else(fallback_syntax)
+}
 
-    let syntax = match load_sidel_text(language) {
-        Some(content) => parse_sidel(&content).unwrap_or_else(|_| fallback_syntax()),
-        None => fallback_syntax(),
-    };
+/// What was skipped compiling `language`'s .sidel files (malformed rules and regions, patterns
+/// that aren't valid regexes, files that aren't TOML), one line each.
+pub fn sidel_errors(language: &str) -> Vec<String> {
+    compiled_sidel(language).errors
+}
+
+/// `errors` as one status-bar line: the first, and how many more there are.
+pub fn summarize(errors: &[String]) -> Option<String> {
+    let first = errors.first()?;
+    Some(match errors.len() {
+        1 => first.clone(),
+        n => format!("{first} (and {} more)", n - 1),
+    })
+}
+
+/// Turn the generic highlighting of languages without rules on or off.
+pub fn set_generic_highlighting(on: bool) {
+    GENERIC_HIGHLIGHTING.store(on, Ordering::Relaxed);
+}
 
-    SYNTAX_CACHE
+// The .sidel layers for `language`, compiled once.
+fn compiled_sidel(language: &str) -> CompiledSidel {
+    // Only hold the map's lock to find the cell; compiling happens outside it.
+    let cell = SYNTAX_CACHE
         .lock()
         .unwrap()
-        .insert(language.to_string(), syntax.clone());
+        .entry(language.to_string())
+        .or_default()
+        .clone();
 
-    syntax
+    cell.get_or_init(|| {
+        // A layer that doesn't parse is left out; the others still apply.
+        let mut errors = Vec::new();
+        let layers: Vec<SidelFile> = load_sidel_texts(language)
+            .iter()
+            .filter_map(|(file, text)| parse_sidel(text, file, &mut errors))
+            .collect();
+        let syntax = (!layers.is_empty()).then(|| Arc::new(compile_sidel(merge_sidel(layers), &mut errors)));
+        CompiledSidel { syntax, errors }
+    })
+    .clone()
 }
 
-fn fallback_syntax() -> Syntax {
-    Syntax {
-        default_color: default_color(),
-        rules: vec![],
+/// Compile `language`'s highlighting on a background thread, so it's ready by the time a
+/// tab first draws with it instead of stalling that render.
+pub fn warm(language: &str) {
+    if !manifest().languages.contains(language) {
+        return;
+    }
+    let language = language.to_string();
+    std::thread::spawn(move || {
+        load_syntax(&language);
+        highlighter(&language);
+    });
+}
+
+fn fallback_syntax() -> Arc<Syntax> {
+    let generic = GENERIC_HIGHLIGHTING.load(Ordering::Relaxed).then(|| compiled_sidel(GENERIC_SIDEL).syntax);
+    generic.flatten().unwrap_or_else(|| {
+        Arc::new(Syntax {
+            default_color: default_color(),
+            rules: vec![],
+            rule_set: None,
+            regions: vec![],
+        })
+    })
+}
+
+/// Stack .sidel layers, earliest first. A later layer's `default_color` wins; its rules replace
+/// earlier rules with the same `name` and are added otherwise, and its regions are added. Later
+/// layers' rules and regions go in front, so among equal priorities they're the ones that win.
+fn merge_sidel(layers: Vec<SidelFile>) -> SidelFile {
+    let mut merged = SidelFile {
+        default_color: None,
+        rule: Vec::new(),
+        region: Vec::new(),
+    };
+    for layer in layers {
+        merged
+            .rule
+            .retain(|r| r.name.is_empty() || !layer.rule.iter().any(|l| l.name == r.name));
+        merged.rule.splice(0..0, layer.rule);
+        merged.region.splice(0..0, layer.region);
+        if layer.default_color.is_some() {
+            merged.default_color = layer.default_color;
+        }
     }
+    merged
 }
 
-fn parse_sidel(toml_text: &str) -> Result<Syntax, toml::de::Error> {
-    let parsed: SidelFile = toml::from_str(toml_text)?;
+// Rules and regions whose patterns don't compile are left out and listed in `errors`.
+fn compile_sidel(parsed: SidelFile, errors: &mut Vec<String>) -> Syntax {
     let mut rules = Vec::new();
 
     for r in parsed.rule {
-        if let Ok(re) = Regex::new(&r.pattern) {
-            rules.push(Rule {
+        match RegexBuilder::new(&r.pattern).case_insensitive(r.ignore_case).build() {
+            Ok(re) if r.group >= re.captures_len() => {
+                errors.push(format!("{} rule \"{}\": no capture group {}", r.file, r.name, r.group))
+            }
+            Ok(re) => rules.push(Rule {
                 name: r.name,
                 regex: re,
                 color: r.color,
                 priority: r.priority,
-            });
+                ignore_case: r.ignore_case,
+                group: r.group,
+            }),
+            Err(e) => errors.push(format!("{} rule \"{}\": {}", r.file, r.name, regex_error(&e))),
         }
     }
 
     rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+    // The set only sees pattern text, so ignore_case rules carry it as an inline flag there.
+    let rule_set = RegexSet::new(rules.iter().map(|r| {
+        if r.ignore_case { format!("(?i:{})", r.regex.as_str()) } else { r.regex.as_str().to_string() }
+    }))
+    .ok();
+
+    let regions = parsed
+        .region
+        .into_iter()
+        .filter_map(|r| match (Regex::new(&r.start), Regex::new(&r.end)) {
+            (Ok(start), Ok(end)) => Some(Region {
+                start,
+                end,
+                language: r.language,
+            }),
+            (Err(e), _) | (_, Err(e)) => {
+                errors.push(format!("{} region \"{}\": {}", r.file, r.language, regex_error(&e)));
+                None
+            }
+        })
+        .collect();
 
-    Ok(Syntax {
-        default_color: parsed.default_color,
+    Syntax {
+        default_color: parsed.default_color.unwrap_or_else(default_color),
         rules,
-    })
+        rule_set,
+        regions,
+    }
+}
+
+// The regex crate draws the pattern with a caret under the problem; the last line says what
+// it is.
+fn regex_error(e: &regex::Error) -> String {
+    e.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_string()
+}
+
+/// A language name or file extension (as used on code fences) to a known language.
+fn resolve_language(name: &str) -> Option<String> {
+    let name = name.trim().to_ascii_lowercase();
+    let manifest = manifest();
+    if manifest.languages.contains(&name) {
+        return Some(name);
+    }
+    manifest.ext_to_lang.get(&name).cloned()
 }
 
 pub fn highlight_line(language: &str, line: &str) -> Vec<HighlightSpan> {
-    let syn = load_syntax(language);
+    highlight_with(&load_syntax(language), line)
+}
 
+fn highlight_with(syn: &Syntax, line: &str) -> Vec<HighlightSpan> {
     if syn.rules.is_empty() || line.is_empty() {
         return vec![HighlightSpan {
             text: line.to_string(),
-            color: syn.default_color,
+            color: syn.default_color.clone(),
         }];
     }
 
     let bytes = line.as_bytes();
     let mut color_at: Vec<Option<&str>> = vec![None; bytes.len()];
+    let mut uncolored = bytes.len();
+    // One pass over the line to find the rules worth running.
+    let matching = syn.rule_set.as_ref().map(|set| set.matches(line));
 
-    for rule in &syn.rules {
-        for m in rule.regex.find_iter(line) {
-            let start = m.start();
-            let end = m.end().min(bytes.len());
-            for i in start..end {
-                if color_at[i].is_none() {
-                    color_at[i] = Some(rule.color.as_str());
+    for (r, rule) in syn.rules.iter().enumerate() {
+        // Everything is colored already, and lower-priority rules never overwrite.
+        if uncolored == 0 {
+            break;
+        }
+        if matching.as_ref().is_some_and(|m| !m.matched(r)) {
+            continue;
+        }
+        for m in rule_ranges(rule, line) {
+            for c in &mut color_at[m] {
+                if c.is_none() {
+                    *c = Some(rule.color.as_str());
+                    uncolored -= 1;
                 }
             }
         }
     }
 
+    spans_by_color(line, &color_at, &syn.default_color)
+}
+
+// The byte ranges a rule colors in `line`: each match, or just its `group` when it has one.
+fn rule_ranges(rule: &Rule, line: &str) -> Vec<Range<usize>> {
+    if rule.group == 0 {
+        return scan::ranges(line, &rule.regex);
+    }
+    rule.regex.captures_iter(line).filter_map(|c| c.get(rule.group)).map(|m| m.range()).collect()
+}
+
+/// Group a line's bytes into spans of one color; uncolored bytes get `default`.
+pub fn spans_by_color(line: &str, color_at: &[Option<&str>], default: &str) -> Vec<HighlightSpan> {
+    if line.is_empty() {
+        return vec![HighlightSpan {
+            text: String::new(),
+            color: default.to_string(),
+        }];
+    }
+
+    let bytes = line.as_bytes();
     let mut spans = Vec::new();
     let mut i = 0;
     while i < bytes.len() {
-        let cur_color = color_at[i].unwrap_or(syn.default_color.as_str());
+        let cur_color = color_at[i].unwrap_or(default);
         let mut j = i + 1;
         while j < bytes.len() {
-            let c = color_at[j].unwrap_or(syn.default_color.as_str());
+            let c = color_at[j].unwrap_or(default);
             if c != cur_color {
                 break;
             }
@@ -303,3 +874,205 @@ pub fn highlight_line(language: &str, line: &str) -> Vec<HighlightSpan> {
 
     spans
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(spans: Vec<HighlightSpan>) -> Vec<(String, String)> {
+        spans.into_iter().map(|s| (s.text, s.color)).collect()
+    }
+
+    // Every rule run over the whole line in priority order, with no prefilter and no early exit.
+    fn highlight_all_rules(syn: &Syntax, line: &str) -> Vec<HighlightSpan> {
+        let mut color_at: Vec<Option<&str>> = vec![None; line.len()];
+        for rule in &syn.rules {
+            for m in rule_ranges(rule, line) {
+                for c in &mut color_at[m] {
+                    c.get_or_insert(rule.color.as_str());
+                }
+            }
+        }
+        spans_by_color(line, &color_at, &syn.default_color)
+    }
+
+    fn inline_syntax(text: &str) -> Syntax {
+        let mut errors = Vec::new();
+        let syn = compile_sidel(parse_sidel(text, "test.sidel", &mut errors).unwrap(), &mut errors);
+        assert_eq!(errors, Vec::<String>::new());
+        syn
+    }
+
+    // The color of the first span of `line`, with and without the RegexSet prefilter.
+    fn first_colors(syn: &Syntax, line: &str) -> (String, String) {
+        let unfiltered = Syntax { rule_set: None, ..syn.clone() };
+        (highlight_with(syn, line)[0].color.clone(), highlight_with(&unfiltered, line)[0].color.clone())
+    }
+
+    #[test]
+    fn ignore_case_rules_match_any_case() {
+        let syn = inline_syntax(
+            r##"
+default_color = "#fff"
+
+[[rule]]
+name = "keyword"
+pattern = '\bselect\b'
+color = "#00f"
+ignore_case = true
+
+[[rule]]
+name = "type"
+pattern = '\bint\b'
+color = "#0f0"
+"##,
+        );
+        assert!(syn.rule_set.is_some());
+        let keyword = ("#00f".to_string(), "#00f".to_string());
+        for line in ["SELECT a", "select a", "SeLeCt a"] {
+            assert_eq!(first_colors(&syn, line), keyword, "{line}");
+        }
+        // Without the flag, a rule keeps to its case, in the prefilter as well as on its own.
+        let plain = ("#fff".to_string(), "#fff".to_string());
+        assert_eq!(first_colors(&syn, "int a"), ("#0f0".to_string(), "#0f0".to_string()));
+        assert_eq!(first_colors(&syn, "INT a"), plain);
+        assert_eq!(first_colors(&syn, "Int a"), plain);
+    }
+
+    #[test]
+    fn context_cache_redoes_the_lines_below_an_edit() {
+        let syn = inline_syntax(
+            r#"
+[[region]]
+start = '^```(\w+)'
+end = '^```$'
+language = "$1"
+"#,
+        );
+        let rust = || LineContext { region: Some((0, "rust".to_string())) };
+        let mut b = Buffer::from_text("intro\n```rust\nfn a() {}\n```\n```rust\nfn b() {}\n```\nend");
+        let mut cache = ContextCache::default();
+        let before = cache.update(&syn, &b, 8).to_vec();
+        assert_eq!(before[2], rust());
+        assert_eq!(before[4], LineContext::default());
+        assert_eq!(before[5], rust());
+
+        // Opening a region on line 0 takes in the lines below it, up to the first closing fence.
+        assert_eq!(before[1], LineContext::default());
+        b.set_cursor(side::buffer::Cursor { line: 0, col: 0 });
+        b.insert("```rust ");
+        let after = cache.update(&syn, &b, 8).to_vec();
+        assert_eq!(after[1], rust());
+        assert_eq!(after[2], rust());
+        assert_eq!(after[4], LineContext::default());
+        assert_eq!(after, ContextCache::default().update(&syn, &b, 8));
+
+        // Nothing changed, nothing to redo; an edit below the fences leaves them be.
+        assert_eq!(cache.update(&syn, &b, 8), after);
+        b.set_cursor(side::buffer::Cursor { line: 7, col: 3 });
+        b.insert("!");
+        assert_eq!(cache.update(&syn, &b, 8), ContextCache::default().update(&syn, &b, 8));
+    }
+
+    #[test]
+    fn closed_tabs_are_forgotten() {
+        let h = SidelHighlighter { language: "markdown".to_string(), contexts: Mutex::new(HashMap::new()) };
+        let b = Buffer::from_text("```rust\nfn a() {}\n```");
+        h.highlight(1, &b, 0..3);
+        h.highlight(2, &b, 0..3);
+        h.forget(1);
+        let contexts = h.contexts.lock().unwrap();
+        assert_eq!(contexts.keys().collect::<Vec<_>>(), [&2]);
+    }
+
+    #[test]
+    fn manifest_can_ask_for_sidel_highlighting() {
+        let text = "[[language]]\nname = \"rust\"\nhighlighter = \"sidel\"\n\n\
+                    [[language]]\nname = \"toml\"\nhighlighter = \"tree-sitter\"\n";
+        let manifest = build_manifest(parse_manifest(text, &mut Vec::new()).unwrap());
+        assert!(manifest.sidel_only.contains("rust"));
+        assert!(!manifest.sidel_only.contains("toml"));
+    }
+
+    #[test]
+    fn builtin_sidel_rules_all_compile() {
+        for file in SIDEL_DIR.files().filter(|f| f.path().extension().is_some_and(|e| e == "sidel")) {
+            let name = file.path().display().to_string();
+            let mut errors = Vec::new();
+            if let Some(parsed) = parse_sidel(file.contents_utf8().unwrap_or_default(), &name, &mut errors) {
+                compile_sidel(parsed, &mut errors);
+            }
+            assert_eq!(errors, Vec::<String>::new(), "{name}");
+        }
+    }
+
+    #[test]
+    fn bad_rule_is_reported_and_the_rest_still_load() {
+        let text = r##"
+[[rule]]
+name = "lookahead"
+pattern = '\bfn(?=\s)'
+color = "#f00"
+
+[[rule]]
+name = "keyword"
+pattern = '\bfn\b'
+color = "#00f"
+"##;
+        let mut errors = Vec::new();
+        let syn = compile_sidel(parse_sidel(text, "test.sidel", &mut errors).unwrap(), &mut errors);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("test.sidel rule \"lookahead\":"), "{}", errors[0]);
+        assert_eq!(syn.rules.len(), 1);
+        assert_eq!(first_colors(&syn, "fn main"), ("#00f".to_string(), "#00f".to_string()));
+    }
+
+    #[test]
+    fn group_rules_color_only_their_group() {
+        let syn = inline_syntax(
+            r##"
+default_color = "#fff"
+
+[[rule]]
+name = "call"
+pattern = '\b([a-z]+)\s*\('
+color = "#ff0"
+group = 1
+"##,
+        );
+        let spans = pairs(highlight_with(&syn, "f(g (x))"));
+        let colored: Vec<&str> = spans.iter().filter(|(_, c)| c == "#ff0").map(|(t, _)| t.as_str()).collect();
+        assert_eq!(colored, ["f", "g"]);
+        // Keywords come first in the file, so a call rule no longer needs to exclude them.
+        let c = pairs(highlight_with(&load_syntax("c"), "if (f(x))"));
+        assert_eq!((c[0].0.as_str(), c[2].0.as_str()), ("if", "f"));
+        assert_ne!(c[0].1, c[2].1);
+
+        let mut errors = Vec::new();
+        let text = "[[rule]]\nname = \"call\"\npattern = 'f'\ncolor = \"#ff0\"\ngroup = 1\n";
+        compile_sidel(parse_sidel(text, "test.sidel", &mut errors).unwrap(), &mut errors);
+        assert_eq!(errors, ["test.sidel rule \"call\": no capture group 1"]);
+    }
+
+    #[test]
+    fn prefiltered_spans_match_running_every_rule() {
+        let cpp = "#include <vector>\n\
+                   namespace app { // keep\n\
+                   template <typename T> static const char* name = R\"(raw \"text\")\";\n\
+                   int main(int argc, char** argv) { return 0x1F + 3.5e2 + 'c'; }\n\
+                   /* block */ std::vector<int> v{1, 2}; auto s = \"a\\\"b\";\n\
+                   \n\
+                   }";
+        for (language, text) in [("cpp", cpp), ("rust", include_str!("syntax.rs"))] {
+            let syn = load_syntax(language);
+            assert!(syn.rules.len() > 10 && syn.rule_set.is_some(), "{language}");
+            for line in text.lines() {
+                assert_eq!(
+                    pairs(highlight_with(&syn, line)),
+                    pairs(highlight_all_rules(&syn, line)),
+                    "{language}: {line}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use include_dir::{include_dir, Dir};
+
+// Starter text for new files, embedded like the syntax folder. Each template is named after
+// its language with that language's extension (templates/rust.rs), and `$0` marks where the
+// cursor starts.
+static TEMPLATE_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
+
+const CURSOR_MARKER: &str = "$0";
+
+pub struct Template {
+    pub text: String,
+    /// Where the marker was, as (line, byte column); the start of the text without one.
+    pub cursor: (usize, usize),
+}
+
+/// Languages that have a template, sorted.
+pub fn languages() -> Vec<String> {
+    let mut langs: Vec<String> = TEMPLATE_DIR
+        .files()
+        .filter_map(|f| f.path().file_stem()?.to_str().map(str::to_string))
+        .collect();
+    langs.sort();
+    langs
+}
+
+pub fn load(language: &str) -> Option<Template> {
+    let file = TEMPLATE_DIR
+        .files()
+        .find(|f| f.path().file_stem().and_then(|s| s.to_str()) == Some(language))?;
+    let raw = file.contents_utf8()?;
+
+    let Some(at) = raw.find(CURSOR_MARKER) else {
+        return Some(Template { text: raw.to_string(), cursor: (0, 0) });
+    };
+    let before = &raw[..at];
+    let line = before.matches('\n').count();
+    let col = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+    Some(Template {
+        text: format!("{before}{}", &raw[at + CURSOR_MARKER.len()..]),
+        cursor: (line, col),
+    })
+}
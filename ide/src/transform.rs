@@ -0,0 +1,281 @@
+// Text transforms behind the Edit menu's selection commands. Pure functions of the selected
+// text (or lines); Buffer::transform_selection and transform_lines apply them as one undo step.
+
+pub fn upper(text: &str) -> String {
+    text.to_uppercase()
+}
+
+pub fn lower(text: &str) -> String {
+    text.to_lowercase()
+}
+
+/// Swap the case of every cased character, using full Unicode mappings (so ß becomes SS).
+pub fn toggle_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_uppercase() {
+            out.extend(c.to_lowercase());
+        } else if c.is_lowercase() {
+            out.extend(c.to_uppercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Capitalize the first letter of each word and lowercase the rest. Apostrophes stay
+/// inside words, so "don't" becomes "Don't".
+pub fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_word = false;
+    for c in text.chars() {
+        if c.is_alphanumeric() || (in_word && c == '\'') {
+            if in_word {
+                out.extend(c.to_lowercase());
+            } else {
+                out.extend(c.to_uppercase());
+            }
+            in_word = true;
+        } else {
+            out.push(c);
+            in_word = false;
+        }
+    }
+    out
+}
+
+/// Line sorts for Sort Lines. Stable, so equal lines keep their order.
+pub fn sort_lines(mut lines: Vec<String>) -> Vec<String> {
+    lines.sort();
+    lines
+}
+
+pub fn sort_lines_descending(mut lines: Vec<String>) -> Vec<String> {
+    lines.sort_by(|a, b| b.cmp(a));
+    lines
+}
+
+pub fn sort_lines_case_insensitive(mut lines: Vec<String>) -> Vec<String> {
+    lines.sort_by_cached_key(|l| l.to_lowercase());
+    lines
+}
+
+/// Sort by the number each line starts with (after leading whitespace); lines without one
+/// go last, in lexical order.
+pub fn sort_lines_numeric(mut lines: Vec<String>) -> Vec<String> {
+    let key = |l: &String| {
+        let t = l.trim_start();
+        let end = t
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+            .map_or(t.len(), |(i, _)| i);
+        t[..end].parse::<f64>().ok()
+    };
+    lines.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
+    lines
+}
+
+/// Drop every blank (empty or whitespace-only) line.
+pub fn remove_blank_lines(lines: Vec<String>) -> Vec<String> {
+    lines.into_iter().filter(|l| !l.trim().is_empty()).collect()
+}
+
+/// Shrink each run of blank lines to a single empty line.
+pub fn collapse_blank_lines(lines: Vec<String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    for line in lines {
+        if !line.trim().is_empty() {
+            out.push(line);
+        } else if out.last().is_none_or(|l| !l.is_empty()) {
+            out.push(String::new());
+        }
+    }
+    out
+}
+
+/// Hard-wrap each paragraph (a run of non-blank lines) so no line passes `width` columns,
+/// breaking at spaces. Lines keep the paragraph's first-line indentation; a word too long
+/// to fit gets a line of its own. Blank lines stay as they are.
+pub fn wrap_paragraphs(lines: Vec<String>, width: usize) -> Vec<String> {
+    let mut out = Vec::with_capacity(lines.len());
+    for_each_paragraph(lines, &mut out, |indent, words, out| {
+        let start = indent.chars().count();
+        let mut line = indent.to_string();
+        let mut cols = start;
+        for word in words {
+            let len = word.chars().count();
+            if cols > start && cols + 1 + len > width {
+                out.push(std::mem::replace(&mut line, indent.to_string()));
+                cols = start;
+            }
+            if cols > start {
+                line.push(' ');
+                cols += 1;
+            }
+            line.push_str(word);
+            cols += len;
+        }
+        out.push(line);
+    });
+    out
+}
+
+/// Unwrap each paragraph onto a single line, words separated by one space.
+pub fn join_paragraphs(lines: Vec<String>) -> Vec<String> {
+    let mut out = Vec::with_capacity(lines.len());
+    for_each_paragraph(lines, &mut out, |indent, words, out| {
+        out.push(format!("{indent}{}", words.join(" ")));
+    });
+    out
+}
+
+// Pass blank lines straight to `out` and each paragraph to `f`, as its first line's
+// indentation and all of its words.
+fn for_each_paragraph(lines: Vec<String>, out: &mut Vec<String>, mut f: impl FnMut(&str, &[&str], &mut Vec<String>)) {
+    let mut para: Vec<String> = Vec::new();
+    let mut flush = |para: &mut Vec<String>, out: &mut Vec<String>| {
+        if let Some(first) = para.first() {
+            let indent = &first[..first.len() - first.trim_start().len()];
+            let words: Vec<&str> = para.iter().flat_map(|l| l.split_whitespace()).collect();
+            f(indent, &words, out);
+        }
+        para.clear();
+    };
+    for line in lines {
+        if line.trim().is_empty() {
+            flush(&mut para, out);
+            out.push(line);
+        } else {
+            para.push(line);
+        }
+    }
+    flush(&mut para, out);
+}
+
+/// A line's leading whitespace as spaces only, each tab counting to the next multiple of
+/// `width` columns. For Convert Indentation, applied with Buffer::reindent.
+pub fn indent_to_spaces(indent: &str, width: usize) -> String {
+    " ".repeat(indent_columns(indent, width))
+}
+
+/// A line's leading whitespace as tabs, with spaces only for a remainder short of a tab.
+pub fn indent_to_tabs(indent: &str, width: usize) -> String {
+    let width = width.max(1);
+    let cols = indent_columns(indent, width);
+    format!("{}{}", "\t".repeat(cols / width), " ".repeat(cols % width))
+}
+
+/// A line's leading whitespace one indent stop deeper: a tab with `tabs`, else the spaces to
+/// the next multiple of `width` columns.
+pub fn indent_indent(indent: &str, width: usize, tabs: bool) -> String {
+    if tabs {
+        return format!("{indent}\t");
+    }
+    let width = width.max(1);
+    format!("{indent}{}", " ".repeat(width - indent_columns(indent, width) % width))
+}
+
+/// A line's leading whitespace one indent stop shallower: back to the previous multiple of
+/// `width` columns. Trailing characters go first, so `\t  ` loses the spaces, not the tab.
+pub fn outdent_indent(indent: &str, width: usize) -> String {
+    let width = width.max(1);
+    let cols = indent_columns(indent, width);
+    let target = cols.saturating_sub(1) / width * width;
+    let mut out = indent.to_string();
+    while indent_columns(&out, width) > target {
+        out.pop();
+    }
+    // Dropping a tab can overshoot; spaces make up the difference.
+    let short = target - indent_columns(&out, width);
+    out.push_str(&" ".repeat(short));
+    out
+}
+
+/// How a file's indented lines are indented: with tabs (alignment spaces after them are
+/// fine), with spaces, or mixed up within the line (a space before a tab).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndentMix {
+    pub tabs: usize,
+    pub spaces: usize,
+    pub mixed: usize,
+}
+
+impl IndentMix {
+    pub fn is_mixed(&self) -> bool {
+        (self.tabs > 0 && self.spaces > 0) || self.mixed > 0
+    }
+
+    /// The style most lines already use, for normalizing the rest.
+    pub fn prefers_tabs(&self) -> bool {
+        self.tabs > self.spaces
+    }
+}
+
+/// Count the indentation styles of the first `sample` indented lines. A lone space isn't
+/// counted, so the ` * ` lines of block comments don't pass for space indentation.
+pub fn indent_mix<'a>(lines: impl IntoIterator<Item = &'a String>, sample: usize) -> IndentMix {
+    let mut mix = IndentMix::default();
+    let indents = lines
+        .into_iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start_matches([' ', '\t']).len()])
+        .filter(|indent| !indent.is_empty() && *indent != " ")
+        .take(sample);
+    for indent in indents {
+        if indent.trim_start_matches('\t').contains('\t') {
+            mix.mixed += 1;
+        } else if indent.starts_with('\t') {
+            mix.tabs += 1;
+        } else {
+            mix.spaces += 1;
+        }
+    }
+    mix
+}
+
+/// Columns per indent level in a space-indented file, guessed from the most common step
+/// between the indents of consecutive lines in the first `sample` non-blank ones. None when
+/// nothing is indented with spaces or the steps don't look like an indent level (2 to 8).
+pub fn indent_unit<'a>(lines: impl IntoIterator<Item = &'a String>, sample: usize) -> Option<usize> {
+    let mut steps = [0usize; 9];
+    let mut prev = 0;
+    for line in lines.into_iter().filter(|l| !l.trim().is_empty()).take(sample) {
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        if indent.contains('\t') {
+            continue;
+        }
+        let step = indent.len().abs_diff(prev);
+        if (2..steps.len()).contains(&step) {
+            steps[step] += 1;
+        }
+        prev = indent.len();
+    }
+    // Ties go to the smaller step, so 2/4 nesting reads as 2.
+    let (unit, &count) = steps.iter().enumerate().rev().max_by_key(|&(_, &n)| n)?;
+    (count > 0).then_some(unit)
+}
+
+/// Columns a run of leading whitespace takes, each tab reaching the next multiple of `width`.
+pub fn indent_columns(indent: &str, width: usize) -> usize {
+    display_columns(indent, width)
+}
+
+/// Columns `text` takes drawn from the start of a line: one per character, except that each
+/// tab reaches the next multiple of `width`.
+pub fn display_columns(text: &str, width: usize) -> usize {
+    let width = width.max(1);
+    text.chars()
+        .fold(0, |cols, c| if c == '\t' { cols + width - cols % width } else { cols + 1 })
+}
+
+/// Drop repeated lines anywhere in the range, keeping each line's first occurrence.
+pub fn dedup_lines(lines: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    lines.into_iter().filter(|l| seen.insert(l.clone())).collect()
+}
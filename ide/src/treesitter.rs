@@ -0,0 +1,222 @@
+use crate::syntax::{HighlightSpan, Highlighter};
+use side::buffer::Buffer;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Parser, Point, Query, QueryCursor, Tree};
+
+// Tree-sitter highlighting for the languages with a bundled grammar (built with the
+// `tree-sitter` feature). Each document keeps the last text it parsed and the tree, so after
+// an edit only the changed part of it is parsed again.
+
+// Capture names from the grammars' highlight queries to colors, matching the .sidel themes.
+// A capture without an entry tries its parent (`function.method` -> `function`); captures
+// with neither, like punctuation, keep the color of whatever they're inside.
+const THEME: &[(&str, &str)] = &[
+    ("attribute", "#C586C0"),
+    ("comment", "#6A9955"),
+    ("comment.documentation", "#4FC1FF"),
+    ("constant", "#DCDCAA"),
+    ("constant.builtin", "#B5CEA8"),
+    ("constructor", "#4EC9B0"),
+    ("escape", "#D7BA7D"),
+    ("function", "#DCDCAA"),
+    ("keyword", "#569CD6"),
+    ("label", "#C586C0"),
+    ("property", "#9CDCFE"),
+    ("string", "#CE9178"),
+    ("type", "#4EC9B0"),
+    ("variable.builtin", "#569CD6"),
+    ("variable.parameter", "#9CDCFE"),
+];
+
+// None when the language has no grammar, so it isn't looked up again.
+static HIGHLIGHTERS: Lazy<Mutex<HashMap<String, Option<Arc<TreeSitterHighlighter>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn grammar(language: &str) -> Option<(Language, &'static str)> {
+    match language {
+        "rust" => Some((tree_sitter_rust::LANGUAGE.into(), tree_sitter_rust::HIGHLIGHTS_QUERY)),
+        _ => None,
+    }
+}
+
+/// The Tree-sitter highlighter for `language`, if there's a grammar for it.
+pub fn highlighter(language: &str) -> Option<Arc<dyn Highlighter>> {
+    let mut cache = HIGHLIGHTERS.lock().unwrap();
+    let h = cache
+        .entry(language.to_string())
+        .or_insert_with(|| TreeSitterHighlighter::new(language).map(Arc::new));
+    h.clone().map(|h| h as Arc<dyn Highlighter>)
+}
+
+/// Drop the parse of `doc` from every grammar's highlighter, see syntax::forget_document.
+pub fn forget_document(doc: u64) {
+    for h in HIGHLIGHTERS.lock().unwrap().values().flatten() {
+        h.forget(doc);
+    }
+}
+
+/// Start over after a syntax reload, so the default color follows the user's .sidel files.
+pub fn clear() {
+    HIGHLIGHTERS.lock().unwrap().clear();
+}
+
+fn capture_color(name: &str) -> Option<&'static str> {
+    let mut name = name;
+    loop {
+        if let Some((_, color)) = THEME.iter().find(|(n, _)| *n == name) {
+            return Some(color);
+        }
+        name = &name[..name.rfind('.')?];
+    }
+}
+
+struct TreeSitterHighlighter {
+    grammar: Language,
+    query: Query,
+    // The color of each of the query's captures, by capture index.
+    colors: Vec<Option<&'static str>>,
+    default_color: String,
+    // Each document's parse, by the tab id Highlighter::highlight is given.
+    states: Mutex<HashMap<u64, ParseState>>,
+}
+
+struct ParseState {
+    parser: Parser,
+    text: String,
+    tree: Option<Tree>,
+}
+
+impl TreeSitterHighlighter {
+    fn new(language: &str) -> Option<Self> {
+        let (grammar, source) = grammar(language)?;
+        let query = Query::new(&grammar, source).ok()?;
+        Parser::new().set_language(&grammar).ok()?;
+        let colors = query.capture_names().iter().map(|n| capture_color(n)).collect();
+        Some(Self {
+            grammar,
+            query,
+            colors,
+            // Text outside any capture looks the same as with the .sidel rules.
+            default_color: crate::syntax::load_syntax(language).default_color.clone(),
+            states: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl ParseState {
+    fn new(grammar: &Language) -> Self {
+        let mut parser = Parser::new();
+        // TreeSitterHighlighter::new already checked the grammar loads.
+        let _ = parser.set_language(grammar);
+        Self {
+            parser,
+            text: String::new(),
+            tree: None,
+        }
+    }
+
+    fn update(&mut self, text: String) {
+        if self.tree.is_some() && text == self.text {
+            return;
+        }
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&edit_between(&self.text, &text));
+        }
+        self.tree = self.parser.parse(&text, self.tree.as_ref());
+        self.text = text;
+    }
+}
+
+// The one edit that turns `old` into `new`: whatever lies between their common prefix and
+// common suffix was replaced.
+fn edit_between(old: &str, new: &str) -> InputEdit {
+    let (old_b, new_b) = (old.as_bytes(), new.as_bytes());
+    let prefix = old_b.iter().zip(new_b).take_while(|(a, b)| a == b).count();
+    let suffix = old_b[prefix..]
+        .iter()
+        .rev()
+        .zip(new_b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old_b.len() - suffix, new_b.len() - suffix);
+    InputEdit {
+        start_byte: prefix,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point_at(old_b, prefix),
+        old_end_position: point_at(old_b, old_end),
+        new_end_position: point_at(new_b, new_end),
+    }
+}
+
+fn point_at(text: &[u8], byte: usize) -> Point {
+    let before = &text[..byte];
+    let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    Point {
+        row: before.iter().filter(|&&b| b == b'\n').count(),
+        column: byte - line_start,
+    }
+}
+
+impl Highlighter for TreeSitterHighlighter {
+    fn highlight(&self, doc: u64, buffer: &Buffer, range: Range<usize>) -> Vec<Vec<HighlightSpan>> {
+        let lines = buffer.lines();
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(doc).or_insert_with(|| ParseState::new(&self.grammar));
+        state.update(lines.join("\n"));
+
+        // Where each line of the range starts in the text; only that stretch gets queried.
+        let mut at: usize = lines[..range.start].iter().map(|l| l.len() + 1).sum();
+        let mut starts = Vec::with_capacity(range.len());
+        for line in &lines[range.clone()] {
+            starts.push(at);
+            at += line.len() + 1;
+        }
+        let from = starts.first().copied().unwrap_or(at);
+        let to = at.min(state.text.len()).max(from);
+
+        let mut color_at: Vec<Option<&str>> = vec![None; to - from];
+        if let Some(tree) = &state.tree {
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(from..to);
+            let mut captures = cursor.captures(&self.query, tree.root_node(), state.text.as_bytes());
+            let mut last = None;
+            // Captures come outermost first, so nested nodes paint over what contains them.
+            while let Some((m, i)) = captures.next() {
+                let capture = m.captures[*i];
+                let node = capture.node.byte_range();
+                // Several patterns can capture the same node; the first in the query wins.
+                if last.as_ref() == Some(&node) {
+                    continue;
+                }
+                last = Some(node.clone());
+                let Some(color) = self.colors[capture.index as usize] else {
+                    continue;
+                };
+                let (start, end) = (node.start.max(from) - from, node.end.min(to).max(from) - from);
+                for c in color_at.iter_mut().take(end).skip(start) {
+                    *c = Some(color);
+                }
+            }
+        }
+
+        starts
+            .iter()
+            .zip(&lines[range])
+            .map(|(&start, line)| {
+                let colors = &color_at[start - from..start - from + line.len()];
+                crate::syntax::spans_by_color(line, colors, &self.default_color)
+            })
+            .collect()
+    }
+
+    fn forget(&self, doc: u64) {
+        self.states.lock().unwrap().remove(&doc);
+    }
+}
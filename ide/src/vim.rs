@@ -0,0 +1,425 @@
+use crate::buffer::{Buffer, Cursor};
+
+// Opt-in Vim-style modal editing, layered over Buffer's movement and edit operations.
+// Covers the everyday subset: hjkl/w/b/0/$/gg/G motions with counts, i/a/I/A/o/O to insert,
+// x/dd/dw/D, v for visual selection, u to undo and :w/:q/:wq/:N commands.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+/// Keys as Vim sees them; the UI maps its own key events onto these.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VimKey {
+    Char(char),
+    Escape,
+    Enter,
+    Backspace,
+    Other,
+}
+
+/// What the UI should do after a key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VimAction {
+    /// Key consumed, text unchanged.
+    None,
+    /// Key consumed and the text changed.
+    Edited,
+    /// Not for Vim (Insert mode typing, arrows, ...): handle it as a normal editor key.
+    PassThrough,
+    /// Copy this text (visual `y`).
+    Yank(String),
+    Write,
+    Quit { force: bool },
+    WriteQuit,
+    GotoLine(usize),
+    /// Unknown `:` command, for the status line.
+    Unknown(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vim {
+    mode: Mode,
+    count: usize,
+    // First key of a two-key command ('d', 'g').
+    pending: Option<char>,
+    // Text typed after ':' while the command line is open.
+    command: Option<String>,
+}
+
+impl Vim {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Mode indicator for the status bar, with the command line while one is being typed.
+    pub fn status(&self) -> String {
+        if let Some(cmd) = &self.command {
+            return format!(":{cmd}");
+        }
+        match self.mode {
+            Mode::Normal => "-- NORMAL --".to_string(),
+            Mode::Insert => "-- INSERT --".to_string(),
+            Mode::Visual => "-- VISUAL --".to_string(),
+        }
+    }
+
+    pub fn handle(&mut self, b: &mut Buffer, key: VimKey) -> VimAction {
+        if self.command.is_some() {
+            return self.command_key(b, key);
+        }
+        match self.mode {
+            Mode::Insert => {
+                if key == VimKey::Escape {
+                    self.mode = Mode::Normal;
+                    b.move_left(false);
+                    VimAction::None
+                } else {
+                    VimAction::PassThrough
+                }
+            }
+            Mode::Normal | Mode::Visual => self.normal_key(b, key),
+        }
+    }
+
+    fn normal_key(&mut self, b: &mut Buffer, key: VimKey) -> VimAction {
+        let visual = self.mode == Mode::Visual;
+        let c = match key {
+            VimKey::Char(c) => c,
+            VimKey::Escape => {
+                self.reset();
+                self.mode = Mode::Normal;
+                b.clear_selection();
+                return VimAction::None;
+            }
+            // Arrows and friends still work; Enter/Backspace just move like j/h.
+            VimKey::Enter => 'j',
+            VimKey::Backspace => 'h',
+            VimKey::Other => return VimAction::PassThrough,
+        };
+
+        if c.is_ascii_digit() && (c != '0' || self.count > 0) {
+            self.count = self.count.saturating_mul(10).saturating_add(c as usize - '0' as usize);
+            return VimAction::None;
+        }
+        let n = self.count.max(1);
+        let pending = self.pending.take();
+        self.count = 0;
+
+        match (pending, c) {
+            (Some('d'), 'd') => return delete_lines(b, n),
+            (Some('d'), 'w') => {
+                let start = b.cursor();
+                let mut end = start;
+                for _ in 0..n {
+                    end = word_forward(b, end);
+                }
+                // dw never eats the line break.
+                if end.line != start.line {
+                    end = Cursor { line: start.line, col: b.lines()[start.line].len() };
+                }
+                return edited_if(b, |b| b.delete(start, end));
+            }
+            (Some('g'), 'g') => {
+                let pos = b.pos_at_char(0, 0);
+                move_to(b, pos, visual);
+                return VimAction::None;
+            }
+            (Some(_), _) => return VimAction::None,
+            (None, 'd') if !visual => {
+                self.pending = Some('d');
+                self.count = n;
+                return VimAction::None;
+            }
+            (None, 'g') => {
+                self.pending = Some('g');
+                return VimAction::None;
+            }
+            _ => {}
+        }
+
+        match c {
+            'h' => (0..n).for_each(|_| b.move_left(visual)),
+            'l' => (0..n).for_each(|_| b.move_right(visual)),
+            'k' => (0..n).for_each(|_| b.move_up(visual)),
+            'j' => (0..n).for_each(|_| b.move_down(visual)),
+            'w' => {
+                let mut pos = b.cursor();
+                for _ in 0..n {
+                    pos = word_forward(b, pos);
+                }
+                move_to(b, pos, visual);
+            }
+            'b' => {
+                let mut pos = b.cursor();
+                for _ in 0..n {
+                    pos = word_backward(b, pos);
+                }
+                move_to(b, pos, visual);
+            }
+            '0' => {
+                let pos = Cursor { line: b.cursor().line, col: 0 };
+                move_to(b, pos, visual);
+            }
+            '$' => {
+                let line = b.cursor().line;
+                let pos = Cursor { line, col: b.lines()[line].len() };
+                move_to(b, pos, visual);
+            }
+            'G' => {
+                let last = b.line_count() - 1;
+                let pos = Cursor { line: last, col: 0 };
+                move_to(b, pos, visual);
+            }
+            ':' => {
+                self.command = Some(String::new());
+            }
+            'v' if !visual => {
+                let at = b.cursor();
+                b.select(at, at);
+                self.mode = Mode::Visual;
+            }
+            'v' => {
+                b.clear_selection();
+                self.mode = Mode::Normal;
+            }
+            'd' | 'x' if visual => {
+                self.mode = Mode::Normal;
+                return edited_if(b, |b| {
+                    include_cursor_char(b);
+                    b.delete_backward();
+                });
+            }
+            'y' if visual => {
+                self.mode = Mode::Normal;
+                include_cursor_char(b);
+                let text = b.selected_text().unwrap_or_default();
+                let start = b.selection().map(|(s, _)| s).unwrap_or(b.cursor());
+                b.set_cursor(start);
+                return VimAction::Yank(text);
+            }
+            'x' => {
+                let start = b.cursor();
+                let text = &b.lines()[start.line];
+                let len: usize = text[start.col..].chars().take(n).map(char::len_utf8).sum();
+                let end = Cursor { line: start.line, col: start.col + len };
+                return edited_if(b, |b| b.delete(start, end));
+            }
+            'D' => {
+                let start = b.cursor();
+                let end = Cursor { line: start.line, col: b.lines()[start.line].len() };
+                return edited_if(b, |b| b.delete(start, end));
+            }
+            'u' => {
+                let mut changed = false;
+                for _ in 0..n {
+                    changed |= b.undo();
+                }
+                return if changed { VimAction::Edited } else { VimAction::None };
+            }
+            'i' => self.mode = Mode::Insert,
+            'a' => {
+                let c = b.cursor();
+                if c.col < b.lines()[c.line].len() {
+                    b.move_right(false);
+                }
+                self.mode = Mode::Insert;
+            }
+            'I' => {
+                let line = b.cursor().line;
+                let indent = b.lines()[line].len() - b.lines()[line].trim_start().len();
+                b.set_cursor(Cursor { line, col: indent });
+                self.mode = Mode::Insert;
+            }
+            'A' => {
+                let line = b.cursor().line;
+                b.set_cursor(Cursor { line, col: b.lines()[line].len() });
+                self.mode = Mode::Insert;
+            }
+            'o' => {
+                let line = b.cursor().line;
+                b.set_cursor(Cursor { line, col: b.lines()[line].len() });
+                b.newline();
+                self.mode = Mode::Insert;
+                return VimAction::Edited;
+            }
+            'O' => {
+                let line = b.cursor().line;
+                b.set_cursor(Cursor { line, col: 0 });
+                b.newline();
+                b.move_up(false);
+                self.mode = Mode::Insert;
+                return VimAction::Edited;
+            }
+            _ => {}
+        }
+        VimAction::None
+    }
+
+    fn command_key(&mut self, b: &mut Buffer, key: VimKey) -> VimAction {
+        let Some(cmd) = self.command.as_mut() else {
+            return VimAction::None;
+        };
+        match key {
+            VimKey::Char(c) => cmd.push(c),
+            VimKey::Backspace => {
+                if cmd.pop().is_none() {
+                    self.command = None;
+                }
+            }
+            VimKey::Escape => self.command = None,
+            VimKey::Enter => {
+                let cmd = self.command.take().unwrap_or_default();
+                if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    b.clear_selection();
+                }
+                return run_command(cmd.trim());
+            }
+            VimKey::Other => {}
+        }
+        VimAction::None
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.pending = None;
+        self.command = None;
+    }
+}
+
+fn run_command(cmd: &str) -> VimAction {
+    match cmd {
+        "w" => VimAction::Write,
+        "q" => VimAction::Quit { force: false },
+        "q!" => VimAction::Quit { force: true },
+        "wq" | "x" => VimAction::WriteQuit,
+        _ => match cmd.parse::<usize>() {
+            Ok(line) => VimAction::GotoLine(line),
+            Err(_) => VimAction::Unknown(cmd.to_string()),
+        },
+    }
+}
+
+fn move_to(b: &mut Buffer, pos: Cursor, visual: bool) {
+    if visual {
+        b.extend_selection(pos);
+    } else {
+        b.set_cursor(pos);
+    }
+}
+
+// Run an edit and report whether it changed anything.
+fn edited_if(b: &mut Buffer, f: impl FnOnce(&mut Buffer)) -> VimAction {
+    let before = b.revision();
+    f(b);
+    if b.revision() != before { VimAction::Edited } else { VimAction::None }
+}
+
+// Visual selections include the character under the cursor, unlike the editor's own.
+fn include_cursor_char(b: &mut Buffer) {
+    let Some(anchor) = b.anchor() else { return };
+    let cursor = b.cursor();
+    if cursor >= anchor {
+        let line = &b.lines()[cursor.line];
+        let next = line[cursor.col..].chars().next().map_or(cursor.col, |c| cursor.col + c.len_utf8());
+        b.select(anchor, Cursor { line: cursor.line, col: next });
+    } else {
+        let line = &b.lines()[anchor.line];
+        let next = line[anchor.col..].chars().next().map_or(anchor.col, |c| anchor.col + c.len_utf8());
+        b.select(Cursor { line: anchor.line, col: next }, cursor);
+    }
+}
+
+fn delete_lines(b: &mut Buffer, n: usize) -> VimAction {
+    let first = b.cursor().line;
+    let last = (first + n - 1).min(b.line_count() - 1);
+    let (start, end) = if last + 1 < b.line_count() {
+        (Cursor { line: first, col: 0 }, Cursor { line: last + 1, col: 0 })
+    } else if first > 0 {
+        // Deleting through the end: take the break before instead of after.
+        let prev = first - 1;
+        (Cursor { line: prev, col: b.lines()[prev].len() }, Cursor { line: last, col: b.lines()[last].len() })
+    } else {
+        (Cursor { line: 0, col: 0 }, Cursor { line: last, col: b.lines()[last].len() })
+    };
+    let action = edited_if(b, |b| b.delete(start, end));
+    let line = first.min(b.line_count() - 1);
+    b.set_cursor(Cursor { line, col: 0 });
+    action
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Space,
+    Word,
+    Punct,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punct
+    }
+}
+
+/// Start of the next word (`w`), crossing lines; an empty line counts as a word.
+fn word_forward(b: &Buffer, pos: Cursor) -> Cursor {
+    let lines = b.lines();
+    let pos = b.clamp(pos);
+    let text = &lines[pos.line];
+
+    let mut col = pos.col;
+    if let Some(first) = text[col..].chars().next() {
+        let cls = class(first);
+        col += text[col..].chars().take_while(|&c| class(c) == cls).map(char::len_utf8).sum::<usize>();
+    }
+    col += text[col..].chars().take_while(|c| c.is_whitespace()).map(char::len_utf8).sum::<usize>();
+    if col < text.len() {
+        return Cursor { line: pos.line, col };
+    }
+
+    for (line, text) in lines.iter().enumerate().skip(pos.line + 1) {
+        let indent = text.len() - text.trim_start().len();
+        if text.trim().is_empty() && !text.is_empty() {
+            continue;
+        }
+        return Cursor { line, col: indent };
+    }
+    Cursor { line: pos.line, col: text.len() }
+}
+
+/// Start of the current or previous word (`b`), crossing lines.
+fn word_backward(b: &Buffer, pos: Cursor) -> Cursor {
+    let lines = b.lines();
+    let mut pos = b.clamp(pos);
+    loop {
+        let text = &lines[pos.line];
+        let before: Vec<char> = text[..pos.col].chars().collect();
+        let mut i = before.len();
+        while i > 0 && before[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        if i > 0 {
+            let cls = class(before[i - 1]);
+            while i > 0 && class(before[i - 1]) == cls {
+                i -= 1;
+            }
+            return b.pos_at_char(pos.line, i);
+        }
+        if pos.line == 0 {
+            return Cursor { line: 0, col: 0 };
+        }
+        pos = Cursor { line: pos.line - 1, col: lines[pos.line - 1].len() };
+        if lines[pos.line].is_empty() {
+            return pos;
+        }
+    }
+}
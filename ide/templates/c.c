@@ -0,0 +1,6 @@
+#include <stdio.h>
+
+int main(void) {
+    $0
+    return 0;
+}
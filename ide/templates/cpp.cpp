@@ -0,0 +1,6 @@
+#include <iostream>
+
+int main() {
+    $0
+    return 0;
+}
@@ -0,0 +1,6 @@
+def main():
+    $0
+
+
+if __name__ == "__main__":
+    main()